@@ -0,0 +1,146 @@
+use crate::config::PowerConfig;
+use crate::crab::Crab;
+use crate::map::Map;
+use crate::player::Player;
+use crate::settings::{Targets, WeaponSet};
+use crate::trajectory::{self, Impact};
+use crate::weapon::{Weapon, WeaponType};
+use ggez::nalgebra::{self as na, Point2, Vector2};
+
+/// The shot an AI team takes on its turn.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Plan {
+    pub weapon: WeaponType,
+    pub direction: Vector2<f32>,
+    /// Charged power, as the player would build it up by holding fire.
+    pub power: f32,
+}
+
+const ANGLE_STEPS: usize = 72;
+const POWER_STEPS: usize = 10;
+
+/// Tries every direction and power of the allowed weapons and picks the shot
+/// that lands closest to an enemy crab. Shots that would hit a crab of the
+/// same side are never picked.
+pub fn plan(
+    map: &Map,
+    players: &[Player],
+    targets: &Targets,
+    weapons: WeaponSet,
+    power: &PowerConfig,
+) -> Option<Plan> {
+    let team = targets.team;
+    let crab = players[team].current_crab()?;
+    let enemies: Vec<Point2<f32>> = players
+        .iter()
+        .enumerate()
+        .filter(|&(i, _)| !targets.is_ally(i))
+        .flat_map(|(_, player)| player.crabs.iter().map(|crab| crab.get_center()))
+        .collect();
+    let mut best: Option<(f32, Plan)> = None;
+    for &weapon in [WeaponType::Bazooka, WeaponType::Pistol].iter() {
+        if !weapons.allows(weapon) {
+            continue;
+        }
+        let powers: Vec<f32> = if weapon == WeaponType::Bazooka {
+            (0..=POWER_STEPS)
+                .map(|i| power.min + (power.max - power.min) * i as f32 / POWER_STEPS as f32)
+                .collect()
+        } else {
+            vec![power.min]
+        };
+        for i in 0..ANGLE_STEPS {
+            let angle = std::f32::consts::PI * 2.0 * i as f32 / ANGLE_STEPS as f32;
+            let direction = Vector2::new(angle.cos(), angle.sin());
+            for &power in powers.iter() {
+                let plan = Plan {
+                    weapon,
+                    direction,
+                    power,
+                };
+                let impact = match impact(map, players, targets, crab, plan) {
+                    Some(impact) => impact,
+                    None => continue,
+                };
+                let score = enemies
+                    .iter()
+                    .map(|target| na::distance(target, &impact))
+                    .fold(f32::MAX, f32::min);
+                let better = match best {
+                    None => true,
+                    Some((best, _)) => score < best,
+                };
+                if better {
+                    best = Some((score, plan));
+                }
+            }
+        }
+    }
+    best.map(|(_, plan)| plan)
+}
+
+/// Where the shot of `plan` lands, following the same rules as the game loop.
+fn impact(map: &Map, players: &[Player], targets: &Targets, crab: &Crab, plan: Plan) -> Option<Point2<f32>> {
+    let mut weapon = Weapon::new(plan.weapon);
+    weapon.set_direction(plan.direction);
+    let shot = weapon.fire(crab.get_pos(), plan.power + 1.0).pop()?;
+    match trajectory::predict(map, players, crab, shot, targets).impact? {
+        Impact { team: Some(hit), .. } if targets.is_ally(hit) => None,
+        impact => Some(impact.point),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+    use crate::settings::MatchSettings;
+    use ggez::graphics::Rect;
+
+    fn new_targets(team: usize) -> Targets {
+        MatchSettings::new(&CONFIG).targets(team, ["gosho", "pesho"][team])
+    }
+
+    fn new_map() -> Map {
+        let mut data = vec![];
+        for y in 0..300 {
+            for _ in 0..600 {
+                data.extend(&[0, 0, 0, if y >= 250 { 255 } else { 0 }]);
+            }
+        }
+        Map::new(&data, 600, 300)
+    }
+
+    fn new_players() -> Vec<Player> {
+        vec![
+            Player::new("ivan", vec![Crab::new("gosho", Rect::new(50.0, 218.0, 48.0, 32.0))]),
+            Player::new("petar", vec![Crab::new("pesho", Rect::new(450.0, 218.0, 48.0, 32.0))]),
+        ]
+    }
+
+    #[test]
+    fn plan_aims_at_enemy() {
+        let map = new_map();
+        let players = new_players();
+        let plan = plan(&map, &players, &new_targets(0), WeaponSet::All, &CONFIG.shots.power).unwrap();
+        let impact = impact(&map, &players, &new_targets(0), &players[0].crabs[0], plan).unwrap();
+        assert!(na::distance(&impact, &players[1].crabs[0].get_center()) < 50.0);
+    }
+
+    #[test]
+    fn plan_respects_weapon_set() {
+        let map = new_map();
+        let players = new_players();
+        let plan = plan(&map, &players, &new_targets(1), WeaponSet::PistolsOnly, &CONFIG.shots.power).unwrap();
+        assert_eq!(plan.weapon, WeaponType::Pistol);
+    }
+
+    #[test]
+    fn plan_without_crabs() {
+        let map = new_map();
+        let mut players = new_players();
+        players[0].surrender();
+        assert_eq!(plan(&map, &players, &new_targets(0), WeaponSet::All, &CONFIG.shots.power), None);
+    }
+}
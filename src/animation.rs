@@ -0,0 +1,259 @@
+use crate::config::AnimationConfig;
+use crate::crab::Crab;
+use ggez::graphics::Rect;
+
+/// What a crab is doing, as far as its sprite is concerned.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum CrabState {
+    Idle,
+    Walk,
+    Jump,
+    Fall,
+    Aim,
+    Fire,
+    Hurt,
+    Die,
+}
+
+impl CrabState {
+    pub const ALL: [CrabState; 8] = [
+        CrabState::Idle,
+        CrabState::Walk,
+        CrabState::Jump,
+        CrabState::Fall,
+        CrabState::Aim,
+        CrabState::Fire,
+        CrabState::Hurt,
+        CrabState::Die,
+    ];
+
+    /// The name used in `AnimationConfig::state`.
+    pub fn from_name(name: &str) -> Option<CrabState> {
+        Self::ALL.iter().copied().find(|state| format!("{:?}", state) == name)
+    }
+}
+
+/// The animation of one crab, kept between frames.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CrabAnimation {
+    state: CrabState,
+    /// Seconds since the state started.
+    time: f32,
+    /// Seconds left of a fire or hurt animation.
+    hold: f32,
+    rect: Rect,
+    facing: f32,
+    health: f32,
+}
+
+impl CrabAnimation {
+    pub const FIRE_TIME: f32 = 0.3;
+    pub const HURT_TIME: f32 = 0.5;
+    pub const DIE_TIME: f32 = 1.0;
+
+    pub fn new(crab: &Crab) -> Self {
+        CrabAnimation {
+            state: CrabState::Idle,
+            time: 0.0,
+            hold: 0.0,
+            rect: crab.get_rect(),
+            facing: crab.facing(),
+            health: crab.get_health(),
+        }
+    }
+
+    pub fn state(&self) -> CrabState {
+        self.state
+    }
+
+    pub fn time(&self) -> f32 {
+        self.time
+    }
+
+    /// Where the crab was last seen, for drawing it after it is gone.
+    pub fn rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn facing(&self) -> f32 {
+        self.facing
+    }
+
+    /// Picks the state from the crab's physics and weapon. Being hurt and
+    /// firing last a little while, dying lasts until the animation is dropped.
+    pub fn update(&mut self, crab: &Crab, seconds: f32) {
+        let rect = crab.get_rect();
+        if crab.is_dying() {
+            self.set_state(CrabState::Die);
+        } else if crab.get_health() < self.health {
+            self.set_state(CrabState::Hurt);
+            self.hold = Self::HURT_TIME;
+        }
+        self.hold = (self.hold - seconds).max(0.0);
+        let state = if self.state == CrabState::Die
+            || (self.hold > 0.0 && (self.state == CrabState::Hurt || self.state == CrabState::Fire))
+        {
+            self.state
+        } else {
+            match crab.vertical_speed() {
+                Some(speed) if speed < 0.0 => CrabState::Jump,
+                Some(_) => CrabState::Fall,
+                None if rect.x != self.rect.x => CrabState::Walk,
+                None if crab.has_weapon() => CrabState::Aim,
+                None => CrabState::Idle,
+            }
+        };
+        self.set_state(state);
+        self.time += seconds;
+        self.rect = rect;
+        self.facing = crab.facing();
+        self.health = crab.get_health();
+    }
+
+    pub fn fire(&mut self) {
+        if self.state != CrabState::Die {
+            self.set_state(CrabState::Fire);
+            self.hold = Self::FIRE_TIME;
+        }
+    }
+
+    pub fn die(&mut self) {
+        self.set_state(CrabState::Die);
+    }
+
+    pub fn update_dead(&mut self, seconds: f32) {
+        self.time += seconds;
+    }
+
+    /// Whether the crab is done dying and can be forgotten.
+    pub fn is_over(&self) -> bool {
+        self.state == CrabState::Die && self.time >= Self::DIE_TIME
+    }
+
+    fn set_state(&mut self, state: CrabState) {
+        if self.state != state {
+            self.state = state;
+            self.time = 0.0;
+        }
+    }
+}
+
+/// The frame of `animation` to show `time` seconds after it started.
+pub fn frame(animation: &AnimationConfig, time: f32) -> u16 {
+    if animation.frames == 0 {
+        return 0;
+    }
+    let frame = (time * animation.fps).max(0.0) as u32;
+    if animation.looping {
+        (frame % animation.frames as u32) as u16
+    } else {
+        frame.min(animation.frames as u32 - 1) as u16
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+    use crate::map::Map;
+    use crate::weapon::WeaponType;
+    use ggez::nalgebra::Vector2;
+
+    fn new_map() -> Map {
+        let mut data = vec![];
+        for y in 0..200 {
+            for _ in 0..200 {
+                data.extend(&[0, 0, 0, if y >= 100 { 255 } else { 0 }]);
+            }
+        }
+        Map::new(&data, 200, 200)
+    }
+
+    fn new_crab(map: &Map) -> Crab {
+        let mut crab = Crab::new("gosho", Rect::new(50.0, 68.0, 20.0, 32.0));
+        crab.update(Vector2::new(0.0, 0.0), 0.0, map);
+        crab
+    }
+
+    #[test]
+    fn animation_follows_physics() {
+        let map = new_map();
+        let mut crab = new_crab(&map);
+        let mut animation = CrabAnimation::new(&crab);
+        animation.update(&crab, 0.1);
+        assert_eq!(animation.state(), CrabState::Idle);
+
+        crab.update(Vector2::new(1.0, 0.0), 0.1, &map);
+        animation.update(&crab, 0.1);
+        assert_eq!(animation.state(), CrabState::Walk);
+
+        crab.jump(&map);
+        crab.update(Vector2::new(0.0, 0.0), 0.1, &map);
+        animation.update(&crab, 0.1);
+        assert_eq!(animation.state(), CrabState::Jump);
+        for _ in 0..4 {
+            crab.update(Vector2::new(0.0, 0.0), 0.1, &map);
+        }
+        animation.update(&crab, 0.1);
+        assert_eq!(animation.state(), CrabState::Fall);
+
+        while crab.is_airborne() {
+            crab.update(Vector2::new(0.0, 0.0), 0.1, &map);
+        }
+        crab.set_weapon(WeaponType::Bazooka);
+        animation.update(&crab, 0.1);
+        assert_eq!(animation.state(), CrabState::Aim);
+    }
+
+    #[test]
+    fn animation_holds_hurt_and_fire() {
+        let map = new_map();
+        let mut crab = new_crab(&map);
+        let mut animation = CrabAnimation::new(&crab);
+        animation.fire();
+        animation.update(&crab, 0.1);
+        assert_eq!(animation.state(), CrabState::Fire);
+        crab.reduce_health(10.0);
+        animation.update(&crab, 0.1);
+        assert_eq!(animation.state(), CrabState::Hurt);
+        animation.update(&crab, CrabAnimation::HURT_TIME);
+        assert_eq!(animation.state(), CrabState::Idle);
+
+        animation.die();
+        assert_eq!(animation.state(), CrabState::Die);
+        animation.update_dead(0.5);
+        assert!(!animation.is_over());
+        animation.update_dead(CrabAnimation::DIE_TIME);
+        assert!(animation.is_over());
+    }
+
+    #[test]
+    fn animation_frames() {
+        let walk = AnimationConfig {
+            state: "Walk",
+            row: 1,
+            frames: 4,
+            fps: 10.0,
+            looping: true,
+        };
+        assert_eq!(frame(&walk, 0.0), 0);
+        assert_eq!(frame(&walk, 0.25), 2);
+        assert_eq!(frame(&walk, 0.45), 0);
+        let die = AnimationConfig { looping: false, ..walk };
+        assert_eq!(frame(&die, 10.0), 3);
+        assert_eq!(CrabState::from_name("Walk"), Some(CrabState::Walk));
+        assert_eq!(CrabState::from_name("Swim"), None);
+    }
+
+    #[test]
+    fn animation_config_covers_every_state() {
+        let sheet = CONFIG.players[0].crab.sheet.as_ref().unwrap();
+        for state in CrabState::ALL.iter() {
+            assert!(sheet
+                .animations
+                .iter()
+                .any(|animation| CrabState::from_name(animation.state) == Some(*state)));
+        }
+    }
+}
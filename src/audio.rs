@@ -0,0 +1,225 @@
+use crate::config::AudioConfig;
+use crate::settings::Options;
+use crate::weapon::WeaponType;
+use ggez::audio::{SoundSource, Source};
+use ggez::{filesystem, Context};
+use std::collections::HashMap;
+
+/// Sound effects the match asks for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Sound {
+    Pistol,
+    Bazooka,
+    Girder,
+    Blowtorch,
+    Drill,
+    PoisonDart,
+    FreezeRay,
+    Explosion,
+    Footstep,
+    Jump,
+    Splash,
+    Hurt,
+    Death,
+    TurnStart,
+}
+
+/// The background track of a scene.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Music {
+    Menu,
+    Match,
+}
+
+impl Sound {
+    pub const ALL: [Sound; 14] = [
+        Sound::Pistol,
+        Sound::Bazooka,
+        Sound::Girder,
+        Sound::Blowtorch,
+        Sound::Drill,
+        Sound::PoisonDart,
+        Sound::FreezeRay,
+        Sound::Explosion,
+        Sound::Footstep,
+        Sound::Jump,
+        Sound::Splash,
+        Sound::Hurt,
+        Sound::Death,
+        Sound::TurnStart,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Sound> {
+        Self::ALL.iter().copied().find(|sound| format!("{:?}", sound) == name)
+    }
+
+    /// The sound of using `weapon`, none for skipping the turn.
+    pub fn fired_by(weapon: WeaponType) -> Option<Sound> {
+        match weapon {
+            WeaponType::Bazooka => Some(Sound::Bazooka),
+            WeaponType::Pistol => Some(Sound::Pistol),
+            WeaponType::Skip => None,
+            WeaponType::Girder => Some(Sound::Girder),
+            WeaponType::Blowtorch => Some(Sound::Blowtorch),
+            WeaponType::Drill => Some(Sound::Drill),
+            WeaponType::PoisonDart => Some(Sound::PoisonDart),
+            WeaponType::FreezeRay => Some(Sound::FreezeRay),
+        }
+    }
+}
+
+impl Music {
+    pub const ALL: [Music; 2] = [Music::Menu, Music::Match];
+
+    pub fn from_name(name: &str) -> Option<Music> {
+        Self::ALL.iter().copied().find(|music| format!("{:?}", music) == name)
+    }
+}
+
+/// Plays the sounds and the music of the config at the volumes of the options.
+pub struct Audio {
+    sounds: HashMap<Sound, Source>,
+    music: HashMap<Music, Source>,
+    playing: Option<Music>,
+    options: Options,
+}
+
+impl Audio {
+    /// Loads every sound and track of `cfg`. The ones that are missing or
+    /// can't be loaded stay silent.
+    pub fn new(ctx: &mut Context, cfg: &AudioConfig, options: Options) -> Self {
+        let mut missing = vec![];
+        let mut load = |ctx: &mut Context, path: &str| {
+            if !filesystem::is_file(ctx, path) {
+                missing.push(path.to_owned());
+                return None;
+            }
+            match Source::new(ctx, path) {
+                Ok(source) => Some(source),
+                Err(e) => {
+                    eprintln!("Failed to load {}: {}", path, e);
+                    None
+                }
+            }
+        };
+        let mut sounds = HashMap::new();
+        for (name, path) in cfg.sounds {
+            if let Some(sound) = Sound::from_name(name) {
+                if let Some(source) = load(ctx, path) {
+                    sounds.insert(sound, source);
+                }
+            }
+        }
+        let mut music = HashMap::new();
+        for (name, path) in cfg.music {
+            if let Some(track) = Music::from_name(name) {
+                if let Some(mut source) = load(ctx, path) {
+                    source.set_repeat(true);
+                    music.insert(track, source);
+                }
+            }
+        }
+        if !missing.is_empty() {
+            eprintln!("Playing without {}", missing.join(", "));
+        }
+        Audio {
+            sounds,
+            music,
+            playing: None,
+            options,
+        }
+    }
+
+    pub fn set_options(&mut self, options: Options) {
+        if self.options == options {
+            return;
+        }
+        self.options = options;
+        let volume = volume(&options, options.music_volume);
+        if let Some(source) = self.playing.and_then(|music| self.music.get_mut(&music)) {
+            source.set_volume(volume);
+        }
+    }
+
+    pub fn play(&mut self, sound: Sound) {
+        let volume = volume(&self.options, self.options.effects_volume);
+        if volume <= 0.0 {
+            return;
+        }
+        if let Some(source) = self.sounds.get_mut(&sound) {
+            source.set_volume(volume);
+            if let Err(e) = source.play_detached() {
+                eprintln!("Failed to play {:?}: {}", sound, e);
+            }
+        }
+    }
+
+    /// Switches to the `music` track unless it is already playing.
+    pub fn play_music(&mut self, music: Music) {
+        if self.playing == Some(music) {
+            return;
+        }
+        if let Some(source) = self.playing.and_then(|playing| self.music.get_mut(&playing)) {
+            source.stop();
+        }
+        self.playing = Some(music);
+        let volume = volume(&self.options, self.options.music_volume);
+        if let Some(source) = self.music.get_mut(&music) {
+            source.set_volume(volume);
+            if let Err(e) = source.play() {
+                eprintln!("Failed to play {:?} music: {}", music, e);
+            }
+        }
+    }
+}
+
+/// The volume to play at, from a percentage of the options.
+fn volume(options: &Options, percent: u8) -> f32 {
+    if options.muted {
+        0.0
+    } else {
+        percent as f32 / 100.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+    use std::path::Path;
+
+    #[test]
+    fn audio_volume() {
+        let mut options = Options::new(&CONFIG);
+        assert_eq!(volume(&options, 50), 0.5);
+        options.muted = true;
+        assert_eq!(volume(&options, 50), 0.0);
+    }
+
+    #[test]
+    fn audio_config_names() {
+        let shipped = |path: &str| Path::new("resources").join(path.trim_start_matches('/')).is_file();
+        for (name, path) in CONFIG.audio.sounds {
+            assert!(Sound::from_name(name).is_some(), "unknown sound {}", name);
+            assert!(shipped(path), "missing {}", path);
+        }
+        for (name, path) in CONFIG.audio.music {
+            assert!(Music::from_name(name).is_some(), "unknown music {}", name);
+            assert!(shipped(path), "missing {}", path);
+        }
+        assert_eq!(Sound::from_name("Boom"), None);
+    }
+
+    #[test]
+    fn audio_weapon_sounds() {
+        let mut sounds = vec![];
+        for &weapon in WeaponType::ALL.iter().filter(|&&weapon| weapon != WeaponType::Skip) {
+            let sound = Sound::fired_by(weapon).unwrap();
+            assert!(!sounds.contains(&sound));
+            assert!(CONFIG.audio.sounds.iter().any(|(name, _)| Sound::from_name(name) == Some(sound)));
+            sounds.push(sound);
+        }
+        assert_eq!(Sound::fired_by(WeaponType::Skip), None);
+    }
+}
@@ -0,0 +1,156 @@
+use std::env;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process;
+use std::str::FromStr;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crabs::config::CONFIG;
+use crabs::net::{self, Host};
+use crabs::server::{self, Server};
+use crabs::settings::{Control, MapSource, MatchSettings};
+use crabs::simulation::Simulation;
+
+const USAGE: &str = "Usage: crabs-server [options]
+
+  --port PORT        port to listen on (default 7878)
+  --players N        human players to wait for (default 2)
+  --ai N             AI teams added to the match (default 0)
+  --map MAP          map manifest, e.g. /maps/island/map.toml, or \"generated\"
+  --resources DIR    directory of the game resources (default resources)
+  --turn-time SECS   seconds per turn (default unlimited)
+  --seed SEED        seed of the match (default random)
+  --log FILE         file the match events and outcome are appended to";
+
+struct Args {
+    port: u16,
+    settings: MatchSettings,
+    resources: PathBuf,
+    log: Option<PathBuf>,
+}
+
+fn parse<T: FromStr>(option: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", option, value))
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut args = Args {
+        port: net::DEFAULT_PORT,
+        settings: MatchSettings::new(&CONFIG),
+        resources: PathBuf::from("resources"),
+        log: None,
+    };
+    let (mut players, mut ai) = (2, 0);
+    let mut generated = false;
+    let mut options = env::args().skip(1);
+    while let Some(option) = options.next() {
+        if option == "--help" {
+            return Err(String::from(USAGE));
+        }
+        let value = options
+            .next()
+            .ok_or_else(|| format!("{} needs a value\n\n{}", option, USAGE))?;
+        match option.as_str() {
+            "--port" => args.port = parse(&option, &value)?,
+            "--players" => players = parse(&option, &value)?,
+            "--ai" => ai = parse(&option, &value)?,
+            "--map" if value == "generated" => generated = true,
+            "--map" => args.settings.map = MapSource::Package(value),
+            "--resources" => args.resources = PathBuf::from(value),
+            "--turn-time" => args.settings.rules.turn_time = Some(parse(&option, &value)?),
+            "--seed" => args.settings.seed = parse(&option, &value)?,
+            "--log" => args.log = Some(PathBuf::from(value)),
+            _ => return Err(format!("unknown option {}\n\n{}", option, USAGE)),
+        }
+    }
+    let teams = players + ai;
+    if players == 0 || !(MatchSettings::MIN_TEAMS..=MatchSettings::MAX_TEAMS).contains(&teams) {
+        return Err(format!(
+            "a match has {} to {} teams and at least one player",
+            MatchSettings::MIN_TEAMS,
+            MatchSettings::MAX_TEAMS
+        ));
+    }
+    let settings = &mut args.settings;
+    while settings.teams.len() < teams {
+        settings.add_team(&CONFIG);
+    }
+    settings.teams.truncate(teams);
+    for (i, team) in settings.teams.iter_mut().enumerate() {
+        team.control = if i < players { Control::Human } else { Control::Ai };
+    }
+    if generated {
+        settings.map = MapSource::Generated { seed: settings.seed };
+    }
+    Ok(args)
+}
+
+/// Prints what happened and appends it to the log file.
+fn log(lines: Vec<String>, file: &Option<PathBuf>) {
+    for line in lines.iter() {
+        println!("{}", line);
+    }
+    if let (Some(path), false) = (file, lines.is_empty()) {
+        let written = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| lines.iter().try_for_each(|line| writeln!(file, "{}", line)));
+        if let Err(e) = written {
+            eprintln!("Could not write to {}: {}", path.display(), e);
+        }
+    }
+}
+
+fn run(args: Args) -> Result<(), String> {
+    let players = args
+        .settings
+        .teams
+        .iter()
+        .filter(|team| team.control == Control::Human)
+        .count();
+    let (map, package) = server::load_map(&CONFIG, &args.settings, &args.resources)
+        .map_err(|e| format!("could not load the map: {}", e))?;
+    let mut host = Host::bind(args.port).map_err(|e| format!("could not listen: {}", e))?;
+    println!("Waiting for {} players on port {}", players, host.port());
+    let mut joined = 0;
+    while host.clients().len() < players {
+        host.accept().map_err(|e| format!("could not take in players: {}", e))?;
+        if host.clients().len() != joined {
+            joined = host.clients().len();
+            println!("Joined: {}", host.clients().join(", "));
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+
+    let sim = Simulation::new(&CONFIG, args.settings, map, package.as_ref())
+        .map_err(|e| format!("could not start the match: {}", e))?;
+    let mut server = Server::start(sim, host.into_clients());
+    let tick = Duration::from_secs_f32(Simulation::TICK_SECONDS);
+    let mut next = Instant::now();
+    while server.result().is_none() && server.connected() > 0 {
+        server.update();
+        log(server.take_log(), &args.log);
+        next += tick;
+        if let Some(wait) = next.checked_duration_since(Instant::now()) {
+            thread::sleep(wait);
+        }
+    }
+    if server.result().is_none() {
+        log(vec![String::from("Everyone left, match abandoned")], &args.log);
+    }
+    server.flush();
+    Ok(())
+}
+
+fn main() {
+    let result = parse_args().and_then(run);
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
@@ -0,0 +1,157 @@
+use crate::map::Map;
+use ggez::graphics::Rect;
+use ggez::nalgebra::{Point2, Vector2};
+use serde::{Deserialize, Serialize};
+
+/// A thing lying around the map, such as a gravestone or a supply crate. It
+/// falls onto the ground and explosions knock it around, but it doesn't block
+/// shots or hurt anyone.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Body {
+    #[serde(with = "crate::state::rect")]
+    rect: Rect,
+    #[serde(with = "crate::state::vector")]
+    velocity: Vector2<f32>,
+}
+
+impl Body {
+    pub const GRAVITY: f32 = 600.0;
+    /// Speed of a body right at the center of an explosion.
+    pub const KNOCK_SPEED: f32 = 300.0;
+
+    /// A body at rest at `rect`, moved sideways into `map` when it sticks out.
+    pub fn new(rect: Rect, map: &Map) -> Self {
+        let max_x = (map.get_width() as f32 - 1.0 - rect.w).max(0.0);
+        Body {
+            rect: Rect::new(rect.x.clamp(0.0, max_x), rect.y, rect.w, rect.h),
+            velocity: Vector2::new(0.0, 0.0),
+        }
+    }
+
+    pub fn get_rect(&self) -> Rect {
+        self.rect
+    }
+
+    pub fn get_center(&self) -> Point2<f32> {
+        Point2::new(self.rect.x + self.rect.w / 2.0, self.rect.y + self.rect.h / 2.0)
+    }
+
+    fn foot(&self) -> Point2<f32> {
+        Point2::new(self.rect.x + self.rect.w / 2.0, self.rect.bottom())
+    }
+
+    /// Whether the body stands still on the ground.
+    pub fn is_resting(&self, map: &Map) -> bool {
+        self.velocity == Vector2::new(0.0, 0.0) && map.on_ground(self.foot())
+    }
+
+    /// Whether the body fell out of the map or into the water.
+    pub fn is_gone(&self, map: &Map) -> bool {
+        self.rect.top() > map.get_height() as f32 || map.under_water(self.get_center())
+    }
+
+    /// Falls and flies for `seconds`. Walls stop it sideways, the ground stops
+    /// it altogether.
+    pub fn update(&mut self, seconds: f32, map: &Map) {
+        if self.is_resting(map) {
+            return;
+        }
+        self.velocity.y += Self::GRAVITY * seconds;
+        let offset = self.velocity * seconds;
+
+        let max_x = (map.get_width() as f32 - 1.0 - self.rect.w).max(0.0);
+        let x = (self.rect.x + offset.x).clamp(0.0, max_x);
+        let edge = if offset.x > 0.0 { x + self.rect.w } else { x };
+        if map.on_ground(Point2::new(edge, self.rect.y + self.rect.h / 2.0)) {
+            self.velocity.x = 0.0;
+        } else {
+            self.rect.x = x;
+        }
+
+        if offset.y > 0.0 {
+            let foot = self.foot();
+            match map.raycast(foot, foot + Vector2::new(0.0, offset.y)) {
+                Some(ground) => {
+                    self.rect.y = ground.y - self.rect.h;
+                    self.velocity = Vector2::new(0.0, 0.0);
+                }
+                None => self.rect.y += offset.y,
+            }
+        } else {
+            let top = Point2::new(self.rect.x + self.rect.w / 2.0, self.rect.top());
+            match map.raycast(top, top + Vector2::new(0.0, offset.y)) {
+                Some(ceiling) => {
+                    self.rect.y = ceiling.y + 1.0;
+                    self.velocity.y = 0.0;
+                }
+                None => self.rect.y += offset.y,
+            }
+        }
+    }
+
+    /// Throws the body away from an explosion at `center`, up and the
+    /// harder the closer it is.
+    pub fn knock(&mut self, center: Point2<f32>, radius: f32) {
+        let offset = self.get_center() - center;
+        let distance = (offset.norm() - self.rect.h / 2.0).max(0.0);
+        if distance >= radius {
+            return;
+        }
+        let direction = if offset.norm() > 0.0 {
+            offset.normalize()
+        } else {
+            Vector2::new(0.0, -1.0)
+        };
+        let speed = Self::KNOCK_SPEED * (1.0 - distance / radius);
+        self.velocity += (direction + Vector2::new(0.0, -1.0)) * speed;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    /// A map of `width` by `height` with solid ground from row `ground` down.
+    fn new_map(width: u16, height: u16, ground: u16) -> Map {
+        let mut data = vec![0; width as usize * height as usize * 4];
+        for pixel in data.chunks_mut(4).skip(ground as usize * width as usize) {
+            pixel[3] = 255;
+        }
+        Map::new(&data, width, height)
+    }
+
+    #[test]
+    fn body_falls_to_the_ground() {
+        let map = new_map(100, 100, 80);
+        let mut body = Body::new(Rect::new(45.0, 10.0, 10.0, 20.0), &map);
+        assert_eq!(body.get_rect().bottom(), 30.0);
+        assert!(!body.is_resting(&map));
+        for _ in 0..60 {
+            body.update(1.0 / 30.0, &map);
+        }
+        assert!(body.is_resting(&map));
+        assert_eq!(body.get_rect().bottom(), 80.0);
+        assert!(!body.is_gone(&map));
+    }
+
+    #[test]
+    fn body_is_knocked_away() {
+        let map = new_map(200, 100, 80);
+        let mut body = Body::new(Rect::new(95.0, 60.0, 10.0, 20.0), &map);
+        assert!(body.is_resting(&map));
+
+        body.knock(Point2::new(0.0, 70.0), 30.0);
+        assert!(body.is_resting(&map));
+
+        let x = body.get_rect().x;
+        body.knock(Point2::new(90.0, 75.0), 30.0);
+        body.update(1.0 / 30.0, &map);
+        assert!(body.get_rect().x > x);
+        assert!(body.get_rect().bottom() < 80.0);
+        for _ in 0..90 {
+            body.update(1.0 / 30.0, &map);
+        }
+        assert!(body.is_resting(&map));
+    }
+}
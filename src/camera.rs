@@ -0,0 +1,88 @@
+use ggez::graphics::Rect;
+use ggez::nalgebra::{Point2, Vector2};
+
+/// The part of the map a spectator looks at, moved around freely and
+/// zoomed in up to `MAX_ZOOM` times.
+pub struct Camera {
+    center: Point2<f32>,
+    zoom: f32,
+    /// -1.0 to 1.0 on both axes.
+    direction: Vector2<f32>,
+    width: f32,
+    height: f32,
+}
+
+impl Camera {
+    pub const SPEED: f32 = 600.0;
+    pub const ZOOM_STEP: f32 = 1.25;
+    pub const MAX_ZOOM: f32 = 4.0;
+
+    /// A camera over the whole map of `width` by `height`.
+    pub fn new(width: f32, height: f32) -> Self {
+        Camera {
+            center: Point2::new(width / 2.0, height / 2.0),
+            zoom: 1.0,
+            direction: Vector2::new(0.0, 0.0),
+            width,
+            height,
+        }
+    }
+
+    pub fn set_direction_x(&mut self, x: f32) {
+        self.direction.x = x;
+    }
+
+    pub fn set_direction_y(&mut self, y: f32) {
+        self.direction.y = y;
+    }
+
+    /// Zooms in for positive `steps`, out for negative ones.
+    pub fn zoom(&mut self, steps: f32) {
+        self.zoom = (self.zoom * Self::ZOOM_STEP.powf(steps)).clamp(1.0, Self::MAX_ZOOM);
+        self.keep_on_map();
+    }
+
+    /// Moves faster when zoomed out, so crossing the view takes as long at
+    /// every zoom.
+    pub fn update(&mut self, seconds: f32) {
+        self.center += self.direction * Self::SPEED * seconds / self.zoom;
+        self.keep_on_map();
+    }
+
+    /// The part of the map in view.
+    pub fn view(&self) -> Rect {
+        let (w, h) = (self.width / self.zoom, self.height / self.zoom);
+        Rect::new(self.center.x - w / 2.0, self.center.y - h / 2.0, w, h)
+    }
+
+    /// Moves the center so the view doesn't go beyond the edges of the map.
+    fn keep_on_map(&mut self) {
+        let (w, h) = (self.width / self.zoom, self.height / self.zoom);
+        self.center.x = self.center.x.clamp(w / 2.0, self.width - w / 2.0);
+        self.center.y = self.center.y.clamp(h / 2.0, self.height - h / 2.0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn camera_stays_on_map() {
+        let mut camera = Camera::new(800.0, 600.0);
+        assert_eq!(camera.view(), Rect::new(0.0, 0.0, 800.0, 600.0));
+        camera.zoom(-3.0);
+        assert_eq!(camera.view(), Rect::new(0.0, 0.0, 800.0, 600.0));
+        camera.zoom(100.0);
+        assert_eq!(camera.view(), Rect::new(300.0, 225.0, 200.0, 150.0));
+        camera.set_direction_x(1.0);
+        camera.update(1.0);
+        assert_eq!(camera.view(), Rect::new(450.0, 225.0, 200.0, 150.0));
+        camera.update(10.0);
+        assert_eq!(camera.view(), Rect::new(600.0, 225.0, 200.0, 150.0));
+        camera.set_direction_x(-1.0);
+        camera.update(1.0);
+        assert_eq!(camera.view(), Rect::new(450.0, 225.0, 200.0, 150.0));
+    }
+}
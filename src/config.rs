@@ -13,6 +13,69 @@ pub static CONFIG: GameConfig = GameConfig {
                 image_firing: "/crab-firing.png",
                 width: 48,
                 height: 32,
+                sheet: Some(SheetConfig {
+                    image: "/crab-sheet.png",
+                    frame_width: 64,
+                    frame_height: 43,
+                    animations: &[
+                        AnimationConfig {
+                            state: "Idle",
+                            row: 0,
+                            frames: 2,
+                            fps: 2.0,
+                            looping: true,
+                        },
+                        AnimationConfig {
+                            state: "Walk",
+                            row: 1,
+                            frames: 4,
+                            fps: 10.0,
+                            looping: true,
+                        },
+                        AnimationConfig {
+                            state: "Jump",
+                            row: 2,
+                            frames: 1,
+                            fps: 1.0,
+                            looping: false,
+                        },
+                        AnimationConfig {
+                            state: "Fall",
+                            row: 3,
+                            frames: 1,
+                            fps: 1.0,
+                            looping: false,
+                        },
+                        AnimationConfig {
+                            state: "Aim",
+                            row: 4,
+                            frames: 1,
+                            fps: 1.0,
+                            looping: false,
+                        },
+                        AnimationConfig {
+                            state: "Fire",
+                            row: 5,
+                            frames: 2,
+                            fps: 8.0,
+                            looping: false,
+                        },
+                        AnimationConfig {
+                            state: "Hurt",
+                            row: 6,
+                            frames: 2,
+                            fps: 8.0,
+                            looping: true,
+                        },
+                        AnimationConfig {
+                            state: "Die",
+                            row: 7,
+                            frames: 4,
+                            fps: 4.0,
+                            looping: false,
+                        },
+                    ],
+                }),
             },
         },
         PlayerConfig {
@@ -23,14 +86,17 @@ pub static CONFIG: GameConfig = GameConfig {
                 image_firing: "/crab-firing2.png",
                 width: 48,
                 height: 32,
+                sheet: None,
             },
         },
     ],
     map: MapConfig {
-        image: "/map.png",
+        package: "/maps/island/map.toml",
+        scorch: true,
     },
     weapons: WeaponsConfig {
         image: "/weapons.png",
+        ammo: &[("Girder", 2), ("Blowtorch", 2), ("Drill", 2), ("PoisonDart", 2), ("FreezeRay", 1)],
     },
     shots: ShotsConfig {
         pistol: ShotConfig {
@@ -65,6 +131,59 @@ pub static CONFIG: GameConfig = GameConfig {
         width: 20,
         height: 25,
     },
+    controls: ControlsConfig {
+        keys: &[
+            ("MoveLeft", &["Left"]),
+            ("MoveRight", &["Right"]),
+            ("AimUp", &["Up"]),
+            ("AimDown", &["Down"]),
+            ("FineAim", &["LShift", "RShift"]),
+            ("Fire", &["Space"]),
+            ("Jump", &["Return"]),
+            ("OpenWeapons", &["W"]),
+            ("NextCrab", &["Tab"]),
+            ("Surrender", &["F10"]),
+            ("QuickSave", &["F5"]),
+            ("QuickLoad", &["F9"]),
+            ("Pause", &["Escape"]),
+        ],
+        buttons: &[
+            ("MoveLeft", &["DPadLeft"]),
+            ("MoveRight", &["DPadRight"]),
+            ("AimUp", &["DPadUp"]),
+            ("AimDown", &["DPadDown"]),
+            ("FineAim", &["LeftTrigger"]),
+            ("Fire", &["South"]),
+            ("Jump", &["East"]),
+            ("OpenWeapons", &["North"]),
+            ("NextCrab", &["West"]),
+            ("Pause", &["Start"]),
+        ],
+    },
+    audio: AudioConfig {
+        sounds: &[
+            ("Pistol", "/sounds/pistol.wav"),
+            ("Bazooka", "/sounds/bazooka.wav"),
+            ("Girder", "/sounds/girder.wav"),
+            ("Blowtorch", "/sounds/blowtorch.wav"),
+            ("Drill", "/sounds/drill.wav"),
+            ("PoisonDart", "/sounds/poison-dart.wav"),
+            ("FreezeRay", "/sounds/freeze-ray.wav"),
+            ("Explosion", "/sounds/explosion.wav"),
+            ("Footstep", "/sounds/footstep.wav"),
+            ("Jump", "/sounds/jump.wav"),
+            ("Splash", "/sounds/splash.wav"),
+            ("Hurt", "/sounds/hurt.wav"),
+            ("Death", "/sounds/death.wav"),
+            ("TurnStart", "/sounds/turn-start.wav"),
+        ],
+        music: &[
+            ("Menu", "/music/menu.wav"),
+            ("Match", "/music/match.wav"),
+        ],
+        music_volume: 60,
+        effects_volume: 80,
+    },
 };
 
 pub struct GameConfig {
@@ -76,6 +195,23 @@ pub struct GameConfig {
     pub shots: ShotsConfig,
     pub aim: ImageConfig,
     pub arrow: ImageConfig,
+    pub controls: ControlsConfig,
+    pub audio: AudioConfig,
+}
+
+/// Sound effects and music tracks by `Sound` and `Music` name. Missing files are skipped.
+pub struct AudioConfig {
+    pub sounds: &'static [(&'static str, &'static str)],
+    pub music: &'static [(&'static str, &'static str)],
+    /// Default volumes in percent.
+    pub music_volume: u8,
+    pub effects_volume: u8,
+}
+
+/// Default bindings, from action names to key and gamepad button names.
+pub struct ControlsConfig {
+    pub keys: &'static [(&'static str, &'static [&'static str])],
+    pub buttons: &'static [(&'static str, &'static [&'static str])],
 }
 
 pub struct CrabConfig {
@@ -83,6 +219,26 @@ pub struct CrabConfig {
     pub image_firing: &'static str,
     pub width: u16,
     pub height: u16,
+    /// Animated sprites, `image` and `image_firing` are used without them.
+    pub sheet: Option<SheetConfig>,
+}
+
+/// A sprite sheet with one row of equally sized frames per animation.
+pub struct SheetConfig {
+    pub image: &'static str,
+    pub frame_width: u16,
+    pub frame_height: u16,
+    pub animations: &'static [AnimationConfig],
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AnimationConfig {
+    /// Name of a `CrabState`, e.g. "Walk".
+    pub state: &'static str,
+    pub row: u16,
+    pub frames: u16,
+    pub fps: f32,
+    pub looping: bool,
 }
 
 pub struct PowerConfig {
@@ -98,11 +254,14 @@ pub struct PlayerConfig {
 }
 
 pub struct MapConfig {
-    pub image: &'static str,
+    pub package: &'static str,
+    pub scorch: bool,
 }
 
 pub struct WeaponsConfig {
     pub image: &'static str,
+    /// Shots every team has of a weapon, the ones not listed are unlimited.
+    pub ammo: &'static [(&'static str, u32)],
 }
 
 pub struct ShotsConfig {
@@ -0,0 +1,297 @@
+use crate::config::ControlsConfig;
+use ggez::event::{Button, KeyCode};
+use ggez::{filesystem, Context, GameError, GameResult};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{Read, Write};
+
+/// What a key or a gamepad button does in a match.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    MoveLeft,
+    MoveRight,
+    AimUp,
+    AimDown,
+    /// Slows the aim down while held.
+    FineAim,
+    /// Charges while held, fires on release.
+    Fire,
+    Jump,
+    OpenWeapons,
+    NextCrab,
+    Surrender,
+    QuickSave,
+    QuickLoad,
+    Pause,
+}
+
+impl Action {
+    pub const ALL: [Action; 13] = [
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::AimUp,
+        Action::AimDown,
+        Action::FineAim,
+        Action::Fire,
+        Action::Jump,
+        Action::OpenWeapons,
+        Action::NextCrab,
+        Action::Surrender,
+        Action::QuickSave,
+        Action::QuickLoad,
+        Action::Pause,
+    ];
+
+    /// The name used in the config and in the bindings file.
+    pub fn name(self) -> String {
+        format!("{:?}", self)
+    }
+
+    pub fn from_name(name: &str) -> Option<Action> {
+        Self::ALL.iter().copied().find(|action| action.name() == name)
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Action::MoveLeft => "Move left",
+            Action::MoveRight => "Move right",
+            Action::AimUp => "Aim up",
+            Action::AimDown => "Aim down",
+            Action::FineAim => "Fine aim",
+            Action::Fire => "Charge / fire",
+            Action::Jump => "Jump",
+            Action::OpenWeapons => "Weapons",
+            Action::NextCrab => "Next crab",
+            Action::Surrender => "Surrender",
+            Action::QuickSave => "Quick save",
+            Action::QuickLoad => "Quick load",
+            Action::Pause => "Pause",
+        }
+    }
+
+    /// The key the action stands for in menus, so they work with a gamepad.
+    pub fn menu_key(self) -> Option<KeyCode> {
+        match self {
+            Action::MoveLeft => Some(KeyCode::Left),
+            Action::MoveRight => Some(KeyCode::Right),
+            Action::AimUp => Some(KeyCode::Up),
+            Action::AimDown => Some(KeyCode::Down),
+            Action::Fire | Action::Jump => Some(KeyCode::Return),
+            Action::Pause => Some(KeyCode::Escape),
+            _ => None,
+        }
+    }
+}
+
+/// Keys that can be bound, found by their `Debug` name.
+const KEYS: &[KeyCode] = &[
+    KeyCode::A, KeyCode::B, KeyCode::C, KeyCode::D, KeyCode::E, KeyCode::F, KeyCode::G,
+    KeyCode::H, KeyCode::I, KeyCode::J, KeyCode::K, KeyCode::L, KeyCode::M, KeyCode::N,
+    KeyCode::O, KeyCode::P, KeyCode::Q, KeyCode::R, KeyCode::S, KeyCode::T, KeyCode::U,
+    KeyCode::V, KeyCode::W, KeyCode::X, KeyCode::Y, KeyCode::Z,
+    KeyCode::Key0, KeyCode::Key1, KeyCode::Key2, KeyCode::Key3, KeyCode::Key4,
+    KeyCode::Key5, KeyCode::Key6, KeyCode::Key7, KeyCode::Key8, KeyCode::Key9,
+    KeyCode::F1, KeyCode::F2, KeyCode::F3, KeyCode::F4, KeyCode::F5, KeyCode::F6,
+    KeyCode::F7, KeyCode::F8, KeyCode::F9, KeyCode::F10, KeyCode::F11, KeyCode::F12,
+    KeyCode::Left, KeyCode::Right, KeyCode::Up, KeyCode::Down,
+    KeyCode::Space, KeyCode::Return, KeyCode::Escape, KeyCode::Tab, KeyCode::Back,
+    KeyCode::Insert, KeyCode::Delete, KeyCode::Home, KeyCode::End,
+    KeyCode::PageUp, KeyCode::PageDown,
+    KeyCode::LShift, KeyCode::RShift, KeyCode::LControl, KeyCode::RControl,
+    KeyCode::LAlt, KeyCode::RAlt,
+    KeyCode::Numpad0, KeyCode::Numpad1, KeyCode::Numpad2, KeyCode::Numpad3,
+    KeyCode::Numpad4, KeyCode::Numpad5, KeyCode::Numpad6, KeyCode::Numpad7,
+    KeyCode::Numpad8, KeyCode::Numpad9,
+];
+
+const BUTTONS: &[Button] = &[
+    Button::South, Button::East, Button::North, Button::West, Button::C, Button::Z,
+    Button::LeftTrigger, Button::LeftTrigger2, Button::RightTrigger, Button::RightTrigger2,
+    Button::Select, Button::Start, Button::Mode, Button::LeftThumb, Button::RightThumb,
+    Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+];
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEYS.iter().copied().find(|key| format!("{:?}", key) == name)
+}
+
+pub fn button_from_name(name: &str) -> Option<Button> {
+    BUTTONS.iter().copied().find(|button| format!("{:?}", button) == name)
+}
+
+/// Which keys and gamepad buttons trigger which actions.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Bindings {
+    keys: Vec<(KeyCode, Action)>,
+    buttons: Vec<(Button, Action)>,
+}
+
+/// The bindings file, from action names to key or button names.
+#[derive(Debug, Default, Deserialize, Serialize)]
+struct BindingsFile {
+    #[serde(default)]
+    keys: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    buttons: BTreeMap<String, Vec<String>>,
+}
+
+impl Bindings {
+    /// Rebound controls, saved in the user config directory.
+    pub const FILE: &'static str = "/controls.toml";
+
+    /// The default bindings of the config. Unknown names are skipped.
+    pub fn new(cfg: &ControlsConfig) -> Self {
+        let mut bindings = Bindings {
+            keys: vec![],
+            buttons: vec![],
+        };
+        for (action, keys) in cfg.keys {
+            if let Some(action) = Action::from_name(action) {
+                for key in keys.iter().filter_map(|name| key_from_name(name)) {
+                    bindings.keys.push((key, action));
+                }
+            }
+        }
+        for (action, buttons) in cfg.buttons {
+            if let Some(action) = Action::from_name(action) {
+                for button in buttons.iter().filter_map(|name| button_from_name(name)) {
+                    bindings.buttons.push((button, action));
+                }
+            }
+        }
+        bindings
+    }
+
+    /// The default bindings with the saved ones on top.
+    pub fn load(ctx: &mut Context, cfg: &ControlsConfig) -> Self {
+        let mut bindings = Self::new(cfg);
+        if !filesystem::is_file(ctx, Self::FILE) {
+            return bindings;
+        }
+        let mut contents = String::new();
+        let result = filesystem::open(ctx, Self::FILE)
+            .and_then(|mut file| Ok(file.read_to_string(&mut contents)?))
+            .and_then(|_| bindings.parse(&contents));
+        if let Err(e) = result {
+            eprintln!("Ignoring the saved controls: {}", e);
+            bindings = Self::new(cfg);
+        }
+        bindings
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult {
+        let mut file = filesystem::create(ctx, Self::FILE)?;
+        file.write_all(self.to_toml().as_bytes())?;
+        Ok(())
+    }
+
+    /// Replaces the bindings of every action listed in `contents`.
+    pub fn parse(&mut self, contents: &str) -> GameResult {
+        let invalid = |what: &str, name: &str| {
+            GameError::ResourceLoadError(format!("invalid controls: unknown {} {}", what, name))
+        };
+        let file: BindingsFile = toml::from_str(contents)
+            .map_err(|e| GameError::ResourceLoadError(format!("invalid controls: {}", e)))?;
+        for (action, keys) in file.keys.iter() {
+            let action = Action::from_name(action).ok_or_else(|| invalid("action", action))?;
+            self.keys.retain(|&(_, a)| a != action);
+            for name in keys {
+                let key = key_from_name(name).ok_or_else(|| invalid("key", name))?;
+                self.bind_key(action, key, false);
+            }
+        }
+        for (action, buttons) in file.buttons.iter() {
+            let action = Action::from_name(action).ok_or_else(|| invalid("action", action))?;
+            self.buttons.retain(|&(_, a)| a != action);
+            for name in buttons {
+                let button = button_from_name(name).ok_or_else(|| invalid("button", name))?;
+                self.bind_button(action, button, false);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn to_toml(&self) -> String {
+        let mut file = BindingsFile::default();
+        for action in Action::ALL.iter() {
+            let keys = self.keys(*action).iter().map(|key| format!("{:?}", key)).collect();
+            file.keys.insert(action.name(), keys);
+            let buttons = self.buttons(*action).iter().map(|b| format!("{:?}", b)).collect();
+            file.buttons.insert(action.name(), buttons);
+        }
+        toml::to_string(&file).unwrap_or_default()
+    }
+
+    pub fn key_action(&self, key: KeyCode) -> Option<Action> {
+        self.keys.iter().find(|&&(k, _)| k == key).map(|&(_, action)| action)
+    }
+
+    pub fn button_action(&self, button: Button) -> Option<Action> {
+        self.buttons.iter().find(|&&(b, _)| b == button).map(|&(_, action)| action)
+    }
+
+    pub fn keys(&self, action: Action) -> Vec<KeyCode> {
+        self.keys.iter().filter(|&&(_, a)| a == action).map(|&(key, _)| key).collect()
+    }
+
+    pub fn buttons(&self, action: Action) -> Vec<Button> {
+        self.buttons.iter().filter(|&&(_, a)| a == action).map(|&(b, _)| b).collect()
+    }
+
+    /// Binds `key` to `action` only, dropping the other keys of the action when `replace` is set.
+    pub fn bind_key(&mut self, action: Action, key: KeyCode, replace: bool) {
+        self.keys.retain(|&(k, a)| k != key && !(replace && a == action));
+        self.keys.push((key, action));
+    }
+
+    pub fn bind_button(&mut self, action: Action, button: Button, replace: bool) {
+        self.buttons.retain(|&(b, a)| b != button && !(replace && a == action));
+        self.buttons.push((button, action));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+
+    #[test]
+    fn bindings_new() {
+        let bindings = Bindings::new(&CONFIG.controls);
+        assert_eq!(bindings.key_action(KeyCode::Space), Some(Action::Fire));
+        assert_eq!(bindings.key_action(KeyCode::Escape), Some(Action::Pause));
+        assert_eq!(bindings.button_action(Button::South), Some(Action::Fire));
+        assert_eq!(bindings.key_action(KeyCode::Z), None);
+    }
+
+    #[test]
+    fn bindings_bind_key() {
+        let mut bindings = Bindings::new(&CONFIG.controls);
+        bindings.bind_key(Action::Jump, KeyCode::Space, true);
+        assert_eq!(bindings.key_action(KeyCode::Space), Some(Action::Jump));
+        assert_eq!(bindings.keys(Action::Jump), vec![KeyCode::Space]);
+        assert!(bindings.keys(Action::Fire).is_empty());
+    }
+
+    #[test]
+    fn bindings_round_trip() {
+        let mut bindings = Bindings::new(&CONFIG.controls);
+        bindings.bind_key(Action::MoveLeft, KeyCode::A, false);
+        bindings.bind_button(Action::Pause, Button::Select, true);
+        let mut loaded = Bindings::new(&CONFIG.controls);
+        loaded.parse(&bindings.to_toml()).unwrap();
+        assert_eq!(loaded.keys(Action::MoveLeft), vec![KeyCode::Left, KeyCode::A]);
+        assert_eq!(loaded.buttons(Action::Pause), vec![Button::Select]);
+    }
+
+    #[test]
+    fn bindings_parse_invalid() {
+        let mut bindings = Bindings::new(&CONFIG.controls);
+        assert!(bindings.parse("[keys]\nFly = [\"A\"]").is_err());
+        assert!(bindings.parse("[keys]\nJump = [\"Nope\"]").is_err());
+        assert!(bindings.parse("[keys]\nJump = [\"J\"]").is_ok());
+        assert_eq!(bindings.key_action(KeyCode::J), Some(Action::Jump));
+        assert_eq!(bindings.key_action(KeyCode::Return), None);
+    }
+}
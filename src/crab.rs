@@ -1,22 +1,41 @@
 use crate::map::Map;
 use crate::shot::Shot;
+use crate::status::{Status, StatusKind, Statuses};
 use crate::weapon::{Weapon, WeaponType};
 use ggez::graphics::Rect;
 use ggez::nalgebra::{Point2, Vector2};
 use ggez::nalgebra as na;
+use serde::{Deserialize, Serialize};
 
+#[derive(Serialize, Deserialize)]
 pub struct Crab {
+    #[serde(with = "crate::state::vector")]
     pub velocity: Vector2<f32>,
     pub weapon: Option<Weapon>,
     pub name: String,
+    #[serde(with = "crate::state::rect")]
     rect: Rect,
     health: f32,
+    /// Vertical speed while the crab is in the air after a jump.
+    jump: Option<f32>,
+    /// -1.0 when the crab last moved left, 1.0 when it moved right.
+    facing: f32,
+    /// Angle of the weapon above (negative) or below the horizon in the facing direction.
+    aim: f32,
+    #[serde(default)]
+    statuses: Statuses,
 }
 
 impl Crab {
     pub const SPEED: f32 = 250.0;
     pub const GRAVITY: f32 = 50.0;
     pub const HEALTH: f32 = 100.0;
+    pub const JUMP_SPEED: f32 = 250.0;
+    pub const JUMP_GRAVITY: f32 = 800.0;
+    /// Aim rotation in radians per second.
+    pub const AIM_SPEED: f32 = 1.0;
+    pub const FINE_AIM_SPEED: f32 = 0.2;
+    pub const MAX_AIM: f32 = std::f32::consts::FRAC_PI_2;
 
     pub fn new(name: &str, rect: Rect) -> Self {
         Crab {
@@ -25,25 +44,40 @@ impl Crab {
             velocity: Vector2::new(Self::SPEED, 0.0),
             weapon: None,
             health: Self::HEALTH,
+            jump: None,
+            facing: 1.0,
+            aim: 0.0,
+            statuses: Statuses::default(),
         }
     }
 
     pub fn update(&mut self, direction: Vector2<f32>, seconds: f32, map: &Map) {
         let old_x = self.rect.x;
+        if direction.x != 0.0 && direction.x.signum() != self.facing {
+            self.facing = direction.x.signum();
+            self.point_weapon();
+        }
         self.rect.x = na::clamp(
             self.rect.x + self.velocity.x * direction.x * seconds,
             0.0,
             map.get_width() as f32 - 1.0  - self.rect.w,
         );
 
-        let mut steps: f32 = 0.0;
-        // If crab is underground keep climbing up.
-        while map.on_ground(Point2::new(
-            self.rect.x + self.rect.w / 2.0,
-            self.rect.bottom() - steps - 1.0,
-        )) {
-            steps += 1.0;
+        if let Some(speed) = self.jump {
+            self.fly(old_x, speed, seconds, map);
+            return;
         }
+
+        // If crab is underground climb up to the first empty pixel.
+        let above = Point2::new(self.rect.x + self.rect.w / 2.0, self.rect.bottom() - 1.0);
+        let steps = if map.on_ground(above) {
+            match map.first_empty_above(above) {
+                Some(empty) => above.y.round() - empty.y,
+                None => above.y.round() + 1.0,
+            }
+        } else {
+            0.0
+        };
         if 0.0 < self.rect.h && steps > self.rect.h && direction.x != 0.0 {
             self.rect.x = old_x;
         } else {
@@ -51,37 +85,139 @@ impl Crab {
         }
 
         // If crab is above the ground fall to the ground.
-        while !map.on_ground(self.bottom_middle()) &&
-            (!map.on_ground(self.bottom_left()) || !map.on_ground(self.bottom_right())) &&
-                self.rect.bottom() < map.get_height() as f32
-            {
-                self.rect.y += 1.0;
+        let bottom = self.rect.bottom();
+        let height = map.get_height() as f32;
+        if bottom < height {
+            let fall = match self.landing_row(map) {
+                Some(row) => row - bottom.round(),
+                None => (height - bottom).ceil(),
+            };
+            self.rect.y += fall.min((height - bottom).ceil());
+        }
+    }
+
+    pub fn jump(&mut self, map: &Map) {
+        if self.jump.is_none() && self.landing_row(map) == Some(self.rect.bottom().round()) {
+            self.jump = Some(-Self::JUMP_SPEED);
+        }
+    }
+
+    pub fn is_airborne(&self) -> bool {
+        self.jump.is_some()
+    }
+
+    /// Upwards (negative) or downwards speed while the crab is in the air.
+    pub fn vertical_speed(&self) -> Option<f32> {
+        self.jump
+    }
+
+    fn fly(&mut self, old_x: f32, speed: f32, seconds: f32, map: &Map) {
+        // Walls stop a sideways jump.
+        let middle = self.rect.y + self.rect.h / 2.0;
+        if [self.rect.left(), self.rect.right()]
+            .iter()
+            .any(|&x| map.on_ground(Point2::new(x, middle)))
+        {
+            self.rect.x = old_x;
+        }
+
+        let dy = speed * seconds;
+        self.jump = Some(speed + Self::JUMP_GRAVITY * seconds);
+        if dy < 0.0 {
+            let top = Point2::new(self.rect.x + self.rect.w / 2.0, self.rect.top());
+            match map.raycast(top, top + Vector2::new(0.0, dy)) {
+                Some(ceiling) => {
+                    self.rect.y = ceiling.y + 1.0;
+                    self.jump = Some(0.0);
+                }
+                None => self.rect.y += dy,
+            }
+        } else {
+            let landing = self.landing_row(map).unwrap_or(map.get_height() as f32);
+            if self.rect.bottom() + dy >= landing {
+                self.rect.y = landing - self.rect.h;
+                self.jump = None;
+            } else {
+                self.rect.y += dy;
             }
+        }
+    }
+
+    /// The first row below the crab where it stands on its middle or on both of its corners.
+    fn landing_row(&self, map: &Map) -> Option<f32> {
+        let middle = map.first_solid_below(self.bottom_middle()).map(|p| p.y);
+        let mut row = self.rect.bottom();
+        let corners = loop {
+            let left = map.first_solid_below(Point2::new(self.rect.left(), row));
+            let right = map.first_solid_below(Point2::new(self.rect.right(), row));
+            match (left, right) {
+                (Some(left), Some(right)) if left.y == right.y => break Some(left.y),
+                (Some(left), Some(right)) => row = left.y.max(right.y),
+                _ => break None,
+            }
+        };
+        match (middle, corners) {
+            (Some(middle), Some(corners)) => Some(middle.min(corners)),
+            (middle, corners) => middle.or(corners),
+        }
+    }
+
+    /// Moves the crab by `offset` and lets it settle on the ground.
+    pub fn move_by(&mut self, offset: Vector2<f32>, map: &Map) {
+        self.rect.x = na::clamp(
+            self.rect.x + offset.x,
+            0.0,
+            map.get_width() as f32 - 1.0 - self.rect.w,
+        );
+        self.rect.y += offset.y;
+        self.update(Vector2::new(0.0, 0.0), 0.0, map);
     }
 
     pub fn set_weapon(&mut self, weapon: WeaponType) {
-        self.weapon = Some(Weapon::new(weapon))
+        self.weapon = Some(Weapon::new(weapon));
+        self.point_weapon();
     }
 
     pub fn has_weapon(&self) -> bool {
        self.weapon.is_some()
     }
 
-    pub fn set_weapon_direction(&mut self, seconds: f32) {
-        match &mut self.weapon {
-            None => (),
-            Some(weapon) => {
-                let rot = ggez::nalgebra::geometry::Rotation2::new(seconds * 1.0);
-                weapon.set_direction(rot * weapon.direction())
-            },
+    /// Turns the aim by `angle` radians, down for positive angles, within the facing half-plane.
+    pub fn rotate_aim(&mut self, angle: f32) {
+        self.aim = na::clamp(self.aim + angle, -Self::MAX_AIM, Self::MAX_AIM);
+        self.point_weapon();
+    }
+
+    /// Faces and aims towards `direction`.
+    pub fn aim(&mut self, direction: Vector2<f32>) {
+        if direction.x != 0.0 {
+            self.facing = direction.x.signum();
         }
+        self.aim = na::clamp(direction.y.atan2(direction.x.abs()), -Self::MAX_AIM, Self::MAX_AIM);
+        self.point_weapon();
     }
 
-    pub fn fire(&mut self, power: f32) -> Vec<Shot> {
-        match &self.weapon {
+    /// Aim above the horizon in degrees.
+    pub fn aim_degrees(&self) -> f32 {
+        -self.aim.to_degrees()
+    }
+
+    fn point_weapon(&mut self) {
+        let direction = Vector2::new(self.facing * self.aim.cos(), self.aim.sin());
+        if let Some(weapon) = &mut self.weapon {
+            weapon.set_direction(direction)
+        }
+    }
+
+    pub fn fire(&self, power: f32) -> Vec<Shot> {
+        let mut shots = match &self.weapon {
             None => vec![],
             Some(weapon) => weapon.fire(Point2::new(self.rect.x, self.rect.y), power),
+        };
+        if self.has_status(StatusKind::DoubleDamage) {
+            shots.iter_mut().for_each(|shot| shot.set_multiplier(2.0));
         }
+        shots
     }
 
     pub fn get_rect(&self) -> Rect {
@@ -92,6 +228,15 @@ impl Crab {
         Point2::new(self.rect.x, self.rect.y)
     }
 
+    pub fn get_center(&self) -> Point2<f32> {
+        Point2::new(self.rect.x + self.rect.w / 2.0, self.rect.y + self.rect.h / 2.0)
+    }
+
+    /// Horizontal direction the crab is facing, -1.0 for left and 1.0 for right.
+    pub fn facing(&self) -> f32 {
+        self.facing
+    }
+
     pub fn get_health(&self) -> f32 {
         self.health
     }
@@ -100,11 +245,42 @@ impl Crab {
         self.health -= damage;
     }
 
+    /// Takes `damage` from a shot or an explosion, the shield first.
+    pub fn take_damage(&mut self, damage: f32) {
+        let damage = self.statuses.absorb(damage);
+        self.reduce_health(damage);
+    }
+
+    pub fn statuses(&self) -> &Statuses {
+        &self.statuses
+    }
+
+    pub fn has_status(&self, kind: StatusKind) -> bool {
+        self.statuses.has(kind)
+    }
+
+    pub fn add_status(&mut self, status: Status) {
+        self.statuses.add(status);
+    }
+
+    /// Counts a turn of the crab's team off its statuses, see `Statuses::tick`
+    /// for `played`. Poison doesn't kill, it leaves the crab at 1 health at most.
+    pub fn tick_statuses(&mut self, played: bool) {
+        let poison = self.statuses.tick(played);
+        self.health = (self.health - poison).max(self.health.min(1.0));
+    }
+
+    /// Whether the crab was killed and waits for its death to be resolved.
+    pub fn is_dying(&self) -> bool {
+        self.health <= 0.0
+    }
+
     #[allow(dead_code)]
     fn top_left(&self) -> Point2<f32> {
         Point2::new(self.rect.left(), self.rect.top())
     }
 
+    #[allow(dead_code)]
     fn bottom_left(&self) -> Point2<f32> {
         Point2::new(self.rect.left(), self.rect.bottom())
     }
@@ -114,6 +290,7 @@ impl Crab {
         Point2::new(self.rect.right(), self.rect.top())
     }
 
+    #[allow(dead_code)]
     fn bottom_right(&self) -> Point2<f32> {
         Point2::new(self.rect.right(), self.rect.bottom())
     }
@@ -268,11 +445,92 @@ mod tests {
         assert_eq!(crab.get_pos(), Point2::new(2.0, 1.0));
     }
 
+    #[test]
+    fn crab_jumps_and_lands() {
+        let mut data = vec![];
+        for y in 0..40 {
+            for _ in 0..10 {
+                data.extend(&[0, 0, 0, if y >= 35 { 255 } else { 0 }]);
+            }
+        }
+        let map = Map::new(&data, 10, 40);
+        let mut crab = new_crab(Rect::new(2.0, 33.0, 2.0, 2.0));
+
+        crab.jump(&map);
+        assert!(crab.is_airborne());
+        crab.update(none(), 0.05, &map);
+        assert!(crab.get_pos().y < 33.0);
+
+        for _ in 0..20 {
+            crab.update(none(), 0.05, &map);
+        }
+        assert!(!crab.is_airborne());
+        assert_eq!(crab.get_pos(), Point2::new(2.0, 33.0));
+    }
+
+    #[test]
+    fn crab_cant_jump_in_the_air() {
+        let map = new_flat_map();
+        let mut crab = new_crab(Rect::new(0.0, -5.0, 0.0, 0.0));
+        crab.jump(&map);
+        assert!(!crab.is_airborne());
+    }
+
+    #[test]
+    fn crab_aim_stays_in_facing_half_plane() {
+        let mut crab = new_crab(Rect::default());
+        crab.set_weapon(WeaponType::Bazooka);
+        crab.rotate_aim(-10.0);
+        assert_eq!(crab.aim_degrees(), 90.0);
+        crab.rotate_aim(std::f32::consts::FRAC_PI_4 + Crab::MAX_AIM);
+        assert!((crab.aim_degrees() + 45.0).abs() < 1e-4);
+        let direction = crab.weapon.as_ref().unwrap().direction();
+        assert!(direction.x > 0.0 && direction.y > 0.0);
+    }
+
+    #[test]
+    fn crab_faces_last_movement() {
+        let map = new_flat_map();
+        let mut crab = new_crab(Rect::new(1.0, 0.0, 0.0, 0.0));
+        crab.set_weapon(WeaponType::Bazooka);
+        crab.rotate_aim(-0.5);
+        crab.update(left(), 0.0, &map);
+        assert_eq!(crab.facing(), -1.0);
+        let direction = crab.weapon.as_ref().unwrap().direction();
+        assert!(direction.x < 0.0 && direction.y < 0.0);
+
+        crab.update(none(), 0.0, &map);
+        assert_eq!(crab.facing(), -1.0);
+    }
+
+    #[test]
+    fn crab_aim_at_direction() {
+        let mut crab = new_crab(Rect::default());
+        crab.set_weapon(WeaponType::Pistol);
+        crab.aim(Vector2::new(-1.0, -1.0));
+        assert_eq!(crab.facing(), -1.0);
+        assert!((crab.aim_degrees() - 45.0).abs() < 1e-4);
+        crab.aim(Vector2::new(0.0, 1.0));
+        assert_eq!(crab.facing(), -1.0);
+        assert_eq!(crab.aim_degrees(), -90.0);
+    }
+
     #[test]
     fn crab_loses_health() {
         let mut crab = new_crab(Rect::default());
         crab.reduce_health(10.0);
         assert_eq!(crab.get_health(), Crab::HEALTH - 10.0);
     }
+
+    #[test]
+    fn crab_double_damage_keeps_blast() {
+        let mut crab = new_crab(Rect::default());
+        crab.set_weapon(WeaponType::Bazooka);
+        let normal = crab.fire(1.0).remove(0);
+        crab.add_status(Status::new(StatusKind::DoubleDamage));
+        let double = crab.fire(1.0).remove(0);
+        assert_eq!(double.damage(), normal.damage());
+        assert_eq!(double.hit_damage(), 2.0 * normal.hit_damage());
+    }
 }
 
@@ -0,0 +1,279 @@
+use crate::shot::ShotType;
+use ggez::graphics::Color;
+use ggez::nalgebra::{Point2, Vector2};
+use rand::Rng;
+
+/// Something visible that happened in the simulation.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Effect {
+    /// A shot hit a crab or the ground. `crater` is set when it carved the terrain.
+    Impact {
+        pos: Point2<f32>,
+        radius: f32,
+        kind: ShotType,
+        crater: bool,
+    },
+    /// A shot or a crab fell into the water.
+    Splash { pos: Point2<f32> },
+    MuzzleFlash {
+        pos: Point2<f32>,
+        direction: Vector2<f32>,
+    },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Particle {
+    pub pos: Point2<f32>,
+    pub velocity: Vector2<f32>,
+    pub color: Color,
+    pub size: f32,
+    /// How much the particle grows every second, smoke puffs up.
+    pub growth: f32,
+    /// Downwards acceleration, negative for things that rise.
+    pub gravity: f32,
+    pub age: f32,
+    pub life: f32,
+}
+
+impl Particle {
+    /// Fades out over the particle's life.
+    pub fn color(&self) -> Color {
+        let mut color = self.color;
+        color.a *= (1.0 - self.age / self.life).max(0.0);
+        color
+    }
+}
+
+/// The live particles, never more than `Particles::MAX`.
+#[derive(Debug, Default)]
+pub struct Particles {
+    particles: Vec<Particle>,
+}
+
+impl Particles {
+    pub const MAX: usize = 1500;
+    const DEBRIS_PER_RADIUS: f32 = 0.8;
+    const FLASH_COLOR: Color = Color {
+        r: 1.0,
+        g: 0.95,
+        b: 0.7,
+        a: 1.0,
+    };
+    const FIRE_COLOR: Color = Color {
+        r: 1.0,
+        g: 0.5,
+        b: 0.1,
+        a: 0.9,
+    };
+    const SMOKE_COLOR: Color = Color {
+        r: 0.35,
+        g: 0.35,
+        b: 0.35,
+        a: 0.6,
+    };
+    const DUST_COLOR: Color = Color {
+        r: 0.8,
+        g: 0.75,
+        b: 0.6,
+        a: 0.7,
+    };
+    const WATER_COLOR: Color = Color {
+        r: 0.7,
+        g: 0.85,
+        b: 1.0,
+        a: 0.9,
+    };
+
+    pub fn iter(&self) -> impl Iterator<Item = &Particle> {
+        self.particles.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.particles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.particles.is_empty()
+    }
+
+    /// Adds a particle unless there are too many already.
+    pub fn push(&mut self, particle: Particle) {
+        if self.particles.len() < Self::MAX {
+            self.particles.push(particle);
+        }
+    }
+
+    /// Spawns the particles of `effect`. Debris takes the color of the ground
+    /// it was blown out of, as given by `ground`.
+    pub fn emit(&mut self, effect: &Effect, ground: impl Fn(Point2<f32>) -> Option<Color>) {
+        let mut rng = rand::thread_rng();
+        match *effect {
+            Effect::Impact {
+                pos,
+                radius,
+                kind,
+                crater,
+            } => {
+                if kind == ShotType::Bazooka {
+                    self.push(Particle {
+                        size: radius,
+                        growth: radius,
+                        life: 0.15,
+                        ..Self::still(pos, Self::FLASH_COLOR)
+                    });
+                    for _ in 0..(radius as usize).min(30) {
+                        self.push(Particle {
+                            velocity: Self::random_direction(&mut rng) * rng.gen_range(20.0, 3.0 * radius),
+                            size: rng.gen_range(2.0, 5.0),
+                            gravity: -40.0,
+                            life: rng.gen_range(0.2, 0.5),
+                            ..Self::still(pos, Self::FIRE_COLOR)
+                        });
+                    }
+                    for _ in 0..(radius as usize / 2).min(15) {
+                        self.push(Particle {
+                            velocity: Vector2::new(rng.gen_range(-15.0, 15.0), rng.gen_range(-40.0, -10.0)),
+                            size: rng.gen_range(4.0, 8.0),
+                            growth: 10.0,
+                            gravity: -10.0,
+                            life: rng.gen_range(1.0, 2.0),
+                            ..Self::still(pos, Self::SMOKE_COLOR)
+                        });
+                    }
+                } else {
+                    for _ in 0..6 {
+                        self.push(Particle {
+                            velocity: Self::random_direction(&mut rng) * rng.gen_range(10.0, 40.0),
+                            size: rng.gen_range(1.5, 3.0),
+                            growth: 4.0,
+                            life: rng.gen_range(0.3, 0.6),
+                            ..Self::still(pos, Self::DUST_COLOR)
+                        });
+                    }
+                }
+                if crater {
+                    for _ in 0..(radius * Self::DEBRIS_PER_RADIUS) as usize {
+                        let direction = Self::random_direction(&mut rng);
+                        let from = pos + direction * rng.gen_range(0.0, radius);
+                        let color = match ground(from) {
+                            Some(color) => color,
+                            None => continue,
+                        };
+                        self.push(Particle {
+                            velocity: Vector2::new(direction.x, direction.y - 1.0) * rng.gen_range(60.0, 180.0),
+                            size: rng.gen_range(1.5, 3.5),
+                            gravity: 400.0,
+                            life: rng.gen_range(0.6, 1.2),
+                            ..Self::still(from, color)
+                        });
+                    }
+                }
+            }
+            Effect::Splash { pos } => {
+                for _ in 0..20 {
+                    self.push(Particle {
+                        velocity: Vector2::new(rng.gen_range(-60.0, 60.0), rng.gen_range(-200.0, -80.0)),
+                        size: rng.gen_range(1.5, 3.0),
+                        gravity: 500.0,
+                        life: rng.gen_range(0.4, 0.8),
+                        ..Self::still(pos, Self::WATER_COLOR)
+                    });
+                }
+            }
+            Effect::MuzzleFlash { pos, direction } => {
+                let direction = if direction.norm() > 0.0 {
+                    direction.normalize()
+                } else {
+                    direction
+                };
+                for i in 0..5 {
+                    self.push(Particle {
+                        velocity: direction * (40.0 + 30.0 * i as f32),
+                        size: 5.0 - i as f32 * 0.7,
+                        life: 0.12,
+                        ..Self::still(pos, Self::FLASH_COLOR)
+                    });
+                }
+            }
+        }
+    }
+
+    pub fn update(&mut self, seconds: f32) {
+        for particle in self.particles.iter_mut() {
+            particle.age += seconds;
+            particle.velocity.y += particle.gravity * seconds;
+            particle.pos += particle.velocity * seconds;
+            particle.size += particle.growth * seconds;
+        }
+        self.particles.retain(|particle| particle.age < particle.life);
+    }
+
+    fn still(pos: Point2<f32>, color: Color) -> Particle {
+        Particle {
+            pos,
+            velocity: Vector2::new(0.0, 0.0),
+            color,
+            size: 1.0,
+            growth: 0.0,
+            gravity: 0.0,
+            age: 0.0,
+            life: 1.0,
+        }
+    }
+
+    fn random_direction(rng: &mut impl Rng) -> Vector2<f32> {
+        let angle = rng.gen_range(0.0, std::f32::consts::PI * 2.0);
+        Vector2::new(angle.cos(), angle.sin())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn impact(crater: bool) -> Effect {
+        Effect::Impact {
+            pos: Point2::new(100.0, 100.0),
+            radius: 25.0,
+            kind: ShotType::Bazooka,
+            crater,
+        }
+    }
+
+    #[test]
+    fn particles_debris_uses_ground_color() {
+        let ground = Color::new(0.1, 0.6, 0.2, 1.0);
+        let mut with_crater = Particles::default();
+        with_crater.emit(&impact(true), |_| Some(ground));
+        let mut without = Particles::default();
+        without.emit(&impact(false), |_| Some(ground));
+
+        assert!(with_crater.len() > without.len());
+        assert!(with_crater.iter().any(|particle| particle.color == ground));
+        assert!(!without.iter().any(|particle| particle.color == ground));
+    }
+
+    #[test]
+    fn particles_expire() {
+        let mut particles = Particles::default();
+        particles.emit(&Effect::Splash { pos: Point2::new(0.0, 0.0) }, |_| None);
+        assert!(!particles.is_empty());
+        let first = *particles.iter().next().unwrap();
+        particles.update(0.1);
+        let moved = *particles.iter().next().unwrap();
+        assert!(moved.pos.y < first.pos.y);
+        assert!(moved.color().a < first.color().a);
+        particles.update(1.0);
+        assert!(particles.is_empty());
+    }
+
+    #[test]
+    fn particles_are_capped() {
+        let mut particles = Particles::default();
+        for _ in 0..1000 {
+            particles.emit(&impact(true), |_| Some(Color::new(1.0, 1.0, 1.0, 1.0)));
+        }
+        assert_eq!(particles.len(), Particles::MAX);
+    }
+}
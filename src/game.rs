@@ -1,56 +1,125 @@
-use crate::config::{GameConfig, PlayerConfig, Screen};
+use crate::audio::Sound;
+use crate::camera::Camera;
+use crate::config::GameConfig;
+use crate::controls::{Action, Bindings};
 use crate::gui::{self, GUI};
 use crate::map::Map;
-use crate::player::Player;
+use crate::net::Lockstep;
+use crate::outcome::MatchResult;
+use crate::pause::{PauseAction, PauseMenu};
+use crate::profile::Profiles;
+use crate::save;
+use crate::package::MapPackage;
 use crate::crab::Crab;
-use crate::shot::{Shot, ShotType};
-use ggez::graphics::Rect;
+use crate::scene::Transition;
+use crate::settings::{Control, MapSource, MatchSettings, Options};
+use crate::simulation::{Command, Simulation, TickInput};
+use crate::terrain;
+use crate::tool::Girder;
+use crate::trajectory::{self, Trajectory};
+use crate::weapon::WeaponType;
+use ggez::event::{Axis, Button};
+use ggez::input::gamepad::GamepadId;
 use ggez::input::mouse::MouseButton;
-use ggez::nalgebra::{Vector2};
+use ggez::nalgebra::Point2;
 use ggez::{event, timer};
-use ggez::{graphics, Context, GameResult};
-use rand::{self, Rng};
+use ggez::{graphics, Context, GameError, GameResult};
 
 #[derive(Debug, Default)]
 struct InputState {
     movement: f32,
     weapons_menu_open: bool,
     weapon_direction: f32,
+    fine_aim: bool,
+    /// Selected item of the weapons menu, for keyboard and gamepad.
+    weapon_cursor: usize,
+    /// Whether fire is held since the turn could shoot.
+    charging: bool,
     power: f32,
+    cursor: (f32, f32),
+    /// Actions waiting for the next tick.
+    commands: Vec<Command>,
 }
 
 pub struct Game {
     cfg: &'static GameConfig,
+    options: Options,
     gui: GUI,
-    map: Map,
-    players: Vec<Player>,
+    sim: Simulation,
     input: InputState,
-    active_player_idx: usize,
-    shots: Vec<GameShot>,
-    shooting_in_progress: bool,
-    winner: String,
-    map_hits: Vec<GameShot>,
+    /// Sounds to play, taken by the scene that owns the audio.
+    sounds: Vec<Sound>,
+    /// The other peers of a network match.
+    lockstep: Option<Lockstep>,
+    /// Where a spectator looks.
+    camera: Option<Camera>,
+    /// A message shown for a while, with the seconds left.
+    notice: Option<(String, f32)>,
+    /// Ticks due that couldn't be simulated yet.
+    pending_ticks: u32,
+    /// Ticks in a row that waited for the input of another peer.
+    waiting: u32,
+    pause: Option<PauseMenu>,
+    bindings: Bindings,
+    /// Whether the result went into the profiles already.
+    recorded: bool,
+    next: Option<Transition>,
 }
 
 impl Game {
-    pub fn new(ctx: &mut Context, cfg: &'static GameConfig) -> GameResult<Game> {
-        let mut players = vec![];
-        let mut players_cfg = vec![];
-        for i in 0..cfg.players_count {
-            let i = i as usize;
-            let player = Game::new_player(&cfg.players[i], &cfg.screen);
-            players.push(player);
-            players_cfg.push(gui::PlayerConfig {
-                name: cfg.players[i].name,
-                crab_image: cfg.players[i].crab.image,
-                crab_firing_image: cfg.players[i].crab.image_firing,
-            });
-        }
+    const GIRDER_ROTATION_STEP: f32 = std::f32::consts::PI / 16.0;
+    const STICK_DEAD_ZONE: f32 = 0.3;
+    /// Ticks without input from the other peers before saying so.
+    const WAITING_NOTICE_TICKS: u32 = 10;
+    /// Ticks sent by an authoritative server simulated at most per frame.
+    const MAX_SERVER_TICKS: usize = 60;
+    const NOTICE_TIME: f32 = 2.0;
+
+    pub fn new(
+        ctx: &mut Context,
+        cfg: &'static GameConfig,
+        settings: MatchSettings,
+        options: Options,
+        bindings: Bindings,
+    ) -> GameResult<Game> {
+        let (package, map_image) = match &settings.map {
+            MapSource::Package(manifest) => {
+                let package = MapPackage::load(ctx, manifest)?;
+                let image = graphics::Image::new(ctx, &package.foreground)?;
+                let map_image = gui::MapImage {
+                    pixels: image.to_rgba8(ctx)?,
+                    width: image.width(),
+                    height: image.height(),
+                };
+                (Some(package), map_image)
+            }
+            MapSource::Generated { seed } => {
+                let (width, height) = (cfg.screen.width as u16, cfg.screen.height as u16);
+                let map_image = gui::MapImage {
+                    pixels: terrain::generate(width, height, *seed),
+                    width,
+                    height,
+                };
+                (None, map_image)
+            }
+        };
+        let players_cfg = settings
+            .teams
+            .iter()
+            .map(|team| gui::PlayerConfig {
+                name: team.name.clone(),
+                crab_image: cfg.players[team.style].crab.image,
+                crab_firing_image: cfg.players[team.style].crab.image_firing,
+                sheet: cfg.players[team.style].crab.sheet.as_ref(),
+            })
+            .collect();
         let gui = GUI::new(
             ctx,
             gui::Config {
                 images: gui::ImagesConfig {
-                    map: cfg.map.image,
+                    map: map_image,
+                    background: package.as_ref().and_then(|p| p.background.clone()),
+                    scorch: options.scorch,
                     weapons: cfg.weapons.image,
                     shots: gui::ShotsConfig {
                         pistol: cfg.shots.pistol.image,
@@ -70,143 +139,530 @@ impl Game {
                 players: players_cfg,
             },
         )?;
-        let map_image = gui.get_map();
-        let data = map_image.to_rgba8(ctx)?;
-        let map = Map::new(&data, map_image.width() as u16, map_image.height() as u16);
-
-        // Necessary for placing players on the ground.
-        for player in players.iter_mut() {
-            for crab in player.crabs.iter_mut() {
-                crab.update(Vector2::new(0.0, 0.0), 0.0, &map);
+        let (data, width, height) = gui.get_map_data();
+        let mut map = Map::new(data, width, height);
+        let water_level = match &package {
+            Some(package) => {
+                if let Some(indestructible) = &package.indestructible {
+                    let image = graphics::Image::new(ctx, indestructible)?;
+                    map.set_indestructible(&image.to_rgba8(ctx)?);
+                }
+                package.water_level
             }
+            None => Some(height as f32 - terrain::WATER_DEPTH),
+        };
+        if settings.rules.water {
+            map.set_water_level(water_level);
         }
+
+        let mut sim = Simulation::new(cfg, settings, map, package.as_ref())?;
+        let sounds = sim.take_events().sounds;
         Ok(Self {
             cfg,
+            options,
             gui,
-            map,
-            players,
+            sim,
             input: InputState::default(),
-            active_player_idx: 0,
-            shots: vec![],
-            shooting_in_progress: false,
-            winner: String::new(),
-            map_hits: vec![],
+            sounds,
+            lockstep: None,
+            camera: None,
+            notice: None,
+            pending_ticks: 0,
+            waiting: 0,
+            pause: None,
+            bindings,
+            next: None,
+            recorded: false,
         })
     }
+
+    /// Resumes the match saved at `path`.
+    pub fn load(
+        ctx: &mut Context,
+        cfg: &'static GameConfig,
+        options: Options,
+        bindings: Bindings,
+        path: &str,
+    ) -> GameResult<Game> {
+        let sim = save::load(ctx, cfg, path)?;
+        let mut game = Game::new(ctx, cfg, sim.settings().clone(), options, bindings)?;
+        game.resume(sim);
+        Ok(game)
+    }
+
+    /// Carries on with `sim` instead of the match this game started with.
+    fn resume(&mut self, sim: Simulation) {
+        self.sim = sim;
+        self.gui.sync_map(self.sim.map());
+    }
+
+    /// Plays the match with the other peers of `lockstep`, only their teams
+    /// are left to them. Spectators pick the match up where it was when they
+    /// joined and look around freely.
+    pub fn set_lockstep(&mut self, mut lockstep: Lockstep) -> GameResult {
+        if let Some(state) = lockstep.take_state() {
+            let sim = Simulation::restore(self.cfg, state)
+                .map_err(|e| GameError::ResourceLoadError(format!("invalid match state: {}", e)))?;
+            self.resume(sim);
+        }
+        if lockstep.is_spectating() {
+            self.camera = Some(Camera::new(self.cfg.screen.width, self.cfg.screen.height));
+        }
+        self.lockstep = Some(lockstep);
+        Ok(())
+    }
+
+    pub fn options(&self) -> Options {
+        self.options
+    }
+
+    pub fn take_sounds(&mut self) -> Vec<Sound> {
+        std::mem::take(&mut self.sounds)
+    }
+
+    /// The scene to switch to, once the match is left.
+    pub fn transition(&mut self) -> Option<Transition> {
+        self.next.take()
+    }
 }
 
 impl Game {
-    fn spawn_shots(&mut self, shots: Vec<Shot>) {
-        self.shots = shots
+    fn girder(&self) -> Girder {
+        let (x, y) = self.input.cursor;
+        self.sim.girder(Point2::new(x, y))
+    }
+
+    fn hud(&self) -> gui::Hud<'_> {
+        let spectating = self.camera.is_some();
+        let teams = self
+            .sim
+            .players()
             .iter()
-            .map(|shot| GameShot {
-                is_alive: true,
-                shot: shot.clone(),
+            .zip(self.sim.settings().teams.iter())
+            .enumerate()
+            .map(|(i, (player, team))| gui::HudTeam {
+                name: &player.name,
+                health: player.total_health(),
+                max_health: team.crabs as f32 * Crab::HEALTH,
+                crabs: player.crabs.len(),
+                ammo: if spectating { self.sim.inventory(i).limited() } else { vec![] },
             })
             .collect();
-        self.shooting_in_progress = true;
+        let active = self.sim.active_player_idx();
+        let weapon = self.sim.active_player().weapon_kind();
+        gui::Hud {
+            teams,
+            active,
+            next: self.sim.next_player_idx(),
+            weapon,
+            ammo: weapon.and_then(|weapon| self.sim.inventory(active).left(weapon)),
+            time_left: self.sim.turn_time_left(),
+            crab_choice: if self.sim.can_select_crab() && self.is_local_turn() {
+                Some(self.sim.free_selects(active))
+            } else {
+                None
+            },
+        }
     }
 
-    fn switch_turn(&mut self) {
-        self.active_player().switch_crab();
-        self.active_player_idx = (self.active_player_idx + 1) % self.players.len();
+    fn restart(&mut self, ctx: &mut Context) {
+        // The peers of a network match can't restart it on their own.
+        if self.lockstep.is_some() {
+            return;
+        }
+        let bindings = self.bindings.clone();
+        let mut settings = self.sim.settings().clone();
+        settings.seed = rand::random();
+        match Game::new(ctx, self.cfg, settings, self.options, bindings) {
+            Ok(game) => *self = game,
+            Err(e) => {
+                eprintln!("Failed to restart the match: {}", e);
+                self.next = Some(Transition::Menu);
+            }
+        }
     }
 
-    fn active_player(&mut self) -> &mut Player {
-        &mut self.players[self.active_player_idx]
+    fn pause(&mut self) {
+        self.input.movement = 0.0;
+        self.input.weapon_direction = 0.0;
+        self.input.power = 0.0;
+        self.input.charging = false;
+        self.pause = Some(PauseMenu::new(self.options));
     }
 
-    fn is_crab_active(&self, name: &str) -> bool {
-        self.players[self.active_player_idx].is_crab_active(name)
+    /// The result of the match, an authoritative server has the last word on it.
+    fn result(&self) -> Option<MatchResult> {
+        let server_result = self.lockstep.as_ref().and_then(Lockstep::result);
+        server_result.or_else(|| self.sim.result())
     }
 
-    fn handle_collisions(&mut self) {
-        for shot in self.shots.iter_mut() {
-            for (i, player) in self.players.iter_mut().enumerate() {
-                let player_hit =
-                    player.handle_collisions(shot.shot.clone(), i == self.active_player_idx);
-                let map_hit = self.map.handle_collisions(shot.shot.clone());
-                if map_hit {
-                    self.map_hits.push(shot.clone());
-                }
-                if player_hit || map_hit {
-                    shot.is_alive = false;
+    /// Adds the finished match to the profiles of the people who played it
+    /// at this machine, once the simulation played it to the end.
+    fn record_result(&mut self, ctx: &mut Context) {
+        let result = match self.result() {
+            Some(result) if !self.recorded && self.sim.result().is_some() => result,
+            _ => return,
+        };
+        self.recorded = true;
+        let recorded: Vec<usize> = self
+            .sim
+            .settings()
+            .teams
+            .iter()
+            .enumerate()
+            .filter(|(i, team)| {
+                team.control == Control::Human && self.lockstep.as_ref().is_none_or(|lockstep| lockstep.is_local(*i))
+            })
+            .map(|(i, _)| i)
+            .collect();
+        if recorded.is_empty() {
+            return;
+        }
+        let names: Vec<&str> = self.sim.players().iter().map(|p| p.name.as_str()).collect();
+        let mut profiles = Profiles::load(ctx);
+        profiles.record(&names, &self.sim.settings().sides(), self.sim.stats(), result, &recorded);
+        if let Err(e) = profiles.save(ctx) {
+            eprintln!("Failed to save the profiles: {}", e);
+        }
+    }
+
+    /// Why a network match can't go on.
+    fn halted(&self) -> Option<String> {
+        let lockstep = self.lockstep.as_ref()?;
+        if let Some(tick) = lockstep.desync() {
+            return Some(format!("The match went out of sync at tick {}, press Esc to leave", tick));
+        }
+        lockstep
+            .error()
+            .map(|error| format!("{}, press Esc to leave", error))
+    }
+
+    /// Keys of the pause menu and the game-over screen.
+    fn menu_key_down(&mut self, ctx: &mut Context, keycode: event::KeyCode) {
+        if self.pause.is_some() {
+            self.handle_pause_key(ctx, keycode);
+            return;
+        }
+        match keycode {
+            event::KeyCode::R | event::KeyCode::Return => self.restart(ctx),
+            event::KeyCode::Escape => self.next = Some(Transition::Menu),
+            _ => (),
+        }
+    }
+
+    fn quick_save(&mut self, ctx: &mut Context) {
+        let notice = match save::save(ctx, save::QUICK_SAVE, &self.sim) {
+            Ok(()) => String::from("Saved"),
+            Err(e) => format!("Could not save: {}", e),
+        };
+        self.notice = Some((notice, Self::NOTICE_TIME));
+    }
+
+    fn quick_load(&mut self, ctx: &mut Context) {
+        // The other peers would go on with the match they have.
+        if self.lockstep.is_some() {
+            let notice = String::from("Saved matches can't be loaded during a network game");
+            self.notice = Some((notice, Self::NOTICE_TIME));
+            return;
+        }
+        match Game::load(ctx, self.cfg, self.options, self.bindings.clone(), save::QUICK_SAVE) {
+            Ok(game) => {
+                *self = game;
+                self.notice = Some((String::from("Loaded"), Self::NOTICE_TIME));
+            }
+            Err(e) => self.notice = Some((format!("Could not load: {}", e), Self::NOTICE_TIME)),
+        }
+    }
+
+    fn action_down(&mut self, ctx: &mut Context, action: Action) {
+        match action {
+            Action::Pause => return self.pause(),
+            Action::QuickSave => return self.quick_save(ctx),
+            Action::QuickLoad => return self.quick_load(ctx),
+            Action::FineAim => self.input.fine_aim = true,
+            _ => (),
+        }
+        if let Some(camera) = &mut self.camera {
+            match action {
+                Action::MoveLeft => camera.set_direction_x(-1.0),
+                Action::MoveRight => camera.set_direction_x(1.0),
+                Action::AimUp => camera.set_direction_y(-1.0),
+                Action::AimDown => camera.set_direction_y(1.0),
+                _ => (),
+            }
+            return;
+        }
+        if !self.is_local_turn() {
+            return;
+        }
+        if self.input.weapons_menu_open {
+            self.weapons_menu_action(action);
+            return;
+        }
+        match action {
+            Action::MoveLeft => self.input.movement = -1.0,
+            Action::MoveRight => self.input.movement = 1.0,
+            Action::AimUp => self.input.weapon_direction = -1.0,
+            Action::AimDown => self.input.weapon_direction = 1.0,
+            Action::Fire if !self.sim.is_shooting() => self.input.charging = true,
+            Action::Jump => self.input.commands.push(Command::Jump),
+            Action::OpenWeapons => self.input.weapons_menu_open = true,
+            Action::NextCrab if self.sim.can_select_crab() => self.input.commands.push(Command::NextCrab),
+            Action::Surrender => self.input.commands.push(Command::Surrender),
+            _ => (),
+        }
+    }
+
+    fn action_up(&mut self, action: Action) {
+        if let Some(camera) = &mut self.camera {
+            match action {
+                Action::MoveLeft | Action::MoveRight => camera.set_direction_x(0.0),
+                Action::AimUp | Action::AimDown => camera.set_direction_y(0.0),
+                _ => (),
+            }
+        }
+        match action {
+            Action::MoveLeft | Action::MoveRight => self.input.movement = 0.0,
+            Action::AimUp | Action::AimDown => self.input.weapon_direction = 0.0,
+            Action::FineAim => self.input.fine_aim = false,
+            Action::Fire if self.input.charging => {
+                self.input.charging = false;
+                if !self.sim.is_shooting() && self.sim.result().is_none() && self.is_local_turn() {
+                    self.input.commands.push(Command::Fire { power: self.input.power });
                 }
+                self.input.power = 0.0;
             }
+            _ => (),
         }
     }
 
-    fn is_outside(rect: Rect, width: f32, height: f32) -> bool {
-        rect.top() < 0.0 || rect.left() < 0.0 || rect.bottom() > height || rect.right() > width
+    fn weapons_menu_action(&mut self, action: Action) {
+        let count = gui::WEAPONS_COUNT;
+        match action {
+            Action::MoveLeft => {
+                self.input.weapon_cursor = (self.input.weapon_cursor + count - 1) % count;
+            }
+            Action::MoveRight => self.input.weapon_cursor = (self.input.weapon_cursor + 1) % count,
+            Action::Fire | Action::Jump => {
+                let weapon = gui::weapon_at(self.input.weapon_cursor);
+                if self.sim.inventory(self.sim.active_player_idx()).has(weapon) {
+                    self.input.commands.push(Command::SelectWeapon(weapon));
+                    self.input.weapons_menu_open = false;
+                }
+            }
+            Action::OpenWeapons => self.input.weapons_menu_open = false,
+            _ => (),
+        }
     }
 
-    fn new_player(player_cfg: &PlayerConfig, screen_cfg: &Screen) -> Player {
-        let mut rng = rand::thread_rng();
-        let mut crabs: Vec<Crab> = vec![];
+    fn handle_pause_key(&mut self, ctx: &mut Context, keycode: event::KeyCode) {
+        let action = match &mut self.pause {
+            Some(pause) => pause.key_down(keycode),
+            None => return,
+        };
+        match action {
+            None => (),
+            Some(PauseAction::Resume) => self.pause = None,
+            Some(PauseAction::Restart) => self.restart(ctx),
+            Some(PauseAction::QuitToMenu) => self.next = Some(Transition::Menu),
+            Some(PauseAction::Options(options)) => {
+                if options.fullscreen != self.options.fullscreen {
+                    if let Err(e) = options.apply(ctx) {
+                        eprintln!("Failed to change the window mode: {}", e);
+                    }
+                }
+                self.gui.set_scorch(options.scorch);
+                self.options = options;
+            }
+        }
+    }
 
-        for i in 0..player_cfg.crabs_count {
-            let crab = Crab::new(
-                &format!("{}:{}", player_cfg.name, i),
-                graphics::Rect::new(
-                    rng.gen::<f32>() * screen_cfg.width - 1.0,
-                    100.0,
-                    player_cfg.crab.width as f32,
-                    player_cfg.crab.height as f32,
-                ),
-            );
-            crabs.push(crab);
+    /// Where the aimed shot would go, if the match shows it.
+    fn trajectory(&self) -> Option<Trajectory> {
+        if !self.sim.settings().rules.shows_trajectory() || self.sim.is_shooting() || !self.is_local_turn() {
+            return None;
         }
-        Player::new(player_cfg.name, crabs)
+        let crab = self.sim.active_player().current_crab()?;
+        let shot = crab.fire(self.input.power + 1.0).pop()?;
+        let targets = self.sim.settings().targets(self.sim.active_player_idx(), &crab.name);
+        Some(trajectory::predict(self.sim.map(), self.sim.players(), crab, shot, &targets))
     }
-}
 
-impl event::EventHandler for Game {
-    fn update(&mut self, ctx: &mut Context) -> GameResult {
-        if self
-            .players
+    /// The crab of the active team at `x`, `y` on the screen.
+    fn clicked_crab(&self, x: f32, y: f32) -> Option<usize> {
+        let point = Point2::new(x, y);
+        self.sim
+            .active_player()
+            .crabs
             .iter()
-            .any(|player| player.total_health() <= 0.0)
-        {
-            let player = self
-                .players
-                .iter()
-                .find(|p| p.total_health() > 0.0)
-                .unwrap();
-            self.winner = player.name.clone();
-            return Ok(());
-        }
+            .position(|crab| crab.get_rect().contains(point))
+    }
+
+    /// Whether the active team is played by a person at this machine.
+    fn is_local_turn(&self) -> bool {
+        let team = self.sim.active_player_idx();
+        self.sim.is_human_turn()
+            && match &self.lockstep {
+                Some(lockstep) => lockstep.is_local(team),
+                None => true,
+            }
+    }
 
-        const FPS: u32 = 30;
-        let seconds = 1.0 / (FPS as f32);
-        if ggez::input::keyboard::is_key_pressed(ctx, event::KeyCode::Space) {
-            self.input.power = ggez::nalgebra::clamp(self.input.power + seconds / self.cfg.shots.power.time, self.cfg.shots.power.min, self.cfg.shots.power.max);
+    /// What the active team does during the next tick, `None` while the
+    /// input of another peer is on its way.
+    fn next_input(&mut self) -> Option<TickInput> {
+        if !self.is_local_turn() {
+            self.input.commands.clear();
+        }
+        if !self.sim.is_human_turn() {
+            return Some(TickInput::default());
         }
+        let tick = self.sim.tick() + 1;
+        let local = self.is_local_turn();
+        let input = self.local_input();
+        match &mut self.lockstep {
+            None => Some(input),
+            Some(lockstep) if local => {
+                lockstep.send_input(tick, &input);
+                Some(input)
+            }
+            Some(lockstep) => lockstep.take_input(tick),
+        }
+    }
 
-        while timer::check_update_time(ctx, FPS) {
-            self.players[self.active_player_idx].update_crab(
-                Vector2::new(self.input.movement, 0.0),
-                seconds,
-                &self.map,
-            );
+    /// What the local player does this tick.
+    fn local_input(&mut self) -> TickInput {
+        TickInput {
+            movement: self.input.movement,
+            aim: self.input.weapon_direction,
+            fine_aim: self.input.fine_aim,
+            commands: std::mem::take(&mut self.input.commands),
+        }
+    }
 
-            self.players[self.active_player_idx]
-                .set_weapon_direction(self.input.weapon_direction * seconds);
+    /// Against an authoritative server the local input goes out every tick
+    /// and only the ticks the server sent back are simulated.
+    fn follow_server(&mut self) {
+        let ticks = std::mem::take(&mut self.pending_ticks);
+        if !self.is_local_turn() {
+            self.input.commands.clear();
+        }
+        for _ in 0..ticks {
+            if !self.is_local_turn() {
+                break;
+            }
+            let tick = self.sim.tick() + 1;
+            let input = self.local_input();
+            if let Some(lockstep) = &mut self.lockstep {
+                lockstep.send_input(tick, &input);
+            }
+        }
+        let mut stepped = false;
+        for _ in 0..Self::MAX_SERVER_TICKS {
+            let tick = self.sim.tick() + 1;
+            match self.lockstep.as_mut().and_then(|lockstep| lockstep.take_input(tick)) {
+                Some(input) => self.step(&input),
+                None => break,
+            }
+            stepped = true;
+        }
+        if stepped {
+            self.waiting = 0;
+        } else {
+            self.waiting += ticks;
+        }
+    }
 
-            for shot in self.shots.iter_mut() {
-                shot.update(seconds);
+    /// Simulates a tick and passes on what happened to the GUI and the audio.
+    fn step(&mut self, input: &TickInput) {
+        self.sim.step(input);
+        if let Some(lockstep) = &mut self.lockstep {
+            let tick = self.sim.tick();
+            if lockstep.wants_hash(tick) {
+                lockstep.record_hash(tick, self.sim.state_hash());
+            }
+            if lockstep.wants_state() {
+                match self.sim.snapshot() {
+                    Ok(state) => lockstep.send_state(self.sim.settings(), &state),
+                    Err(e) => eprintln!("Could not send the match to spectators: {}", e),
+                }
             }
+        }
+        let events = self.sim.take_events();
+        for name in events.fired.iter() {
+            self.gui.crab_fired(name);
+        }
+        self.gui.add_damage(&events.damage);
+        let crabs: Vec<(&str, &Crab)> = self
+            .sim
+            .players()
+            .iter()
+            .flat_map(|player| player.crabs.iter().map(move |crab| (player.name.as_str(), crab)))
+            .collect();
+        self.gui.update(Simulation::TICK_SECONDS, &crabs);
+        self.gui.add_effects(&events.effects);
+        self.sounds.extend(events.sounds);
+    }
+}
 
-            if self.shooting_in_progress && self.shots.len() == 0 {
-                self.switch_turn();
-                self.shooting_in_progress = false;
+impl event::EventHandler for Game {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if let Some(lockstep) = &mut self.lockstep {
+            lockstep.poll();
+        }
+        let delta = timer::delta(ctx).as_secs_f32();
+        if let Some(camera) = &mut self.camera {
+            camera.update(delta);
+        }
+        if let Some((_, left)) = &mut self.notice {
+            *left -= delta;
+            if *left <= 0.0 {
+                self.notice = None;
             }
+        }
+        if self.sim.result().is_some() || self.halted().is_some() {
+            self.record_result(ctx);
+            return Ok(());
+        }
 
-            self.handle_collisions();
+        let seconds = Simulation::TICK_SECONDS;
+        if self.pause.is_some() && self.lockstep.is_none() {
+            // Drop the paused time so the match doesn't catch up on resume.
+            while timer::check_update_time(ctx, Simulation::FPS) {}
+            return Ok(());
+        }
+        if self.input.charging && self.is_local_turn() {
+            let power = &self.cfg.shots.power;
+            self.input.power = (self.input.power + seconds / power.time).clamp(power.min, power.max);
+        }
 
-            let width = self.cfg.screen.width;
-            let height = self.cfg.screen.height;
-            self.shots
-                .retain(|shot| !Self::is_outside(shot.get_rect(), width, height) && shot.is_alive);
+        while timer::check_update_time(ctx, Simulation::FPS) {
+            self.pending_ticks += 1;
+        }
+        if self.lockstep.as_ref().is_some_and(Lockstep::is_authoritative) {
+            self.follow_server();
+            return Ok(());
+        }
+        // Catch up with the other peers when their inputs pile up.
+        if let Some(lockstep) = &self.lockstep {
+            if lockstep.queued() > Simulation::FPS as usize / 2 {
+                self.pending_ticks += 1;
+            }
+        }
+        while self.pending_ticks > 0 && self.sim.result().is_none() {
+            match self.next_input() {
+                Some(input) => {
+                    self.step(&input);
+                    self.waiting = 0;
+                }
+                None => {
+                    // The time spent waiting is not made up for.
+                    self.waiting += self.pending_ticks;
+                    self.pending_ticks = 0;
+                    break;
+                }
+            }
+            self.pending_ticks -= 1;
         }
 
         Ok(())
@@ -216,30 +672,106 @@ impl event::EventHandler for Game {
         graphics::clear(ctx, [0.0, 0.0, 0.0, 0.0].into());
         let power = self.input.power;
 
-        self.gui.draw_map(
-            ctx,
-            graphics::Rect::new(0.0, 0.0, self.cfg.screen.width, self.cfg.screen.height),
-        )?;
-        self.gui.draw_map_hits(ctx, &self.map_hits)?;
+        let screen = graphics::Rect::new(0.0, 0.0, self.cfg.screen.width, self.cfg.screen.height);
+        // Spectators draw the part of the map they look at, the HUD stays put.
+        if let Some(camera) = &self.camera {
+            graphics::set_screen_coordinates(ctx, camera.view())?;
+        }
 
-        for player in self.players.iter() {
+        let edits = self.sim.take_map_edits();
+        self.gui.update_map(ctx, self.sim.map(), &edits)?;
+        self.gui.draw_map(ctx, screen)?;
+
+        for gravestone in self.sim.gravestones().iter() {
+            self.gui.draw_gravestone(ctx, gravestone)?;
+        }
+        for supply in self.sim.crates().iter() {
+            self.gui.draw_supply_crate(ctx, supply)?;
+        }
+        for player in self.sim.players().iter() {
             for crab in player.crabs.iter() {
-                self.gui.draw_crab(ctx, &player.name, crab, self.is_crab_active(&crab.name), power)?;
+                self.gui.draw_crab(ctx, &player.name, crab, self.sim.is_crab_active(&crab.name), power)?;
                 // let rect = crab.get_rect();
                 // self.gui.draw_rect(ctx, rect)?;
             }
         }
 
-        for shot in self.shots.iter() {
-            self.gui.draw_shot(ctx, &shot.shot)?;
+        for shot in self.sim.shots().iter() {
+            self.gui.draw_shot(ctx, shot.shot())?;
+        }
+        self.gui.draw_dying(ctx)?;
+        self.gui.draw_particles(ctx)?;
+        self.gui.draw_popups(ctx)?;
+
+        if let Some(trajectory) = self.trajectory() {
+            self.gui.draw_trajectory(ctx, &trajectory)?;
+        }
+
+        if let Some(level) = self.sim.map().get_water_level() {
+            self.gui.draw_water(
+                ctx,
+                graphics::Rect::new(0.0, level, self.cfg.screen.width, self.cfg.screen.height - level),
+            )?;
+        }
+
+        if !self.sim.is_shooting()
+            && self.is_local_turn()
+            && self.sim.active_player().weapon_kind() == Some(WeaponType::Girder)
+        {
+            let girder = self.girder();
+            let valid = self.sim.can_place_girder(&girder);
+            self.gui.draw_girder(ctx, &girder, valid)?;
+        }
+        graphics::set_screen_coordinates(ctx, screen)?;
+
+        if self.sim.result().is_none() {
+            self.gui.draw_hud(ctx, &self.hud())?;
+            if self.sim.shows_summary() {
+                let totals: Vec<(&str, f32)> = self
+                    .sim
+                    .players()
+                    .iter()
+                    .zip(self.sim.turn_damage().iter())
+                    .filter(|&(_, &damage)| damage > 0.0)
+                    .map(|(player, &damage)| (player.name.as_str(), damage))
+                    .collect();
+                self.gui.draw_turn_summary(ctx, &totals)?;
+            }
         }
 
         if self.input.weapons_menu_open {
-            self.gui.draw_weapons_menu(ctx)?;
+            self.gui.draw_weapons_menu(ctx, self.sim.inventory(self.sim.active_player_idx()), self.input.weapon_cursor)?;
+        }
+
+        if let Some(result) = self.result() {
+            let names: Vec<&str> = self.sim.players().iter().map(|p| p.name.as_str()).collect();
+            let teams: Vec<gui::TeamSummary> = self
+                .sim
+                .players()
+                .iter()
+                .zip(self.sim.stats().iter())
+                .map(|(player, stats)| gui::TeamSummary {
+                    name: &player.name,
+                    stats,
+                    crabs_left: player.crabs.len(),
+                })
+                .collect();
+            let title = result.describe(&names, &self.sim.settings().sides());
+            self.gui.draw_game_over(ctx, &title, &teams)?;
+        }
+
+        if let Some(notice) = self.halted() {
+            self.gui.draw_notice(ctx, &notice)?;
+        } else if self.waiting > Self::WAITING_NOTICE_TICKS {
+            let team = &self.sim.active_player().name;
+            self.gui.draw_notice(ctx, &format!("Waiting for {}...", team))?;
+        } else if let Some((notice, _)) = &self.notice {
+            self.gui.draw_notice(ctx, notice)?;
         }
 
-        if self.winner.len() > 0 {
-            self.gui.draw_winner(ctx, &self.winner)?;
+        if let Some(pause) = &self.pause {
+            self.gui.draw_shade(ctx, screen)?;
+            pause.draw(ctx)?;
         }
 
         graphics::present(ctx)?;
@@ -253,13 +785,10 @@ impl event::EventHandler for Game {
         _keymods: event::KeyMods,
         _repeat: bool,
     ) {
-        match keycode {
-            event::KeyCode::Left => self.input.movement = -1.0,
-            event::KeyCode::Right => self.input.movement = 1.0,
-            event::KeyCode::Up => self.input.weapon_direction = -1.0,
-            event::KeyCode::Down => self.input.weapon_direction = 1.0,
-            event::KeyCode::Escape => event::quit(ctx),
-            _ => (),
+        if self.pause.is_some() || self.sim.result().is_some() || self.halted().is_some() {
+            self.menu_key_down(ctx, keycode);
+        } else if let Some(action) = self.bindings.key_action(keycode) {
+            self.action_down(ctx, action);
         }
     }
 
@@ -269,59 +798,89 @@ impl event::EventHandler for Game {
         keycode: event::KeyCode,
         _keymods: event::KeyMods,
     ) {
-        match keycode {
-            event::KeyCode::Left | event::KeyCode::Right => self.input.movement = 0.0,
-            event::KeyCode::Up | event::KeyCode::Down => self.input.weapon_direction = 0.0,
-            event::KeyCode::Space => {
-                if !self.shooting_in_progress {
-                    let power = self.input.power;
-                    let shots = self.active_player().fire(power + 1.0);
-                    self.input.power = 0.0;
-                    self.spawn_shots(shots);
-                }
+        if let Some(action) = self.bindings.key_action(keycode) {
+            self.action_up(action);
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, button: Button, _id: GamepadId) {
+        let action = match self.bindings.button_action(button) {
+            Some(action) => action,
+            None => return,
+        };
+        if self.pause.is_some() || self.sim.result().is_some() || self.halted().is_some() {
+            if let Some(keycode) = action.menu_key() {
+                self.menu_key_down(ctx, keycode);
             }
+        } else {
+            self.action_down(ctx, action);
+        }
+    }
+
+    fn gamepad_button_up_event(&mut self, _ctx: &mut Context, button: Button, _id: GamepadId) {
+        if let Some(action) = self.bindings.button_action(button) {
+            self.action_up(action);
+        }
+    }
+
+    fn gamepad_axis_event(&mut self, _ctx: &mut Context, axis: Axis, value: f32, _id: GamepadId) {
+        if self.pause.is_some() || self.sim.result().is_some() || !self.is_local_turn() {
+            return;
+        }
+        let value = if value.abs() < Self::STICK_DEAD_ZONE { 0.0 } else { value };
+        match axis {
+            Axis::LeftStickX => self.input.movement = value,
+            // The stick points up for positive values, aiming up turns the weapon back.
+            Axis::LeftStickY => self.input.weapon_direction = -value,
             _ => (),
         }
     }
 
     fn mouse_button_down_event(&mut self, _ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if self.pause.is_some() || self.sim.result().is_some() || !self.is_local_turn() {
+            return;
+        }
         match button {
             MouseButton::Right => {
                 self.input.weapons_menu_open = !self.input.weapons_menu_open;
             }
-            MouseButton::Left => {
-                if self.input.weapons_menu_open {
-                    match self.gui.is_weapon_activated(x, y) {
-                        None => (),
-                        Some(weapon) => self.active_player().set_weapon(weapon),
+            MouseButton::Left if self.input.weapons_menu_open => {
+                match self.gui.is_weapon_activated(x, y) {
+                    Some(weapon) if self.sim.inventory(self.sim.active_player_idx()).has(weapon) => {
+                        self.input.commands.push(Command::SelectWeapon(weapon))
                     }
+                    _ => (),
+                }
+            }
+            MouseButton::Left if self.sim.can_select_crab() && self.clicked_crab(x, y).is_some() => {
+                if let Some(idx) = self.clicked_crab(x, y) {
+                    self.input.commands.push(Command::SelectCrab(idx));
                 }
             }
+            MouseButton::Left
+                if !self.sim.is_shooting()
+                    && self.sim.active_player().weapon_kind() == Some(WeaponType::Girder) =>
+            {
+                self.input.cursor = (x, y);
+                self.input.commands.push(Command::PlaceGirder { x, y });
+            }
             _ => (),
         }
     }
-}
 
-#[derive(Clone)]
-pub struct GameShot {
-    shot:  Shot,
-    is_alive: bool,
-}
-
-impl GameShot {
-    pub fn get_kind(&self) -> ShotType {
-        self.shot.get_kind()
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        self.input.cursor = (x, y);
     }
 
-    pub fn update(&mut self, seconds: f32) {
-        self.shot.update(seconds)
-    }
-
-    pub fn damage(&self) -> f32 {
-        self.shot.damage()
-    }
-
-    pub fn get_rect(&self) -> Rect {
-        self.shot.get_rect()
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) {
+        if let Some(camera) = &mut self.camera {
+            camera.zoom(y);
+        } else if self.pause.is_none()
+            && self.sim.result().is_none()
+            && self.is_local_turn()
+            && self.sim.active_player().weapon_kind() == Some(WeaponType::Girder)
+        {
+            self.input.commands.push(Command::RotateAim(y * Self::GIRDER_ROTATION_STEP));
+        }
     }
 }
@@ -0,0 +1,32 @@
+use crate::body::Body;
+use crate::map::Map;
+use ggez::graphics::Rect;
+use serde::{Deserialize, Serialize};
+
+/// What a dead crab leaves behind.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Gravestone {
+    /// The team of the crab.
+    pub team: usize,
+    #[serde(flatten)]
+    pub body: Body,
+}
+
+impl Gravestone {
+    pub const WIDTH: f32 = 14.0;
+    pub const HEIGHT: f32 = 18.0;
+
+    /// A gravestone standing where the crab of `team` in `crab` was.
+    pub fn new(team: usize, crab: Rect, map: &Map) -> Self {
+        let rect = Rect::new(
+            crab.x + (crab.w - Self::WIDTH) / 2.0,
+            crab.bottom() - Self::HEIGHT,
+            Self::WIDTH,
+            Self::HEIGHT,
+        );
+        Gravestone {
+            team,
+            body: Body::new(rect, map),
+        }
+    }
+}
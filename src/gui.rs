@@ -1,6 +1,17 @@
+use crate::animation::{self, CrabAnimation, CrabState};
+use crate::config::{AnimationConfig, SheetConfig};
 use crate::crab::Crab;
-use crate::game::{GameShot};
+use crate::effects::{Effect, Particles};
+use crate::gravestone::Gravestone;
+use crate::supply::SupplyCrate;
+use crate::status::StatusKind;
+use crate::map::{Map, TerrainEdit};
+use crate::inventory::Inventory;
+use crate::stats::{DamageEvent, TeamStats};
 use crate::shot::{Shot, ShotType};
+use crate::texture::MapTexture;
+use crate::tool::Girder;
+use crate::trajectory::Trajectory;
 use crate::weapon::WeaponType;
 use ggez::graphics::{self, DrawParam, Rect, Text};
 use ggez::nalgebra::{Point2, Vector2};
@@ -8,12 +19,21 @@ use ggez::{Context, GameResult};
 use std::collections::HashMap;
 
 pub struct GUI {
-    map: graphics::Image,
-    players: HashMap<&'static str, Player>,
+    map: MapTexture,
+    background: Option<graphics::Image>,
+    players: HashMap<String, Player>,
     weapons: WeaponsMenu,
     shots: ShotImages,
     aim: ImageSettings,
     arrow: ImageSettings,
+    /// Health shown above each crab, catching up with the real one.
+    shown_health: HashMap<String, f32>,
+    /// Animations of the crabs by name, with their team.
+    animations: HashMap<String, (String, CrabAnimation)>,
+    /// Crabs that are gone but still playing their death, with their team.
+    dying: Vec<(String, CrabAnimation)>,
+    popups: Vec<Popup>,
+    particles: Particles,
 }
 
 pub struct Config {
@@ -21,8 +41,17 @@ pub struct Config {
     pub players: Vec<PlayerConfig>,
 }
 
+/// RGBA pixels of the map foreground.
+pub struct MapImage {
+    pub pixels: Vec<u8>,
+    pub width: u16,
+    pub height: u16,
+}
+
 pub struct ImagesConfig {
-    pub map: &'static str,
+    pub map: MapImage,
+    pub background: Option<String>,
+    pub scorch: bool,
     pub weapons: &'static str,
     pub shots: ShotsConfig,
     pub aim: ImageConfig,
@@ -40,45 +69,167 @@ pub struct ShotsConfig {
     pub bazooka: &'static str,
 }
 
+pub struct TeamSummary<'a> {
+    pub name: &'a str,
+    pub stats: &'a TeamStats,
+    pub crabs_left: usize,
+}
+
+/// What the HUD shows about the match.
+pub struct Hud<'a> {
+    pub teams: Vec<HudTeam<'a>>,
+    pub active: usize,
+    pub next: usize,
+    pub weapon: Option<WeaponType>,
+    /// Shots left of the weapon, `None` when they are unlimited.
+    pub ammo: Option<u32>,
+    /// Seconds left in the turn, when turns are timed.
+    pub time_left: Option<f32>,
+    /// Set while the team can pick its crab, with the turns left it can do
+    /// so on when they are limited.
+    pub crab_choice: Option<Option<u32>>,
+}
+
+pub struct HudTeam<'a> {
+    pub name: &'a str,
+    pub health: f32,
+    /// The health the team started the match with.
+    pub max_health: f32,
+    pub crabs: usize,
+    /// Shots left of the weapons with limited ammo, shown to spectators.
+    pub ammo: Vec<(WeaponType, u32)>,
+}
+
 pub struct PlayerConfig {
-    pub name: &'static str,
+    pub name: String,
     pub crab_image: &'static str,
     pub crab_firing_image: &'static str,
+    pub sheet: Option<&'static SheetConfig>,
 }
 
 impl GUI {
     const AIM_DISTANCE: f32 = 50.0;
     const ARROW_DISTANCE: f32 = 20.0;
     const HEALTH_DISTANCE: f32 = 20.0;
+    const AIM_ANGLE_DISTANCE: f32 = 18.0;
+    const TRAJECTORY_DOT_STEP: usize = 2;
+    const TRAJECTORY_DOT_RADIUS: f32 = 2.0;
     const WEAPONS_IMAGE_DISTANCE: f32 = 10.0;
-    const WINNER_BANNER_DISTANCE: f32 = 30.0;
+    const HEALTH_COUNT_SPEED: f32 = 60.0;
+    const HURT_COLOR: graphics::Color = graphics::Color {
+        r: 1.0,
+        g: 0.4,
+        b: 0.4,
+        a: 1.0,
+    };
+    const POPUP_TIME: f32 = 1.5;
+    const POPUP_RISE: f32 = 40.0;
+    const POPUP_COLOR: graphics::Color = graphics::Color {
+        r: 1.0,
+        g: 0.3,
+        b: 0.2,
+        a: 1.0,
+    };
+    const HUD_PADDING: f32 = 10.0;
+    const HUD_LINE: f32 = 22.0;
+    const HUD_NAME_WIDTH: f32 = 110.0;
+    const HUD_BAR_WIDTH: f32 = 120.0;
+    const HUD_BAR_HEIGHT: f32 = 12.0;
+    const TEAM_COLORS: [graphics::Color; 4] = [
+        graphics::Color { r: 0.9, g: 0.25, b: 0.2, a: 1.0 },
+        graphics::Color { r: 0.2, g: 0.5, b: 0.95, a: 1.0 },
+        graphics::Color { r: 0.3, g: 0.8, b: 0.3, a: 1.0 },
+        graphics::Color { r: 0.95, g: 0.8, b: 0.2, a: 1.0 },
+    ];
+    const GAME_OVER_WIDTH: f32 = 600.0;
+    const GAME_OVER_LINE: f32 = 24.0;
+    const GAME_OVER_WINDOW_PADDING: f32 = 12.0;
     const POWER_DISTANCE: f32 = 10.0;
     const POWER_HEIGHT: f32 = 5.0;
     const WEAPONS_IMAGE_WIDTH: f32 = 32.0;
     const WEAPONS_IMAGE_HEIGHT: f32 = 32.0;
+    const WATER_COLOR: graphics::Color = graphics::Color {
+        r: 0.1,
+        g: 0.3,
+        b: 0.8,
+        a: 0.6,
+    };
+    const SKY_COLOR: graphics::Color = graphics::Color {
+        r: 0.45,
+        g: 0.7,
+        b: 0.9,
+        a: 1.0,
+    };
+    const GRAVESTONE_COLOR: graphics::Color = graphics::Color {
+        r: 0.55,
+        g: 0.55,
+        b: 0.6,
+        a: 1.0,
+    };
+    const CRATE_COLOR: graphics::Color = graphics::Color {
+        r: 0.65,
+        g: 0.45,
+        b: 0.2,
+        a: 1.0,
+    };
+    const CRATE_EDGE_COLOR: graphics::Color = graphics::Color {
+        r: 0.35,
+        g: 0.2,
+        b: 0.05,
+        a: 1.0,
+    };
+    const STATUS_ICON_SIZE: f32 = 16.0;
 
     pub fn new(ctx: &mut Context, cfg: Config) -> GameResult<Self> {
-        let map = graphics::Image::new(ctx, &cfg.images.map)?;
-        let (map_width, map_height) = (map.width() as f32, map.height() as f32);
-        let weapons = graphics::Image::new(ctx, &cfg.images.weapons)?;
+        let (map_width, map_height) = (cfg.images.map.width, cfg.images.map.height);
+        let map = MapTexture::new(
+            ctx,
+            cfg.images.map.pixels,
+            map_width,
+            map_height,
+            cfg.images.scorch,
+        )?;
+        let (map_width, map_height) = (map_width as f32, map_height as f32);
+        let background = match &cfg.images.background {
+            None => None,
+            Some(background) => Some(graphics::Image::new(ctx, background)?),
+        };
+        let weapons = graphics::Image::new(ctx, cfg.images.weapons)?;
         let mut players = HashMap::new();
-        for player_cfg in cfg.players.iter() {
-            let crab_image = graphics::Image::new(ctx, &player_cfg.crab_image)?;
-            let crab_firing_image = graphics::Image::new(ctx, &player_cfg.crab_firing_image)?;
+        for player_cfg in cfg.players.into_iter() {
+            let crab_image = graphics::Image::new(ctx, player_cfg.crab_image)?;
+            let crab_firing_image = graphics::Image::new(ctx, player_cfg.crab_firing_image)?;
+            let sheet = match player_cfg.sheet {
+                None => None,
+                Some(sheet) => Some(Sheet {
+                    image: graphics::Image::new(ctx, sheet.image)?,
+                    frame_width: sheet.frame_width as f32,
+                    frame_height: sheet.frame_height as f32,
+                    animations: sheet
+                        .animations
+                        .iter()
+                        .filter_map(|animation| {
+                            CrabState::from_name(animation.state).map(|state| (state, *animation))
+                        })
+                        .collect(),
+                }),
+            };
             players.insert(
-                player_cfg.name.clone(),
+                player_cfg.name,
                 Player {
                     crab_image,
                     crab_firing_image,
+                    sheet,
                 },
             );
         }
-        let pistol = graphics::Image::new(ctx, &cfg.images.shots.pistol)?;
-        let bazooka = graphics::Image::new(ctx, &cfg.images.shots.bazooka)?;
-        let aim = graphics::Image::new(ctx, &cfg.images.aim.image)?;
-        let arrow = graphics::Image::new(ctx, &cfg.images.arrow.image)?;
+        let pistol = graphics::Image::new(ctx, cfg.images.shots.pistol)?;
+        let bazooka = graphics::Image::new(ctx, cfg.images.shots.bazooka)?;
+        let aim = graphics::Image::new(ctx, cfg.images.aim.image)?;
+        let arrow = graphics::Image::new(ctx, cfg.images.arrow.image)?;
         Ok(GUI {
             map,
+            background,
             players,
             weapons: WeaponsMenu {
                 image: weapons,
@@ -100,64 +251,412 @@ impl GUI {
                 width: cfg.images.arrow.width,
                 height: cfg.images.arrow.height,
             },
+            shown_health: HashMap::new(),
+            animations: HashMap::new(),
+            dying: vec![],
+            popups: vec![],
+            particles: Particles::default(),
         })
     }
 
+    /// Shows a floating number for every hit.
+    pub fn add_damage(&mut self, damage: &[DamageEvent]) {
+        for event in damage {
+            self.popups.push(Popup {
+                text: format!("-{}", event.amount.round()),
+                pos: Point2::new(event.pos.x, event.pos.y - Self::HEALTH_DISTANCE),
+                age: 0.0,
+            });
+        }
+    }
+
+    pub fn add_effects(&mut self, effects: &[Effect]) {
+        let (pixels, width, height) = (self.map.pixels(), self.map.width(), self.map.height());
+        let ground = |p: Point2<f32>| {
+            if p.x < 0.0 || p.y < 0.0 || p.x >= width as f32 || p.y >= height as f32 {
+                return None;
+            }
+            let i = (p.y as usize * width as usize + p.x as usize) * 4;
+            match pixels[i..i + 4] {
+                [_, _, _, 0] => None,
+                [r, g, b, _] => Some(graphics::Color::from_rgb(r, g, b)),
+                _ => None,
+            }
+        };
+        for effect in effects {
+            self.particles.emit(effect, ground);
+        }
+    }
+
+    /// Moves the particles, floating numbers and crab animations and counts
+    /// the health labels down towards the health of `crabs`, given with
+    /// their team names.
+    pub fn update(&mut self, seconds: f32, crabs: &[(&str, &Crab)]) {
+        self.particles.update(seconds);
+        for popup in self.popups.iter_mut() {
+            popup.age += seconds;
+        }
+        self.popups.retain(|popup| popup.age < Self::POPUP_TIME);
+
+        for (_, animation) in self.dying.iter_mut() {
+            animation.update_dead(seconds);
+        }
+        self.dying.retain(|(_, animation)| !animation.is_over());
+        let gone: Vec<String> = self
+            .animations
+            .keys()
+            .filter(|name| !crabs.iter().any(|(_, crab)| &crab.name == *name))
+            .cloned()
+            .collect();
+        for name in gone {
+            if let Some((team, mut animation)) = self.animations.remove(&name) {
+                animation.die();
+                self.dying.push((team, animation));
+            }
+        }
+        for (team, crab) in crabs {
+            self.animations
+                .entry(crab.name.clone())
+                .or_insert_with(|| ((*team).to_owned(), CrabAnimation::new(crab)))
+                .1
+                .update(crab, seconds);
+        }
+
+        self.shown_health.retain(|name, _| crabs.iter().any(|(_, crab)| &crab.name == name));
+        for (_, crab) in crabs {
+            let health = crab.get_health().max(0.0);
+            let shown = self.shown_health.entry(crab.name.clone()).or_insert(health);
+            *shown = if *shown > health {
+                (*shown - Self::HEALTH_COUNT_SPEED * seconds).max(health)
+            } else {
+                health
+            };
+        }
+    }
+
+    pub fn draw_particles(&self, ctx: &mut Context) -> GameResult {
+        if self.particles.is_empty() {
+            return Ok(());
+        }
+        let mut mesh = graphics::MeshBuilder::new();
+        for particle in self.particles.iter() {
+            mesh.circle(graphics::DrawMode::fill(), particle.pos, particle.size.max(0.5), 0.5, particle.color());
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())
+    }
+
+    pub fn draw_popups(&self, ctx: &mut Context) -> GameResult {
+        for popup in self.popups.iter() {
+            let progress = popup.age / Self::POPUP_TIME;
+            let mut color = Self::POPUP_COLOR;
+            color.a = 1.0 - progress;
+            let mut text = Text::new(popup.text.as_str());
+            text.set_font(graphics::Font::default(), graphics::Scale::uniform(Self::HUD_LINE));
+            let dest = Point2::new(
+                popup.pos.x - text.width(ctx) as f32 / 2.0,
+                popup.pos.y - Self::POPUP_RISE * progress,
+            );
+            graphics::draw(ctx, &text, DrawParam::default().dest(dest).color(color))?;
+        }
+        Ok(())
+    }
+
+    /// The damage every team took during the turn that just ended.
+    pub fn draw_turn_summary(&self, ctx: &mut Context, teams: &[(&str, f32)]) -> GameResult {
+        let width = self.map.width() as f32;
+        let panel = Rect::new(
+            (width - Self::HUD_BAR_WIDTH * 2.0) / 2.0,
+            Self::HUD_PADDING,
+            Self::HUD_BAR_WIDTH * 2.0,
+            Self::HUD_LINE * (teams.len() as f32 + 1.0) + Self::HUD_PADDING,
+        );
+        let background = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            panel,
+            graphics::Color::new(0.0, 0.0, 0.0, 0.6),
+        )?;
+        graphics::draw(ctx, &background, DrawParam::default())?;
+        let lines = std::iter::once(String::from("Damage this turn"))
+            .chain(teams.iter().map(|(name, damage)| format!("{}: -{}", name, damage.round())));
+        for (i, line) in lines.enumerate() {
+            graphics::draw(
+                ctx,
+                &Text::new(line),
+                DrawParam::default().dest(Point2::new(
+                    panel.x + Self::HUD_PADDING,
+                    panel.y + Self::HUD_PADDING / 2.0 + i as f32 * Self::HUD_LINE,
+                )),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// A line in the middle of the screen, e.g. while waiting for another player.
+    pub fn draw_notice(&self, ctx: &mut Context, notice: &str) -> GameResult {
+        let (width, height) = (self.map.width() as f32, self.map.height() as f32);
+        let text = Text::new(notice);
+        let (text_width, text_height) = text.dimensions(ctx);
+        let panel = Rect::new(
+            (width - text_width as f32) / 2.0 - Self::HUD_PADDING,
+            height / 3.0,
+            text_width as f32 + 2.0 * Self::HUD_PADDING,
+            text_height as f32 + Self::HUD_PADDING,
+        );
+        self.draw_shade(ctx, panel)?;
+        graphics::draw(
+            ctx,
+            &text,
+            DrawParam::default().dest(Point2::new(
+                panel.x + Self::HUD_PADDING,
+                panel.y + Self::HUD_PADDING / 2.0,
+            )),
+        )
+    }
+
     pub fn draw_map(&self, ctx: &mut Context, rect: Rect) -> GameResult {
-        let pos = Point2::new(rect.x, rect.y);
-        let scale = Vector2::new(rect.w/self.map.width() as f32, rect.h/self.map.height() as f32);
-        graphics::draw(ctx, &self.map, DrawParam::default().dest(pos).scale(scale))
+        self.draw_background(ctx, rect)?;
+        self.map.draw(ctx, rect)
+    }
+
+    fn draw_background(&self, ctx: &mut Context, rect: Rect) -> GameResult {
+        match &self.background {
+            None => {
+                let sky = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    rect,
+                    Self::SKY_COLOR,
+                )?;
+                graphics::draw(ctx, &sky, DrawParam::default())
+            }
+            Some(image) => {
+                let scale = Vector2::new(
+                    rect.w / image.width() as f32,
+                    rect.h / image.height() as f32,
+                );
+                graphics::draw(ctx, image, DrawParam::default().dest(rect.point()).scale(scale))
+            }
+        }
+    }
+
+    pub fn set_scorch(&mut self, scorch: bool) {
+        self.map.set_scorch(scorch)
     }
 
-    pub fn get_map(&self) -> &graphics::Image {
-        &self.map
+    /// Brings the map texture in line with the mask after `edits`.
+    pub fn update_map(&mut self, ctx: &mut Context, map: &Map, edits: &[TerrainEdit]) -> GameResult {
+        self.map.apply(edits);
+        self.map.refresh(ctx, map)
+    }
+
+    /// Redraws the whole map, for a map that changed without edits.
+    pub fn sync_map(&mut self, map: &Map) {
+        self.map.sync(map);
+    }
+
+    pub fn get_map_data(&self) -> (&[u8], u16, u16) {
+        (self.map.pixels(), self.map.width(), self.map.height())
     }
 
     pub fn draw_crab(&self, ctx: &mut Context, player_name: &str, crab: &Crab, is_active: bool, power: f32) -> GameResult {
         let player = self.players.get(player_name).unwrap();
         let crab_rect = crab.get_rect();
-        let scale = Vector2::new(
-            crab_rect.w / player.crab_image.width() as f32,
-            crab_rect.h / player.crab_image.height() as f32,
-        );
         let rect = Rect::new(
             crab_rect.x,
             crab_rect.y,
             self.weapons.rect.w,
             self.weapons.rect.h,
         );
-        match &crab.weapon {
-            None => graphics::draw(
-                ctx,
-                &player.crab_image,
-                DrawParam::default().dest(rect.point()).scale(scale),
-            ),
-            Some(weapon) => {
-                graphics::draw(
-                    ctx,
-                    &player.crab_firing_image,
-                    DrawParam::default().dest(rect.point()).scale(scale),
-                )?;
-                self.draw_weapon(ctx, weapon.kind(), rect)?;
-                if is_active && (weapon.kind() == WeaponType::Pistol || weapon.kind() == WeaponType::Bazooka) {
-                    let d = weapon.direction().scale(Self::AIM_DISTANCE);
-                    let aim_dest = Point2::new(rect.x + d.x, rect.y + d.y);
-                    self.draw_aim(ctx, aim_dest)?;
-                    if weapon.kind() == WeaponType::Bazooka {
-                        self.draw_power(ctx, rect, power)?;
-                    }
+        let (state, time) = match self.animations.get(&crab.name) {
+            Some((_, animation)) => (animation.state(), animation.time()),
+            None => (CrabState::Idle, 0.0),
+        };
+        self.draw_sprite(ctx, player, state, time, crab_rect, crab.facing(), crab.has_weapon())?;
+        if crab.is_dying() {
+            return Ok(());
+        }
+        if let Some(weapon) = &crab.weapon {
+            self.draw_weapon(ctx, weapon.kind(), rect)?;
+            if is_active && !weapon.kind().is_tool() && weapon.kind() != WeaponType::Skip {
+                let d = weapon.direction().scale(Self::AIM_DISTANCE);
+                let aim_dest = Point2::new(rect.x + d.x, rect.y + d.y);
+                self.draw_aim(ctx, aim_dest)?;
+                self.draw_aim_angle(ctx, crab)?;
+                if weapon.kind() == WeaponType::Bazooka {
+                    self.draw_power(ctx, rect, power)?;
                 }
-                Ok(())
             }
-        }?;
+        }
         if is_active {
             self.draw_arrow(ctx, Point2::new(crab_rect.x + crab_rect.w / 2.0, crab_rect.top() - Self::ARROW_DISTANCE))?;
         }
+        self.draw_statuses(ctx, crab)?;
         self.draw_health(ctx, crab)
     }
 
+    /// The crabs that are gone, until their death is over.
+    pub fn draw_dying(&self, ctx: &mut Context) -> GameResult {
+        for (team, animation) in self.dying.iter() {
+            if let Some(player) = self.players.get(team) {
+                let time = animation.time();
+                self.draw_sprite(ctx, player, CrabState::Die, time, animation.rect(), animation.facing(), false)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// A headstone with a cross, in the color of the dead crab's team.
+    pub fn draw_gravestone(&self, ctx: &mut Context, gravestone: &Gravestone) -> GameResult {
+        let rect = gravestone.body.get_rect();
+        let radius = rect.w / 2.0;
+        let center = Point2::new(rect.x + radius, rect.y + radius);
+        let color = Self::TEAM_COLORS[gravestone.team % Self::TEAM_COLORS.len()];
+        let cross = [
+            [Point2::new(center.x, center.y - radius / 2.0), Point2::new(center.x, rect.bottom() - radius / 2.0)],
+            [Point2::new(center.x - radius / 2.0, center.y), Point2::new(center.x + radius / 2.0, center.y)],
+        ];
+        let mut mesh = graphics::MeshBuilder::new();
+        mesh.circle(graphics::DrawMode::fill(), center, radius, 0.5, Self::GRAVESTONE_COLOR)
+            .rectangle(
+                graphics::DrawMode::fill(),
+                Rect::new(rect.x, center.y, rect.w, rect.bottom() - center.y),
+                Self::GRAVESTONE_COLOR,
+            );
+        for line in cross.iter() {
+            mesh.line(line, 2.0, color)?;
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())
+    }
+
+    /// A wooden box with the icon of the status inside.
+    pub fn draw_supply_crate(&self, ctx: &mut Context, supply: &SupplyCrate) -> GameResult {
+        let rect = supply.body.get_rect();
+        let mut mesh = graphics::MeshBuilder::new();
+        mesh.rectangle(graphics::DrawMode::fill(), rect, Self::CRATE_COLOR)
+            .rectangle(graphics::DrawMode::stroke(2.0), rect, Self::CRATE_EDGE_COLOR);
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())?;
+        let center = Point2::new(rect.x + rect.w / 2.0, rect.y + rect.h / 2.0);
+        self.draw_status_icon(ctx, supply.status, center)
+    }
+
+    /// A row of status icons centered above the health of `crab`.
+    fn draw_statuses(&self, ctx: &mut Context, crab: &Crab) -> GameResult {
+        let rect = crab.get_rect();
+        let count = crab.statuses().iter().count() as f32;
+        let left = rect.x + rect.w / 2.0 - (count - 1.0) * Self::STATUS_ICON_SIZE / 2.0;
+        let y = rect.top() - Self::HEALTH_DISTANCE - Self::STATUS_ICON_SIZE / 2.0;
+        for (i, status) in crab.statuses().iter().enumerate() {
+            let center = Point2::new(left + i as f32 * Self::STATUS_ICON_SIZE, y);
+            self.draw_status_icon(ctx, status.kind, center)?;
+        }
+        Ok(())
+    }
+
+    fn draw_status_icon(&self, ctx: &mut Context, kind: StatusKind, center: Point2<f32>) -> GameResult {
+        let color = match kind {
+            StatusKind::Poison => graphics::Color::new(0.3, 0.8, 0.2, 1.0),
+            StatusKind::Freeze => graphics::Color::new(0.6, 0.85, 1.0, 1.0),
+            StatusKind::Shield => graphics::Color::new(0.95, 0.8, 0.2, 1.0),
+            StatusKind::DoubleDamage => graphics::Color::new(0.9, 0.2, 0.2, 1.0),
+        };
+        let radius = Self::STATUS_ICON_SIZE / 2.0 - 1.0;
+        let circle = graphics::Mesh::new_circle(ctx, graphics::DrawMode::fill(), center, radius, 0.5, color)?;
+        graphics::draw(ctx, &circle, DrawParam::default())?;
+        let mut label = Text::new(kind.label());
+        label.set_font(graphics::Font::default(), graphics::Scale::uniform(radius * 1.5));
+        let (width, height) = label.dimensions(ctx);
+        let dest = Point2::new(center.x - width as f32 / 2.0, center.y - height as f32 / 2.0);
+        graphics::draw(ctx, &label, DrawParam::default().dest(dest).color(graphics::BLACK))
+    }
+
+    /// Plays the fire animation of the crab.
+    pub fn crab_fired(&mut self, name: &str) {
+        if let Some((_, animation)) = self.animations.get_mut(name) {
+            animation.fire();
+        }
+    }
+
+    /// Draws a frame of the team's sprite sheet, or one of its two images when
+    /// it has none, tinted red when hurt and fading away when dying. The
+    /// sprites face right and are mirrored around the crab's middle to face left.
+    #[allow(clippy::too_many_arguments)]
+    fn draw_sprite(
+        &self,
+        ctx: &mut Context,
+        player: &Player,
+        state: CrabState,
+        time: f32,
+        rect: Rect,
+        facing: f32,
+        armed: bool,
+    ) -> GameResult {
+        let animation = player.sheet.as_ref().and_then(|sheet| {
+            sheet
+                .animations
+                .get(&state)
+                .or_else(|| sheet.animations.get(&CrabState::Idle))
+                .map(|animation| (sheet, animation))
+        });
+        let (image, src, size, color) = match animation {
+            Some((sheet, animation)) => {
+                let (width, height) = (sheet.image.width() as f32, sheet.image.height() as f32);
+                let src = Rect::new(
+                    animation::frame(animation, time) as f32 * sheet.frame_width / width,
+                    animation.row as f32 * sheet.frame_height / height,
+                    sheet.frame_width / width,
+                    sheet.frame_height / height,
+                );
+                (&sheet.image, src, (sheet.frame_width, sheet.frame_height), graphics::WHITE)
+            }
+            None => {
+                let image = if armed || state == CrabState::Fire {
+                    &player.crab_firing_image
+                } else {
+                    &player.crab_image
+                };
+                let color = match state {
+                    CrabState::Hurt => Self::HURT_COLOR,
+                    CrabState::Die => graphics::Color {
+                        a: (1.0 - time / CrabAnimation::DIE_TIME).max(0.0),
+                        ..Self::HURT_COLOR
+                    },
+                    _ => graphics::WHITE,
+                };
+                let size = (image.width() as f32, image.height() as f32);
+                (image, Rect::one(), size, color)
+            }
+        };
+        let scale = Vector2::new(rect.w / size.0, rect.h / size.1);
+        let param = if facing < 0.0 {
+            DrawParam::default()
+                .dest(Point2::new(rect.right(), rect.y))
+                .scale(Vector2::new(-scale.x, scale.y))
+        } else {
+            DrawParam::default().dest(rect.point()).scale(scale)
+        };
+        graphics::draw(ctx, image, param.src(src).color(color))
+    }
+
+    fn draw_aim_angle(&self, ctx: &mut Context, crab: &Crab) -> GameResult {
+        let rect = crab.get_rect();
+        let angle = Text::new(format!("{:.0}°", crab.aim_degrees()));
+        graphics::draw(
+            ctx,
+            &angle,
+            DrawParam::default().dest(Point2::new(
+                rect.x + rect.w / 4.0,
+                rect.bottom() + Self::AIM_ANGLE_DISTANCE,
+            )),
+        )
+    }
+
     fn draw_health(&self, ctx: &mut Context, crab: &Crab) -> GameResult {
-        let health = Text::new(format!("{}", crab.get_health()));
+        let shown = self.shown_health.get(&crab.name).copied().unwrap_or_else(|| crab.get_health());
+        let health = Text::new(format!("{}", shown.round()));
         let rect = crab.get_rect();
         graphics::draw(
             ctx,
@@ -221,32 +720,210 @@ impl GUI {
         graphics::draw(ctx, &rect, DrawParam::default())
     }
 
-    pub fn draw_winner(&self, ctx: &mut Context, winner: &str) -> GameResult {
-        let winner_banner = Text::new(format!("{} wins", winner));
-        graphics::draw(
+    pub fn draw_water(&self, ctx: &mut Context, rect: Rect) -> GameResult {
+        let water = graphics::Mesh::new_rectangle(
             ctx,
-            &winner_banner,
-            DrawParam::default().dest(Point2::new(self.map.width() as f32 / 2.0 - 20.0, Self::WINNER_BANNER_DISTANCE)),
-        )
+            graphics::DrawMode::fill(),
+            rect,
+            Self::WATER_COLOR,
+        )?;
+        graphics::draw(ctx, &water, DrawParam::default())
+    }
+
+    /// Darkens `rect`, e.g. under the pause menu.
+    pub fn draw_shade(&self, ctx: &mut Context, rect: Rect) -> GameResult {
+        let shade = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            rect,
+            graphics::Color::new(0.0, 0.0, 0.0, 0.6),
+        )?;
+        graphics::draw(ctx, &shade, DrawParam::default())
     }
 
-    pub fn draw_weapons_menu(&self, ctx: &mut Context) -> GameResult {
-        for idx in 0..WEAPONS_MENU_ITEMS.len() {
-            self.draw_weapon_at_idx(
+    /// Team health bars in the top left corner, the turn timer and whether
+    /// the crab can be picked in the top right one and the selected weapon
+    /// in the bottom left one.
+    pub fn draw_hud(&self, ctx: &mut Context, hud: &Hud) -> GameResult {
+        let (width, height) = (self.map.width() as f32, self.map.height() as f32);
+        for (i, team) in hud.teams.iter().enumerate() {
+            let y = Self::HUD_PADDING + i as f32 * Self::HUD_LINE;
+            let marker = if i == hud.active { "> " } else { "  " };
+            let name = Text::new(format!("{}{}", marker, team.name));
+            graphics::draw(ctx, &name, DrawParam::default().dest(Point2::new(Self::HUD_PADDING, y)))?;
+
+            let x = Self::HUD_PADDING + Self::HUD_NAME_WIDTH;
+            let frame = Rect::new(x, y + 2.0, Self::HUD_BAR_WIDTH, Self::HUD_BAR_HEIGHT);
+            let filled = if team.max_health > 0.0 {
+                (team.health / team.max_health).clamp(0.0, 1.0)
+            } else {
+                0.0
+            };
+            if filled > 0.0 {
+                let bar = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    Rect::new(frame.x, frame.y, frame.w * filled, frame.h),
+                    Self::TEAM_COLORS[i % Self::TEAM_COLORS.len()],
+                )?;
+                graphics::draw(ctx, &bar, DrawParam::default())?;
+            }
+            let outline = graphics::Mesh::new_rectangle(ctx, graphics::DrawMode::stroke(1.0), frame, graphics::WHITE)?;
+            graphics::draw(ctx, &outline, DrawParam::default())?;
+
+            let mut info = format!("{:.0}  {} crabs", team.health.max(0.0), team.crabs);
+            if i == hud.next && hud.next != hud.active {
+                info.push_str("  (next)");
+            }
+            for (weapon, left) in team.ammo.iter() {
+                info.push_str(&format!("  {:?} {}", weapon, left));
+            }
+            graphics::draw(
                 ctx,
-                idx as u8,
-                Rect::new(
-                    self.weapons.rect.x as f32 + idx as f32 * self.weapons.rect.w,
-                    self.weapons.rect.y,
-                    self.weapons.rect.w,
-                    self.weapons.rect.h,
-                ),
-                Vector2::new(1.0, 1.0),
+                &Text::new(info),
+                DrawParam::default().dest(Point2::new(frame.right() + Self::HUD_PADDING, y)),
+            )?;
+        }
+
+        if let Some(time_left) = hud.time_left {
+            let mut timer = Text::new(format!("{:.0}", time_left.max(0.0).ceil()));
+            timer.set_font(graphics::Font::default(), graphics::Scale::uniform(2.0 * Self::HUD_LINE));
+            let x = width - Self::HUD_PADDING - timer.width(ctx) as f32;
+            graphics::draw(ctx, &timer, DrawParam::default().dest(Point2::new(x, Self::HUD_PADDING)))?;
+        }
+
+        if let Some(left) = hud.crab_choice {
+            let mut hint = String::from("Tab / click: choose a crab");
+            if let Some(left) = left {
+                hint.push_str(&format!(" ({} turns left)", left));
+            }
+            let hint = Text::new(hint);
+            let x = width - Self::HUD_PADDING - hint.width(ctx) as f32;
+            let y = Self::HUD_PADDING + 2.0 * Self::HUD_LINE + Self::HUD_PADDING;
+            graphics::draw(ctx, &hint, DrawParam::default().dest(Point2::new(x, y)))?;
+        }
+
+        if let Some(weapon) = hud.weapon {
+            let y = height - Self::WEAPONS_IMAGE_HEIGHT - Self::HUD_PADDING;
+            let icon = Rect::new(Self::HUD_PADDING, y, Self::WEAPONS_IMAGE_WIDTH, Self::WEAPONS_IMAGE_HEIGHT);
+            let (idx, _) = WEAPONS_MENU_ITEMS
+                .iter()
+                .enumerate()
+                .find(|(_, w)| w.kind == weapon)
+                .unwrap();
+            self.draw_weapon_at_idx(ctx, idx as u8, icon, Vector2::new(1.0, 1.0))?;
+            let ammo = match hud.ammo {
+                Some(left) => left.to_string(),
+                None => String::from("unlimited"),
+            };
+            let label = Text::new(format!("{:?}  ammo: {}", weapon, ammo));
+            graphics::draw(
+                ctx,
+                &label,
+                DrawParam::default().dest(Point2::new(icon.right() + Self::HUD_PADDING, y + icon.h / 3.0)),
             )?;
         }
         Ok(())
     }
 
+    pub fn draw_game_over(&self, ctx: &mut Context, title: &str, teams: &[TeamSummary]) -> GameResult {
+        let (width, height) = (self.map.width() as f32, self.map.height() as f32);
+        let panel = Rect::new(
+            (width - Self::GAME_OVER_WIDTH) / 2.0,
+            height / 4.0,
+            Self::GAME_OVER_WIDTH,
+            Self::GAME_OVER_LINE * (teams.len() as f32 + 6.0),
+        );
+        let background = graphics::Mesh::new_rectangle(
+            ctx,
+            graphics::DrawMode::fill(),
+            panel,
+            graphics::Color::new(0.0, 0.0, 0.0, 0.7),
+        )?;
+        graphics::draw(ctx, &background, DrawParam::default())?;
+
+        let mut lines = vec![
+            title.to_owned(),
+            String::new(),
+            format!(
+                "{:<12}{:>7}{:>7}{:>7}{:>7}{:>7}{:>7}{:>7}{:>7}",
+                "Team", "Dealt", "Taken", "Self", "Kills", "Shots", "Hits", "Turns", "Crabs"
+            ),
+        ];
+        for team in teams {
+            lines.push(format!(
+                "{:<12}{:>7}{:>7}{:>7}{:>7}{:>7}{:>7}{:>7}{:>7}",
+                team.name,
+                team.stats.damage_dealt.round(),
+                team.stats.damage_taken.round(),
+                team.stats.self_damage.round(),
+                team.stats.kills,
+                team.stats.shots_fired,
+                team.stats.hits,
+                team.stats.turns_survived,
+                team.crabs_left,
+            ));
+        }
+        let best = teams
+            .iter()
+            .flat_map(|team| team.stats.crabs.iter())
+            .filter(|crab| crab.damage_dealt > 0.0)
+            .max_by(|a, b| a.damage_dealt.total_cmp(&b.damage_dealt));
+        lines.push(String::new());
+        if let Some(crab) = best {
+            lines.push(format!(
+                "Best crab: {} with {} damage and {} kills",
+                crab.name,
+                crab.damage_dealt.round(),
+                crab.kills
+            ));
+        }
+        lines.push(String::from("R / Enter - rematch    Esc - main menu"));
+        for (i, line) in lines.iter().enumerate() {
+            graphics::draw(
+                ctx,
+                &Text::new(line.as_str()),
+                DrawParam::default().dest(Point2::new(
+                    panel.x + Self::GAME_OVER_WINDOW_PADDING,
+                    panel.y + Self::GAME_OVER_WINDOW_PADDING + i as f32 * Self::GAME_OVER_LINE,
+                )),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Draws the weapons menu, dimming the weapons the team can't use.
+    pub fn draw_weapons_menu(&self, ctx: &mut Context, inventory: &Inventory, selected: usize) -> GameResult {
+        for (idx, weapon) in WEAPONS_MENU_ITEMS.iter().enumerate() {
+            let rect = Rect::new(
+                self.weapons.rect.x + idx as f32 * self.weapons.rect.w,
+                self.weapons.rect.y,
+                self.weapons.rect.w,
+                self.weapons.rect.h,
+            );
+            self.draw_weapon_at_idx(ctx, idx as u8, rect, Vector2::new(1.0, 1.0))?;
+            if !inventory.has(weapon.kind) {
+                let dim = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::fill(),
+                    rect,
+                    graphics::Color::new(0.0, 0.0, 0.0, 0.6),
+                )?;
+                graphics::draw(ctx, &dim, DrawParam::default())?;
+            }
+            if idx == selected {
+                let frame = graphics::Mesh::new_rectangle(
+                    ctx,
+                    graphics::DrawMode::stroke(2.0),
+                    rect,
+                    graphics::Color::new(1.0, 0.85, 0.2, 1.0),
+                )?;
+                graphics::draw(ctx, &frame, DrawParam::default())?;
+            }
+        }
+        Ok(())
+    }
+
     fn draw_aim(&self, ctx: &mut Context, dest: Point2<f32>) -> GameResult {
         let scale = Vector2::new(
             self.aim.width/self.aim.image.width() as f32,
@@ -270,29 +947,40 @@ impl GUI {
             ctx,
             &self.arrow.image,
             DrawParam::default()
-                .dest(Point2::new(dest.x - self.arrow.width/2.0, dest.y - self.arrow.height as f32))
+                .dest(Point2::new(dest.x - self.arrow.width/2.0, dest.y - self.arrow.height))
                 .scale(scale),
         )
     }
 
-    pub fn draw_map_hits(&self, ctx: &mut Context, shots: &Vec<GameShot>) -> GameResult {
-        for shot in shots {
-            let circle = graphics::Mesh::new_circle(
-                ctx,
-                graphics::DrawMode::fill(),
-                Point2::new(0.0, 0.0),
-                shot.damage(),
-                1.0,
-                graphics::BLACK,
-            )?;
-            let rect = shot.get_rect();
-            graphics::draw(
-                ctx,
-                &circle,
-                DrawParam::default().dest(Point2::new(rect.x, rect.y)),
-            )?;
+    /// Every few points of the trajectory as a dot, and the crater at its end.
+    pub fn draw_trajectory(&self, ctx: &mut Context, trajectory: &Trajectory) -> GameResult {
+        let color = graphics::Color::new(1.0, 1.0, 1.0, 0.8);
+        let mut mesh = graphics::MeshBuilder::new();
+        for point in trajectory.points.iter().step_by(Self::TRAJECTORY_DOT_STEP) {
+            mesh.circle(graphics::DrawMode::fill(), *point, Self::TRAJECTORY_DOT_RADIUS, 0.5, color);
         }
-        Ok(())
+        if let Some(impact) = trajectory.impact {
+            let blast = graphics::Color::new(1.0, 0.3, 0.2, 0.8);
+            mesh.circle(graphics::DrawMode::stroke(2.0), impact.point, impact.radius.max(1.0), 0.5, blast);
+            mesh.circle(graphics::DrawMode::fill(), impact.point, Self::TRAJECTORY_DOT_RADIUS, 0.5, blast);
+        }
+        let mesh = mesh.build(ctx)?;
+        graphics::draw(ctx, &mesh, DrawParam::default())
+    }
+
+    pub fn draw_girder(&self, ctx: &mut Context, girder: &Girder, valid: bool) -> GameResult {
+        let color = if valid {
+            graphics::Color::new(0.0, 1.0, 0.0, 0.5)
+        } else {
+            graphics::Color::new(1.0, 0.0, 0.0, 0.5)
+        };
+        let mesh = graphics::Mesh::new_polygon(
+            ctx,
+            graphics::DrawMode::fill(),
+            &girder.corners(),
+            color,
+        )?;
+        graphics::draw(ctx, &mesh, DrawParam::default())
     }
 
     fn draw_weapon_at_idx(
@@ -341,6 +1029,14 @@ impl GUI {
 struct Player {
     crab_image: graphics::Image,
     crab_firing_image: graphics::Image,
+    sheet: Option<Sheet>,
+}
+
+struct Sheet {
+    image: graphics::Image,
+    frame_width: f32,
+    frame_height: f32,
+    animations: HashMap<CrabState, AnimationConfig>,
 }
 
 struct WeaponInfo {
@@ -358,13 +1054,26 @@ struct ShotImages {
     bazooka: graphics::Image,
 }
 
+/// A damage number floating up from a hit crab.
+struct Popup {
+    text: String,
+    pos: Point2<f32>,
+    age: f32,
+}
+
 struct ImageSettings {
     image: graphics::Image,
     width: f32,
     height: f32,
 }
 
-static WEAPONS_MENU_ITEMS: &'static [WeaponInfo; 3] = &[
+pub const WEAPONS_COUNT: usize = 8;
+
+pub fn weapon_at(idx: usize) -> WeaponType {
+    WEAPONS_MENU_ITEMS[idx].kind
+}
+
+static WEAPONS_MENU_ITEMS: &[WeaponInfo; WEAPONS_COUNT] = &[
     // WeaponInfo {
     //     kind: WeaponType::Grenade,
     //     image_pos: (0, 0),
@@ -381,5 +1090,25 @@ static WEAPONS_MENU_ITEMS: &'static [WeaponInfo; 3] = &[
         kind: WeaponType::Pistol,
         image_pos: (0, 9),
     },
+    WeaponInfo {
+        kind: WeaponType::Girder,
+        image_pos: (3, 7),
+    },
+    WeaponInfo {
+        kind: WeaponType::Blowtorch,
+        image_pos: (3, 4),
+    },
+    WeaponInfo {
+        kind: WeaponType::Drill,
+        image_pos: (0, 5),
+    },
+    WeaponInfo {
+        kind: WeaponType::PoisonDart,
+        image_pos: (2, 5),
+    },
+    WeaponInfo {
+        kind: WeaponType::FreezeRay,
+        image_pos: (3, 5),
+    },
 ];
 
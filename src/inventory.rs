@@ -0,0 +1,94 @@
+use crate::config::WeaponsConfig;
+use crate::settings::WeaponSet;
+use crate::weapon::WeaponType;
+use serde::{Deserialize, Serialize};
+
+/// The weapons a team can use and the shots it has left of each.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct Inventory {
+    /// `None` for unlimited ammo.
+    ammo: Vec<(WeaponType, Option<u32>)>,
+}
+
+impl Inventory {
+    /// The weapons of `set`, with the ammo of `cfg`.
+    pub fn new(cfg: &WeaponsConfig, set: WeaponSet) -> Self {
+        let ammo = WeaponType::ALL
+            .iter()
+            .copied()
+            .filter(|&weapon| set.allows(weapon))
+            .map(|weapon| {
+                let limit = cfg
+                    .ammo
+                    .iter()
+                    .find(|(name, _)| WeaponType::from_name(name) == Some(weapon))
+                    .map(|&(_, shots)| shots);
+                (weapon, limit)
+            })
+            .collect();
+        Inventory { ammo }
+    }
+
+    /// Shots left of `weapon`, `None` when they are unlimited.
+    pub fn left(&self, weapon: WeaponType) -> Option<u32> {
+        match self.ammo.iter().find(|(kind, _)| *kind == weapon) {
+            Some(&(_, left)) => left,
+            None => Some(0),
+        }
+    }
+
+    pub fn has(&self, weapon: WeaponType) -> bool {
+        self.left(weapon) != Some(0)
+    }
+
+    /// The weapons with limited ammo and the shots left of them.
+    pub fn limited(&self) -> Vec<(WeaponType, u32)> {
+        self.ammo
+            .iter()
+            .filter_map(|&(weapon, left)| left.map(|left| (weapon, left)))
+            .collect()
+    }
+
+    /// Uses up a shot of `weapon`, if there is one.
+    pub fn take(&mut self, weapon: WeaponType) -> bool {
+        match self.ammo.iter_mut().find(|(kind, _)| *kind == weapon) {
+            Some((_, None)) => true,
+            Some((_, Some(left))) if *left > 0 => {
+                *left -= 1;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+
+    #[test]
+    fn inventory_ammo() {
+        let mut inventory = Inventory::new(&CONFIG.weapons, WeaponSet::All);
+        assert_eq!(inventory.left(WeaponType::Bazooka), None);
+        assert_eq!(inventory.left(WeaponType::Drill), Some(2));
+        assert!(inventory.take(WeaponType::Drill));
+        assert!(inventory.take(WeaponType::Drill));
+        assert!(!inventory.take(WeaponType::Drill));
+        assert!(!inventory.has(WeaponType::Drill));
+        assert!(inventory.take(WeaponType::Bazooka));
+        assert!(inventory.has(WeaponType::Bazooka));
+    }
+
+    #[test]
+    fn inventory_follows_weapon_set() {
+        let mut inventory = Inventory::new(&CONFIG.weapons, WeaponSet::PistolsOnly);
+        assert!(inventory.has(WeaponType::Pistol));
+        assert!(!inventory.has(WeaponType::Bazooka));
+        assert!(!inventory.take(WeaponType::Bazooka));
+        for (name, _) in CONFIG.weapons.ammo {
+            assert!(WeaponType::from_name(name).is_some(), "unknown weapon {}", name);
+        }
+    }
+}
@@ -0,0 +1,100 @@
+use crate::menu::{Menu, MenuAction};
+use crate::profile::{Profile, Profiles};
+use crate::scene::Transition;
+use ggez::event::KeyCode;
+use ggez::graphics::{self, DrawParam, Text};
+use ggez::nalgebra::Point2;
+use ggez::{Context, GameResult};
+
+/// Ranks the saved profiles and shows the lifetime totals of the selected one.
+pub struct Leaderboard {
+    profiles: Profiles,
+    menu: Menu,
+}
+
+impl Leaderboard {
+    const DETAILS_LINE: f32 = 20.0;
+    /// Rows of details under the bottom of the screen.
+    const DETAILS_ROWS: f32 = 9.0;
+
+    pub fn new(profiles: Profiles) -> Self {
+        let mut items: Vec<String> = profiles
+            .leaderboard()
+            .iter()
+            .enumerate()
+            .map(|(i, profile)| Self::row(i + 1, profile))
+            .collect();
+        items.push(String::from("Back"));
+        let mut menu = Menu::new("Leaderboard", items);
+        if profiles.leaderboard().is_empty() {
+            menu.set_message(Some(String::from("No matches played yet")));
+        }
+        Leaderboard { profiles, menu }
+    }
+
+    fn row(rank: usize, profile: &Profile) -> String {
+        format!(
+            "{}. {}  {} won, {} lost, {} drawn ({:.0}%)",
+            rank,
+            profile.name,
+            profile.wins,
+            profile.losses,
+            profile.draws,
+            profile.win_rate() * 100.0
+        )
+    }
+
+    pub fn key_down(&mut self, keycode: KeyCode) -> Option<Transition> {
+        let profiles = self.profiles.leaderboard().len();
+        match self.menu.key_down(keycode)? {
+            MenuAction::Activate(idx) if idx < profiles => None,
+            MenuAction::Activate(_) | MenuAction::Back => Some(Transition::Menu),
+            MenuAction::Change(..) => None,
+        }
+    }
+
+    /// The lifetime totals of `profile`, one line each.
+    fn details(profile: &Profile) -> Vec<String> {
+        let totals = &profile.totals;
+        let mut lines = vec![
+            format!("{}: {} matches", profile.name, profile.matches()),
+            format!(
+                "Damage dealt {}, taken {}, to itself {}",
+                totals.damage_dealt.round(),
+                totals.damage_taken.round(),
+                totals.self_damage.round()
+            ),
+            format!("Kills {}, crabs lost {}", totals.kills, totals.crabs_lost),
+            format!(
+                "Shots {}, hits {} ({:.0}%)",
+                totals.shots_fired,
+                totals.hits,
+                profile.accuracy() * 100.0
+            ),
+            format!("Turns survived {}", totals.turns_survived),
+        ];
+        for weapon in totals.weapons.iter() {
+            lines.push(format!("  {:?}: {} shots, {} hits", weapon.weapon, weapon.shots, weapon.hits));
+        }
+        lines
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)?;
+        let profile = match self.profiles.leaderboard().get(self.menu.selected()) {
+            Some(profile) => *profile,
+            None => return Ok(()),
+        };
+        let screen = graphics::screen_coordinates(ctx);
+        let x = screen.x + screen.w / 3.0;
+        let y = screen.bottom() - Self::DETAILS_ROWS * Self::DETAILS_LINE;
+        for (i, line) in Self::details(profile).iter().enumerate() {
+            graphics::draw(
+                ctx,
+                &Text::new(line.as_str()),
+                DrawParam::default().dest(Point2::new(x, y + i as f32 * Self::DETAILS_LINE)),
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,44 @@
+// The derives of the locked serde_derive trip lints of newer compilers.
+#![allow(unknown_lints, non_local_definitions, unexpected_cfgs)]
+
+pub mod ai;
+pub mod animation;
+pub mod audio;
+pub mod body;
+pub mod camera;
 pub mod config;
+pub mod controls;
 pub mod crab;
+pub mod effects;
 pub mod game;
+pub mod gravestone;
 pub mod gui;
+pub mod inventory;
+pub mod leaderboard;
+pub mod lobby;
 pub mod map;
+pub mod mask;
+pub mod menu;
+pub mod net;
+pub mod outcome;
+pub mod package;
+pub mod pause;
 pub mod player;
+pub mod profile;
+pub mod save;
+pub mod scene;
+pub mod server;
+pub mod settings;
+pub mod setup;
 pub mod shot;
+pub mod simulation;
+pub mod spawn;
+pub mod state;
+pub mod stats;
+pub mod status;
+pub mod supply;
+pub mod terrain;
+pub mod texture;
+pub mod tool;
+pub mod trajectory;
 pub mod weapon;
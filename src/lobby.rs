@@ -0,0 +1,201 @@
+use crate::menu::{Menu, MenuAction};
+use crate::net::{self, Host, Join, Lockstep};
+use crate::scene::Transition;
+use crate::settings::MatchSettings;
+use ggez::event::KeyCode;
+use ggez::{Context, GameResult};
+
+/// Hosts a network match with the current match settings, joins one or
+/// watches one.
+pub struct Lobby {
+    settings: MatchSettings,
+    /// Where to join, typed in on the join row.
+    address: String,
+    state: State,
+    menu: Menu,
+    /// The session of the match that was started, for the match scene.
+    lockstep: Option<Lockstep>,
+    error: Option<String>,
+}
+
+enum State {
+    Idle,
+    Hosting(Host),
+    Joining(Join),
+}
+
+impl Lobby {
+    const DEFAULT_ADDRESS: &'static str = "127.0.0.1";
+
+    pub fn new(settings: MatchSettings) -> Self {
+        let mut lobby = Lobby {
+            settings,
+            address: String::from(Self::DEFAULT_ADDRESS),
+            state: State::Idle,
+            menu: Menu::new("Network game", vec![]),
+            lockstep: None,
+            error: None,
+        };
+        lobby.refresh();
+        lobby
+    }
+
+    /// The session of the match that was started.
+    pub fn take_lockstep(&mut self) -> Option<Lockstep> {
+        self.lockstep.take()
+    }
+
+    pub fn key_down(&mut self, keycode: KeyCode) -> Option<Transition> {
+        let joining_row = matches!(self.state, State::Idle) && self.menu.selected() == 1;
+        if keycode == KeyCode::Back && joining_row {
+            self.address.pop();
+            self.refresh();
+            return None;
+        }
+        let transition = match self.menu.key_down(keycode)? {
+            MenuAction::Activate(idx) => self.activate(idx),
+            MenuAction::Change(..) => None,
+            MenuAction::Back => match self.state {
+                State::Idle => Some(Transition::Menu),
+                _ => {
+                    self.state = State::Idle;
+                    None
+                }
+            },
+        };
+        self.refresh();
+        transition
+    }
+
+    /// Characters typed on the join row go into the address.
+    pub fn text_input(&mut self, ch: char) {
+        let allowed = ch.is_ascii_alphanumeric() || ch == '.' || ch == ':' || ch == '-';
+        if allowed && matches!(self.state, State::Idle) && self.menu.selected() == 1 {
+            self.address.push(ch);
+            self.refresh();
+        }
+    }
+
+    /// Takes in clients while hosting, or waits for the host to start.
+    pub fn update(&mut self) -> Option<Transition> {
+        let transition = match std::mem::replace(&mut self.state, State::Idle) {
+            State::Idle => None,
+            State::Hosting(mut host) => {
+                if let Err(e) = host.accept() {
+                    self.error = Some(format!("Could not take in players: {}", e));
+                }
+                self.state = State::Hosting(host);
+                None
+            }
+            State::Joining(join) => match join.poll() {
+                Ok(Ok((settings, lockstep))) => {
+                    self.lockstep = Some(lockstep);
+                    Some(Transition::Play(settings))
+                }
+                Ok(Err(join)) => {
+                    self.state = State::Joining(join);
+                    None
+                }
+                Err(e) => {
+                    self.error = Some(format!("Lost the host: {}", e));
+                    None
+                }
+            },
+        };
+        self.refresh();
+        transition
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)
+    }
+
+    fn activate(&mut self, idx: usize) -> Option<Transition> {
+        self.error = None;
+        match (std::mem::replace(&mut self.state, State::Idle), idx) {
+            (State::Idle, 0) => match Host::bind(net::DEFAULT_PORT) {
+                Ok(host) => self.state = State::Hosting(host),
+                Err(e) => self.error = Some(format!("Could not host: {}", e)),
+            },
+            (State::Idle, 1) => match Join::connect(&self.address) {
+                Ok(join) => self.state = State::Joining(join),
+                Err(e) => self.error = Some(format!("Could not join {}: {}", self.address, e)),
+            },
+            (State::Idle, 2) => match Join::spectate(&self.address) {
+                Ok(join) => self.state = State::Joining(join),
+                Err(e) => self.error = Some(format!("Could not watch {}: {}", self.address, e)),
+            },
+            (State::Idle, _) => return Some(Transition::Menu),
+            (State::Hosting(host), 0) if host.clients().is_empty() => {
+                self.error = Some(String::from("Nobody joined yet"));
+                self.state = State::Hosting(host);
+            }
+            (State::Hosting(host), 0) => {
+                let mut settings = self.settings.clone();
+                settings.seed = rand::random();
+                match host.start(&settings) {
+                    Ok(lockstep) => {
+                        self.lockstep = Some(lockstep);
+                        return Some(Transition::Play(settings));
+                    }
+                    Err(e) => self.error = Some(format!("Could not start the match: {}", e)),
+                }
+            }
+            // Cancelling drops the connections.
+            (State::Hosting(_), _) | (State::Joining(_), _) => (),
+        }
+        None
+    }
+
+    fn refresh(&mut self) {
+        let (items, status) = match &self.state {
+            State::Idle => (
+                vec![
+                    format!("Host a match on port {}", net::DEFAULT_PORT),
+                    format!("Join: {}", self.address),
+                    String::from("Watch the match there"),
+                    String::from("Back"),
+                ],
+                None,
+            ),
+            State::Hosting(host) => {
+                let clients = host.clients();
+                let mut status = if clients.is_empty() {
+                    format!("Waiting for players on port {}", host.port())
+                } else {
+                    format!("Joined: {}", clients.join(", "))
+                };
+                if host.spectators() > 0 {
+                    status.push_str(&format!(", {} watching", host.spectators()));
+                }
+                (vec![String::from("Start match"), String::from("Cancel")], Some(status))
+            }
+            State::Joining(_) => (
+                vec![String::from("Cancel")],
+                Some(String::from("Waiting for the match to start")),
+            ),
+        };
+        self.menu.set_items(items);
+        self.menu.set_message(self.error.clone().or(status));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+
+    #[test]
+    fn lobby_edits_address() {
+        let mut lobby = Lobby::new(MatchSettings::new(&CONFIG));
+        lobby.text_input('x');
+        assert_eq!(lobby.address, Lobby::DEFAULT_ADDRESS);
+        lobby.key_down(KeyCode::Down);
+        lobby.key_down(KeyCode::Back);
+        lobby.text_input('2');
+        lobby.text_input(' ');
+        assert_eq!(lobby.address, "127.0.0.2");
+        assert_eq!(lobby.key_down(KeyCode::Escape), Some(Transition::Menu));
+    }
+}
@@ -1,3 +1,4 @@
+#[allow(clippy::single_component_path_imports)]
 use ggez;
 use ggez::event;
 use ggez::GameResult;
@@ -6,7 +7,7 @@ use std::env;
 use std::path;
 
 use crabs::config::CONFIG;
-use crabs::game::Game;
+use crabs::scene::App;
 
 pub fn main() -> GameResult {
     let resource_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
@@ -25,6 +26,6 @@ pub fn main() -> GameResult {
         .add_resource_path(resource_dir)
         .build()?;
 
-    let game = &mut Game::new(ctx, &CONFIG)?;
-    event::run(ctx, event_loop, game)
+    let app = &mut App::new(ctx, &CONFIG);
+    event::run(ctx, event_loop, app)
 }
@@ -1,30 +1,87 @@
+use crate::mask::Mask;
 use crate::shot::Shot;
+use ggez::graphics::Rect;
 use ggez::nalgebra::Point2;
+use serde::{Deserialize, Serialize};
 use std::fmt;
+use std::hash::{Hash, Hasher};
 
+#[derive(Serialize, Deserialize)]
 pub struct Map {
-    mask: Vec<Vec<i8>>,
+    mask: Mask,
+    indestructible: Option<Mask>,
+    water_level: Option<f32>,
     width: u16,
     height: u16,
+    /// Only kept until the renderer takes them.
+    #[serde(skip)]
+    edits: Vec<TerrainEdit>,
 }
 
-impl Map {
-    pub fn new(data: &Vec<u8>, width: u16, height: u16) -> Map {
-        let width = width as usize;
-        let height = height as usize;
-        let alphas = data.iter().enumerate().filter(|(idx, _)| idx % 4 == 3);
-        let mut mask: Vec<Vec<i8>> = vec![vec![0; width]; height];
-        for (idx, (_, &val)) in alphas.enumerate() {
-            let x = idx % width;
-            let y = idx / width;
-            if val > 0 {
-                mask[y][x] = 1;
-            }
+/// A change made to the terrain, kept so the renderer can mirror the mask.
+#[derive(Clone, Debug, PartialEq)]
+pub enum TerrainEdit {
+    Carve { center: Point2<f32>, radius: f32 },
+    /// `spans` are the `(y, x0, x1)` rows of pixels the fill made solid.
+    Fill {
+        polygon: Vec<Point2<f32>>,
+        spans: Vec<(usize, usize, usize)>,
+    },
+}
+
+impl TerrainEdit {
+    pub fn bounds(&self) -> Rect {
+        let (min, max) = match self {
+            TerrainEdit::Carve { center, radius } => (
+                Point2::new(center.x - radius, center.y - radius),
+                Point2::new(center.x + radius, center.y + radius),
+            ),
+            TerrainEdit::Fill { polygon, .. } => bounds(polygon),
+        };
+        Rect::new(min.x, min.y, max.x - min.x, max.y - min.y)
+    }
+
+    pub fn contains(&self, p: Point2<f32>) -> bool {
+        match self {
+            TerrainEdit::Carve { center, radius } => ggez::nalgebra::distance(center, &p) <= *radius,
+            TerrainEdit::Fill { spans, .. } => spans.iter().any(|&(y, x0, x1)| {
+                p.y >= 0.0 && p.y as usize == y && p.x >= x0 as f32 && p.x <= x1 as f32
+            }),
         }
+    }
+}
+
+impl Map {
+    pub fn new(data: &[u8], width: u16, height: u16) -> Map {
         Map {
-            mask,
-            width: width as u16,
-            height: height as u16,
+            mask: Mask::from_alpha(data, width as usize, height as usize),
+            indestructible: None,
+            water_level: None,
+            width,
+            height,
+            edits: vec![],
+        }
+    }
+
+    /// Marks the opaque pixels of the RGBA `data` as ground that can't be destroyed.
+    pub fn set_indestructible(&mut self, data: &[u8]) {
+        let indestructible = Mask::from_alpha(data, self.width as usize, self.height as usize);
+        self.mask.merge_rows(&indestructible, 0, self.height as usize);
+        self.indestructible = Some(indestructible);
+    }
+
+    pub fn set_water_level(&mut self, water_level: Option<f32>) {
+        self.water_level = water_level;
+    }
+
+    pub fn get_water_level(&self) -> Option<f32> {
+        self.water_level
+    }
+
+    pub fn under_water(&self, pos: Point2<f32>) -> bool {
+        match self.water_level {
+            Some(level) => pos.y >= level,
+            None => false,
         }
     }
 
@@ -36,15 +93,54 @@ impl Map {
         self.height
     }
 
-    pub fn get(&self, x: usize, y: usize) -> Option<i8> {
-        self.mask.get(y)?.get(x).map(|v|*v)
+    pub fn get(&self, x: usize, y: usize) -> Option<bool> {
+        self.mask.get(x, y)
+    }
+
+    /// Whether the pixel is ground that can't be destroyed.
+    pub fn is_indestructible(&self, x: usize, y: usize) -> bool {
+        let indestructible = self.indestructible.as_ref().and_then(|mask| mask.get(x, y));
+        indestructible == Some(true)
     }
 
     pub fn on_ground(&self, pos: Point2<f32>) -> bool {
-        if let Some(land) = self.get(pos.x.round() as usize, pos.y.round() as usize) {
-            return land == 1i8;
+        let (x, y) = (pos.x.round(), pos.y.round());
+        x >= 0.0 && y >= 0.0 && self.get(x as usize, y as usize) == Some(true)
+    }
+
+    /// The first solid pixel at or below `pos`, in the same column.
+    pub fn first_solid_below(&self, pos: Point2<f32>) -> Option<Point2<f32>> {
+        let (x, y) = (pos.x.round(), pos.y.round().max(0.0));
+        if x < 0.0 {
+            return None;
+        }
+        let found = self.mask.scan_down(x as usize, y as usize, true)?;
+        Some(Point2::new(x, found as f32))
+    }
+
+    /// The first empty pixel at or below `pos`, in the same column.
+    pub fn first_empty_below(&self, pos: Point2<f32>) -> Option<Point2<f32>> {
+        let (x, y) = (pos.x.round(), pos.y.round().max(0.0));
+        if x < 0.0 {
+            return None;
         }
-        false
+        let found = self.mask.scan_down(x as usize, y as usize, false)?;
+        Some(Point2::new(x, found as f32))
+    }
+
+    /// The first empty pixel at or above `pos`, in the same column.
+    pub fn first_empty_above(&self, pos: Point2<f32>) -> Option<Point2<f32>> {
+        let (x, y) = (pos.x.round(), pos.y.round());
+        if x < 0.0 || y < 0.0 {
+            return None;
+        }
+        let found = self.mask.scan_up(x as usize, y as usize, false)?;
+        Some(Point2::new(x, found as f32))
+    }
+
+    /// The first solid pixel on the segment from `from` to `to`.
+    pub fn raycast(&self, from: Point2<f32>, to: Point2<f32>) -> Option<Point2<f32>> {
+        self.mask.raycast(from, to)
     }
 
     pub fn handle_collisions(&mut self, shot: Shot) -> bool {
@@ -52,34 +148,75 @@ impl Map {
         let hit_point = Point2::new(shot_rect.x, shot_rect.y);
         let hit = self.on_ground(hit_point);
         if hit {
-            let r = shot.damage() as isize;
-            for i in -r..r + 1 {
-                for j in -r..r + 1 {
-                    let p = Point2::new(hit_point.x + i as f32, hit_point.y + j as f32);
-                    if ggez::nalgebra::distance(&hit_point, &p) <= r as f32 {
-                        if self.on_ground(p) {
-                            self.mask[p.y as usize][p.x as usize] = -1;
-                        }
-                    }
-                }
-            }
+            self.carve_circle(hit_point, shot.damage());
         }
 
         hit
     }
+
+    pub fn carve_circle(&mut self, center: Point2<f32>, radius: f32) {
+        self.mask.set_circle(center, radius, false);
+        if let Some(indestructible) = &self.indestructible {
+            let top = (center.y - radius).max(0.0) as usize;
+            self.mask.merge_rows(indestructible, top, (center.y + radius).max(0.0) as usize + 1);
+        }
+        self.edits.push(TerrainEdit::Carve { center, radius });
+    }
+
+    /// Turns every cell inside the convex `polygon` into solid ground.
+    pub fn fill_polygon(&mut self, polygon: &[Point2<f32>]) {
+        if polygon.len() < 3 {
+            return;
+        }
+        let spans = self.mask.polygon_spans(polygon);
+        for &(y, x0, x1) in spans.iter() {
+            self.mask.set_span(y, x0, x1, true);
+        }
+        self.edits.push(TerrainEdit::Fill {
+            polygon: polygon.to_vec(),
+            spans,
+        });
+    }
+
+    /// Returns the edits made since the last call.
+    pub fn take_edits(&mut self) -> Vec<TerrainEdit> {
+        std::mem::take(&mut self.edits)
+    }
+}
+
+fn bounds(polygon: &[Point2<f32>]) -> (Point2<f32>, Point2<f32>) {
+    let mut min = polygon[0];
+    let mut max = polygon[0];
+    for p in polygon.iter() {
+        min = Point2::new(min.x.min(p.x), min.y.min(p.y));
+        max = Point2::new(max.x.max(p.x), max.y.max(p.y));
+    }
+    (min, max)
 }
 
 impl fmt::Debug for Map {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        for v in self.mask.iter() {
-            write!(f, "{:?}\n", v)?;
+        for y in 0..self.mask.height() {
+            let row: String = (0..self.mask.width())
+                .map(|x| if self.mask.get(x, y) == Some(true) { '#' } else { '.' })
+                .collect();
+            writeln!(f, "{}", row)?;
         }
         write!(f, "dimensions: {:?} x {:?}", self.width, self.height)
     }
 }
 
+/// Hashes the terrain and the water, which is all that the match can change.
+impl Hash for Map {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.mask.hash(state);
+        self.water_level.map(f32::to_bits).hash(state);
+    }
+}
+
 
 #[cfg(test)]
+#[allow(clippy::bool_comparison)]
 mod tests {
 
     use super::*;
@@ -114,10 +251,10 @@ mod tests {
         let map = new_map();
         assert_eq!(map.get_width(), 4);
         assert_eq!(map.get_height(), 2);
-        assert_eq!(map.get(0, 1), Some(0));
-        assert_eq!(map.get(1, 1), Some(1));
-        assert_eq!(map.get(2, 1), Some(1));
-        assert_eq!(map.get(3, 1), Some(0));
+        assert_eq!(map.get(0, 1), Some(false));
+        assert_eq!(map.get(1, 1), Some(true));
+        assert_eq!(map.get(2, 1), Some(true));
+        assert_eq!(map.get(3, 1), Some(false));
         assert_eq!(map.get(4, 1), None);
     }
 
@@ -130,6 +267,22 @@ mod tests {
         assert!(map.on_ground(Point2::new(-1.0, 1.0)) == false);
     }
 
+    #[test]
+    fn map_column_queries() {
+        let map = new_map();
+        assert_eq!(map.first_solid_below(Point2::new(1.0, 0.0)), Some(Point2::new(1.0, 1.0)));
+        assert_eq!(map.first_solid_below(Point2::new(0.0, 0.0)), None);
+        assert_eq!(map.first_solid_below(Point2::new(-1.0, 0.0)), None);
+        assert_eq!(map.first_empty_below(Point2::new(2.0, 1.0)), None);
+        assert_eq!(map.first_empty_below(Point2::new(3.0, 0.0)), Some(Point2::new(3.0, 0.0)));
+        assert_eq!(map.first_empty_above(Point2::new(2.0, 1.0)), Some(Point2::new(2.0, 0.0)));
+        assert_eq!(map.first_empty_above(Point2::new(2.0, 0.0)), Some(Point2::new(2.0, 0.0)));
+        assert_eq!(
+            map.raycast(Point2::new(0.0, 0.0), Point2::new(3.0, 1.0)),
+            Some(Point2::new(2.0, 1.0))
+        );
+    }
+
     #[test]
     fn map_handle_collisions() {
         let mut map = new_map();
@@ -137,10 +290,103 @@ mod tests {
         assert!(map.handle_collisions(new_shot(Point2::new(-1.0, 0.0))) == false);
 
         assert!(map.handle_collisions(new_shot(Point2::new(1.0, 1.0,))) == true);
-        assert_eq!(map.get(0, 1), Some(0));
-        assert_eq!(map.get(1, 1), Some(-1));
-        assert_eq!(map.get(2, 1), Some(-1));
-        assert_eq!(map.get(3, 1), Some(0));
+        assert_eq!(map.get(0, 1), Some(false));
+        assert_eq!(map.get(1, 1), Some(false));
+        assert_eq!(map.get(2, 1), Some(false));
+        assert_eq!(map.get(3, 1), Some(false));
         assert_eq!(map.get(4, 1), None);
+        assert_eq!(map.take_edits().len(), 1);
+        assert!(map.take_edits().is_empty());
+    }
+
+    #[test]
+    fn map_carve_circle() {
+        let mut map = new_map();
+        map.carve_circle(Point2::new(2.0, 1.0), 0.0);
+        assert_eq!(map.get(1, 1), Some(true));
+        assert_eq!(map.get(2, 1), Some(false));
+        assert_eq!(
+            map.take_edits(),
+            vec![TerrainEdit::Carve {
+                center: Point2::new(2.0, 1.0),
+                radius: 0.0
+            }]
+        );
+    }
+
+    #[test]
+    fn map_fill_polygon() {
+        let mut map = new_map();
+        map.carve_circle(Point2::new(1.0, 1.0), 1.0);
+        map.fill_polygon(&[
+            Point2::new(0.0, 0.0),
+            Point2::new(1.0, 0.0),
+            Point2::new(1.0, 1.0),
+            Point2::new(0.0, 1.0),
+        ]);
+        assert_eq!(map.get(0, 0), Some(true));
+        assert_eq!(map.get(1, 0), Some(true));
+        assert_eq!(map.get(0, 1), Some(true));
+        assert_eq!(map.get(1, 1), Some(true));
+        assert_eq!(map.get(2, 1), Some(false));
+        assert_eq!(map.get(2, 0), Some(false));
+        assert_eq!(map.take_edits().len(), 2);
+    }
+
+    #[test]
+    fn map_indestructible() {
+        let mut map = new_map();
+        let x = vec![1, 1, 1, 1];
+        let o = vec![0, 0, 0, 0];
+        let image: Vec<&Vec<u8>> = vec![
+            &o, &o, &o, &o,
+            &o, &x, &o, &x,
+        ];
+        let data: Vec<u8> = image.iter().flat_map(|color| color.iter().cloned()).collect();
+        map.set_indestructible(&data);
+        assert_eq!(map.get(3, 1), Some(true));
+
+        map.carve_circle(Point2::new(2.0, 1.0), 2.0);
+        assert_eq!(map.get(1, 1), Some(true));
+        assert_eq!(map.get(2, 1), Some(false));
+        assert_eq!(map.get(3, 1), Some(true));
+    }
+
+    #[test]
+    fn map_under_water() {
+        let mut map = new_map();
+        assert!(!map.under_water(Point2::new(0.0, 100.0)));
+        map.set_water_level(Some(1.0));
+        assert!(!map.under_water(Point2::new(0.0, 0.0)));
+        assert!(map.under_water(Point2::new(0.0, 1.0)));
+    }
+
+    #[test]
+    fn terrain_edit_contains() {
+        let carve = TerrainEdit::Carve {
+            center: Point2::new(2.0, 2.0),
+            radius: 1.0,
+        };
+        assert!(carve.contains(Point2::new(2.0, 3.0)));
+        assert!(!carve.contains(Point2::new(3.0, 3.0)));
+        assert_eq!(carve.bounds(), Rect::new(1.0, 1.0, 2.0, 2.0));
+
+        let mut map = Map::new(&[0; 4 * 25], 5, 5);
+        map.fill_polygon(&[
+            Point2::new(0.0, 0.0),
+            Point2::new(4.0, 0.0),
+            Point2::new(0.0, 4.0),
+        ]);
+        let fill = map.take_edits().remove(0);
+        assert!(fill.contains(Point2::new(1.0, 1.0)));
+        assert!(!fill.contains(Point2::new(3.0, 3.0)));
+        assert_eq!(fill.bounds(), Rect::new(0.0, 0.0, 4.0, 4.0));
+        // Exactly the pixels the fill made solid.
+        for y in 0..5 {
+            for x in 0..5 {
+                let point = Point2::new(x as f32, y as f32);
+                assert_eq!(fill.contains(point), map.get(x, y) == Some(true));
+            }
+        }
     }
 }
@@ -0,0 +1,468 @@
+use ggez::nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::hash::{Hash, Hasher};
+
+/// Solid/empty terrain packed one bit per pixel, 64 pixels per word. The
+/// bits are kept both row by row and column by column, so that scans go a
+/// word at a time in every direction.
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
+#[serde(try_from = "Rows", into = "Rows")]
+pub struct Mask {
+    width: usize,
+    height: usize,
+    stride: usize,
+    words: Vec<u64>,
+    /// Words per column.
+    column_stride: usize,
+    columns: Vec<u64>,
+}
+
+/// The rows of a mask, all that is saved of it.
+#[derive(Clone, Serialize, Deserialize)]
+struct Rows {
+    width: usize,
+    height: usize,
+    stride: usize,
+    words: Vec<u64>,
+}
+
+impl TryFrom<Rows> for Mask {
+    type Error = String;
+
+    fn try_from(rows: Rows) -> Result<Self, String> {
+        let mut mask = Mask::new(rows.width, rows.height);
+        if rows.stride != mask.stride || rows.words.len() != mask.words.len() {
+            return Err(format!(
+                "a {}x{} mask needs {} words of stride {}, not {} of stride {}",
+                rows.width,
+                rows.height,
+                mask.words.len(),
+                mask.stride,
+                rows.words.len(),
+                rows.stride
+            ));
+        }
+        mask.words = rows.words;
+        mask.transpose();
+        Ok(mask)
+    }
+}
+
+/// Hashes the rows only, the columns hold the same bits.
+impl Hash for Mask {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.width.hash(state);
+        self.height.hash(state);
+        self.words.hash(state);
+    }
+}
+
+impl From<Mask> for Rows {
+    fn from(mask: Mask) -> Self {
+        Rows {
+            width: mask.width,
+            height: mask.height,
+            stride: mask.stride,
+            words: mask.words,
+        }
+    }
+}
+
+impl Mask {
+    const BITS: usize = 64;
+
+    pub fn new(width: usize, height: usize) -> Self {
+        let stride = width.div_ceil(Self::BITS);
+        let column_stride = height.div_ceil(Self::BITS);
+        Mask {
+            width,
+            height,
+            stride,
+            words: vec![0; stride * height],
+            column_stride,
+            columns: vec![0; column_stride * width],
+        }
+    }
+
+    /// Rebuilds the columns from the rows.
+    fn transpose(&mut self) {
+        if self.width == 0 {
+            return;
+        }
+        for y in 0..self.height {
+            self.sync_columns(y, 0, self.width - 1);
+        }
+    }
+
+    /// Copies the pixels from `x0` to `x1` inclusive on row `y` to the columns.
+    fn sync_columns(&mut self, y: usize, x0: usize, x1: usize) {
+        let bit = 1u64 << (y % Self::BITS);
+        for x in x0..=x1 {
+            let column = &mut self.columns[x * self.column_stride + y / Self::BITS];
+            if self.words[y * self.stride + x / Self::BITS] & (1 << (x % Self::BITS)) != 0 {
+                *column |= bit;
+            } else {
+                *column &= !bit;
+            }
+        }
+    }
+
+    /// Pixels with a non-zero alpha in the RGBA `data` are solid.
+    pub fn from_alpha(data: &[u8], width: usize, height: usize) -> Self {
+        let mut mask = Mask::new(width, height);
+        let alphas = data.iter().enumerate().filter(|(idx, _)| idx % 4 == 3);
+        for (idx, (_, &val)) in alphas.enumerate() {
+            if val > 0 {
+                mask.set(idx % width, idx / width, true);
+            }
+        }
+        mask
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> Option<bool> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let word = self.words[y * self.stride + x / Self::BITS];
+        Some(word & (1 << (x % Self::BITS)) != 0)
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, solid: bool) {
+        if x < self.width && y < self.height {
+            self.set_span(y, x, x, solid);
+        }
+    }
+
+    /// Sets the pixels from `x0` to `x1` inclusive on row `y`, a word at a time.
+    pub fn set_span(&mut self, y: usize, x0: usize, x1: usize, solid: bool) {
+        if y >= self.height || x0 >= self.width || x0 > x1 {
+            return;
+        }
+        let x1 = x1.min(self.width - 1);
+        let row = y * self.stride;
+        for w in x0 / Self::BITS..=x1 / Self::BITS {
+            let from = if w == x0 / Self::BITS { x0 % Self::BITS } else { 0 };
+            let to = if w == x1 / Self::BITS { x1 % Self::BITS } else { Self::BITS - 1 };
+            let bits = (!0u64 >> (Self::BITS - 1 - to + from)) << from;
+            if solid {
+                self.words[row + w] |= bits;
+            } else {
+                self.words[row + w] &= !bits;
+            }
+        }
+        self.sync_columns(y, x0, x1);
+    }
+
+    /// First pixel at or right of `x` on row `y` that is `solid`.
+    pub fn scan_right(&self, x: usize, y: usize, solid: bool) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let row = y * self.stride;
+        for w in x / Self::BITS..self.stride {
+            let mut word = if solid { self.words[row + w] } else { !self.words[row + w] };
+            if w == x / Self::BITS {
+                word &= !0u64 << (x % Self::BITS);
+            }
+            if word != 0 {
+                let found = w * Self::BITS + word.trailing_zeros() as usize;
+                return if found < self.width { Some(found) } else { None };
+            }
+        }
+        None
+    }
+
+    /// First pixel at or left of `x` on row `y` that is `solid`.
+    pub fn scan_left(&self, x: usize, y: usize, solid: bool) -> Option<usize> {
+        if y >= self.height {
+            return None;
+        }
+        let x = x.min(self.width.checked_sub(1)?);
+        let row = y * self.stride;
+        for w in (0..=x / Self::BITS).rev() {
+            let mut word = if solid { self.words[row + w] } else { !self.words[row + w] };
+            if w == x / Self::BITS {
+                word &= !0u64 >> (Self::BITS - 1 - x % Self::BITS);
+            }
+            if word != 0 {
+                return Some(w * Self::BITS + Self::BITS - 1 - word.leading_zeros() as usize);
+            }
+        }
+        None
+    }
+
+    /// First pixel at or below `y` in column `x` that is `solid`.
+    pub fn scan_down(&self, x: usize, y: usize, solid: bool) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let column = x * self.column_stride;
+        for w in y / Self::BITS..self.column_stride {
+            let mut word = if solid { self.columns[column + w] } else { !self.columns[column + w] };
+            if w == y / Self::BITS {
+                word &= !0u64 << (y % Self::BITS);
+            }
+            if word != 0 {
+                let found = w * Self::BITS + word.trailing_zeros() as usize;
+                return if found < self.height { Some(found) } else { None };
+            }
+        }
+        None
+    }
+
+    /// First pixel at or above `y` in column `x` that is `solid`.
+    pub fn scan_up(&self, x: usize, y: usize, solid: bool) -> Option<usize> {
+        if x >= self.width || y >= self.height {
+            return None;
+        }
+        let column = x * self.column_stride;
+        for w in (0..=y / Self::BITS).rev() {
+            let mut word = if solid { self.columns[column + w] } else { !self.columns[column + w] };
+            if w == y / Self::BITS {
+                word &= !0u64 >> (Self::BITS - 1 - y % Self::BITS);
+            }
+            if word != 0 {
+                return Some(w * Self::BITS + Self::BITS - 1 - word.leading_zeros() as usize);
+            }
+        }
+        None
+    }
+
+    pub fn set_circle(&mut self, center: Point2<f32>, radius: f32, solid: bool) {
+        let top = (center.y - radius).ceil().max(0.0) as usize;
+        let bottom = (center.y + radius).floor();
+        if bottom < 0.0 {
+            return;
+        }
+        for y in top..=bottom as usize {
+            let dy = y as f32 - center.y;
+            let dx = (radius * radius - dy * dy).max(0.0).sqrt();
+            let right = (center.x + dx).floor();
+            if right < 0.0 {
+                continue;
+            }
+            let left = (center.x - dx).ceil().max(0.0) as usize;
+            self.set_span(y, left, right as usize, solid);
+        }
+    }
+
+    /// Sets every pixel inside the convex `polygon`.
+    pub fn set_polygon(&mut self, polygon: &[Point2<f32>], solid: bool) {
+        for (y, x0, x1) in self.polygon_spans(polygon) {
+            self.set_span(y, x0, x1, solid);
+        }
+    }
+
+    /// The pixels inside the convex `polygon` that are on the mask, as
+    /// `(y, x0, x1)` spans of whole rows.
+    pub fn polygon_spans(&self, polygon: &[Point2<f32>]) -> Vec<(usize, usize, usize)> {
+        let mut spans = vec![];
+        if polygon.len() < 3 {
+            return spans;
+        }
+        let top = polygon.iter().map(|p| p.y).fold(f32::MAX, f32::min);
+        let bottom = polygon.iter().map(|p| p.y).fold(f32::MIN, f32::max);
+        if bottom < 0.0 {
+            return spans;
+        }
+        for y in top.ceil().max(0.0) as usize..=(bottom.floor() as usize).min(self.height.saturating_sub(1)) {
+            let row = y as f32;
+            let mut left = f32::MAX;
+            let mut right = f32::MIN;
+            for (i, a) in polygon.iter().enumerate() {
+                let b = polygon[(i + 1) % polygon.len()];
+                if (a.y - row) * (b.y - row) > 0.0 {
+                    continue;
+                }
+                let xs = if a.y == b.y {
+                    (a.x, b.x)
+                } else {
+                    let x = a.x + (row - a.y) * (b.x - a.x) / (b.y - a.y);
+                    (x, x)
+                };
+                left = left.min(xs.0.min(xs.1));
+                right = right.max(xs.0.max(xs.1));
+            }
+            if left <= right && right >= 0.0 {
+                let x0 = left.ceil().max(0.0) as usize;
+                let x1 = (right.floor() as usize).min(self.width.saturating_sub(1));
+                if x0 <= x1 && y < self.height {
+                    spans.push((y, x0, x1));
+                }
+            }
+        }
+        spans
+    }
+
+    /// First solid pixel on the segment from `from` to `to`.
+    pub fn raycast(&self, from: Point2<f32>, to: Point2<f32>) -> Option<Point2<f32>> {
+        let d = to - from;
+        let steps = d.x.abs().max(d.y.abs()).ceil().max(1.0) as usize;
+        for i in 0..=steps {
+            let p = from + d * (i as f32 / steps as f32);
+            let (x, y) = (p.x.round(), p.y.round());
+            if x >= 0.0 && y >= 0.0 && self.get(x as usize, y as usize) == Some(true) {
+                return Some(Point2::new(x, y));
+            }
+        }
+        None
+    }
+
+    /// Makes solid every pixel of rows `y0` to `y1` that is solid in `other`.
+    pub fn merge_rows(&mut self, other: &Mask, y0: usize, y1: usize) {
+        let y1 = y1.min(self.height.min(other.height).saturating_sub(1));
+        if self.stride != other.stride || y0 > y1 {
+            return;
+        }
+        let (from, to) = (y0 * self.stride, (y1 + 1) * self.stride);
+        for (word, other) in self.words[from..to].iter_mut().zip(&other.words[from..to]) {
+            *word |= other;
+        }
+        if self.width > 0 {
+            for y in y0..=y1 {
+                self.sync_columns(y, 0, self.width - 1);
+            }
+        }
+    }
+
+    pub fn count(&self) -> usize {
+        self.words.iter().map(|w| w.count_ones() as usize).sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn mask_set_span() {
+        let mut mask = Mask::new(130, 2);
+        mask.set_span(1, 60, 129, true);
+        assert_eq!(mask.get(59, 1), Some(false));
+        assert_eq!(mask.get(60, 1), Some(true));
+        assert_eq!(mask.get(64, 1), Some(true));
+        assert_eq!(mask.get(129, 1), Some(true));
+        assert_eq!(mask.get(130, 1), None);
+        assert_eq!(mask.count(), 70);
+
+        mask.set_span(1, 63, 64, false);
+        assert_eq!(mask.get(63, 1), Some(false));
+        assert_eq!(mask.get(64, 1), Some(false));
+        assert_eq!(mask.count(), 68);
+    }
+
+    #[test]
+    fn mask_scans() {
+        let mut mask = Mask::new(130, 3);
+        mask.set(100, 1, true);
+        mask.set(5, 1, true);
+        assert_eq!(mask.scan_right(6, 1, true), Some(100));
+        assert_eq!(mask.scan_right(101, 1, true), None);
+        assert_eq!(mask.scan_right(5, 1, false), Some(6));
+        assert_eq!(mask.scan_left(99, 1, true), Some(5));
+        assert_eq!(mask.scan_left(4, 1, true), None);
+        assert_eq!(mask.scan_left(129, 1, true), Some(100));
+        assert_eq!(mask.scan_down(100, 0, true), Some(1));
+        assert_eq!(mask.scan_down(100, 2, true), None);
+        assert_eq!(mask.scan_up(5, 2, true), Some(1));
+        assert_eq!(mask.scan_up(5, 1, false), Some(0));
+    }
+
+    #[test]
+    fn mask_scans_columns() {
+        let mut mask = Mask::new(3, 200);
+        mask.set_span(150, 0, 2, true);
+        mask.set(1, 10, true);
+        assert_eq!(mask.scan_down(1, 0, true), Some(10));
+        assert_eq!(mask.scan_down(1, 11, true), Some(150));
+        assert_eq!(mask.scan_down(0, 151, true), None);
+        assert_eq!(mask.scan_down(0, 150, false), Some(151));
+        assert_eq!(mask.scan_up(1, 149, true), Some(10));
+        assert_eq!(mask.scan_up(1, 199, true), Some(150));
+        assert_eq!(mask.scan_up(2, 150, false), Some(149));
+        assert_eq!(mask.scan_down(3, 0, true), None);
+
+        mask.set_circle(Point2::new(1.0, 150.0), 1.0, false);
+        assert_eq!(mask.scan_down(1, 11, true), None);
+        let json = serde_json::to_string(&mask).unwrap();
+        assert!(!json.contains("columns"));
+        let loaded: Mask = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.scan_down(1, 0, true), Some(10));
+        assert!(loaded == mask);
+
+        let short = json.replacen("\"stride\":1", "\"stride\":2", 1);
+        assert!(serde_json::from_str::<Mask>(&short).is_err());
+        let truncated = r#"{"width":3,"height":200,"stride":1,"words":[1,2]}"#;
+        assert!(serde_json::from_str::<Mask>(truncated).is_err());
+    }
+
+    #[test]
+    fn mask_set_circle() {
+        let mut mask = Mask::new(5, 5);
+        mask.set_circle(Point2::new(2.0, 2.0), 1.0, true);
+        assert_eq!(mask.count(), 5);
+        assert_eq!(mask.get(2, 1), Some(true));
+        assert_eq!(mask.get(1, 2), Some(true));
+        assert_eq!(mask.get(1, 1), Some(false));
+
+        mask.set_circle(Point2::new(0.0, 0.0), 10.0, false);
+        assert_eq!(mask.count(), 0);
+    }
+
+    #[test]
+    fn mask_set_polygon() {
+        let mut mask = Mask::new(5, 5);
+        mask.set_polygon(
+            &[
+                Point2::new(0.0, 0.0),
+                Point2::new(4.0, 0.0),
+                Point2::new(0.0, 4.0),
+            ],
+            true,
+        );
+        assert_eq!(mask.get(0, 4), Some(true));
+        assert_eq!(mask.get(2, 2), Some(true));
+        assert_eq!(mask.get(3, 2), Some(false));
+        assert_eq!(mask.count(), 15);
+
+        let spans = mask.polygon_spans(&[
+            Point2::new(3.5, -1.0),
+            Point2::new(9.0, 2.5),
+            Point2::new(3.5, 2.5),
+        ]);
+        assert_eq!(spans, vec![(0, 4, 4), (1, 4, 4), (2, 4, 4)]);
+    }
+
+    #[test]
+    fn mask_merge_rows() {
+        let mut mask = Mask::new(3, 3);
+        let mut other = Mask::new(3, 3);
+        other.set_span(0, 0, 2, true);
+        other.set_span(2, 0, 2, true);
+        mask.merge_rows(&other, 1, 5);
+        assert_eq!(mask.count(), 3);
+        assert_eq!(mask.get(1, 2), Some(true));
+        assert_eq!(mask.get(1, 0), Some(false));
+    }
+
+    #[test]
+    fn mask_raycast() {
+        let mut mask = Mask::new(10, 10);
+        mask.set_span(5, 0, 9, true);
+        assert_eq!(
+            mask.raycast(Point2::new(2.0, 0.0), Point2::new(2.0, 9.0)),
+            Some(Point2::new(2.0, 5.0))
+        );
+        assert_eq!(mask.raycast(Point2::new(0.0, 0.0), Point2::new(9.0, 4.0)), None);
+    }
+}
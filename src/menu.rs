@@ -0,0 +1,150 @@
+use ggez::event::KeyCode;
+use ggez::graphics::{self, DrawParam, Text};
+use ggez::nalgebra::Point2;
+use ggez::{Context, GameResult};
+
+/// A vertical list of items navigated with the arrow keys.
+pub struct Menu {
+    title: String,
+    items: Vec<String>,
+    selected: usize,
+    message: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MenuAction {
+    Activate(usize),
+    /// Left (-1) or right (1) on an item.
+    Change(usize, i32),
+    Back,
+}
+
+impl Menu {
+    const LINE_HEIGHT: f32 = 36.0;
+    const TITLE_SCALE: f32 = 40.0;
+    const ITEM_SCALE: f32 = 24.0;
+    const SELECTED_COLOR: graphics::Color = graphics::Color {
+        r: 1.0,
+        g: 0.85,
+        b: 0.2,
+        a: 1.0,
+    };
+    const MESSAGE_COLOR: graphics::Color = graphics::Color {
+        r: 1.0,
+        g: 0.4,
+        b: 0.4,
+        a: 1.0,
+    };
+
+    pub fn new(title: &str, items: Vec<String>) -> Self {
+        Menu {
+            title: title.to_owned(),
+            items,
+            selected: 0,
+            message: None,
+        }
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    pub fn select(&mut self, idx: usize) {
+        self.selected = idx.min(self.items.len().saturating_sub(1));
+    }
+
+    pub fn set_items(&mut self, items: Vec<String>) {
+        self.selected = self.selected.min(items.len().saturating_sub(1));
+        self.items = items;
+    }
+
+    /// A line shown under the items, e.g. why a match could not start.
+    pub fn set_message(&mut self, message: Option<String>) {
+        self.message = message;
+    }
+
+    pub fn key_down(&mut self, keycode: KeyCode) -> Option<MenuAction> {
+        if self.items.is_empty() {
+            return None;
+        }
+        match keycode {
+            KeyCode::Up => {
+                self.selected = (self.selected + self.items.len() - 1) % self.items.len();
+                None
+            }
+            KeyCode::Down => {
+                self.selected = (self.selected + 1) % self.items.len();
+                None
+            }
+            KeyCode::Left => Some(MenuAction::Change(self.selected, -1)),
+            KeyCode::Right => Some(MenuAction::Change(self.selected, 1)),
+            KeyCode::Return | KeyCode::Space => Some(MenuAction::Activate(self.selected)),
+            KeyCode::Escape => Some(MenuAction::Back),
+            _ => None,
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        let screen = graphics::screen_coordinates(ctx);
+        let mut title = Text::new(self.title.as_str());
+        title.set_font(graphics::Font::default(), graphics::Scale::uniform(Self::TITLE_SCALE));
+        let x = screen.x + screen.w / 3.0;
+        let mut y = screen.y + screen.h / 5.0;
+        graphics::draw(ctx, &title, DrawParam::default().dest(Point2::new(x, y)))?;
+        y += 2.0 * Self::LINE_HEIGHT;
+
+        for (i, item) in self.items.iter().enumerate() {
+            let (line, color) = if i == self.selected {
+                (format!("> {}", item), Self::SELECTED_COLOR)
+            } else {
+                (format!("  {}", item), graphics::WHITE)
+            };
+            let mut text = Text::new(line);
+            text.set_font(graphics::Font::default(), graphics::Scale::uniform(Self::ITEM_SCALE));
+            graphics::draw(ctx, &text, DrawParam::default().dest(Point2::new(x, y)).color(color))?;
+            y += Self::LINE_HEIGHT;
+        }
+
+        if let Some(message) = &self.message {
+            let text = Text::new(message.as_str());
+            y += Self::LINE_HEIGHT;
+            graphics::draw(
+                ctx,
+                &text,
+                DrawParam::default().dest(Point2::new(x, y)).color(Self::MESSAGE_COLOR),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn new_menu() -> Menu {
+        Menu::new("Crabs!", vec!["a".to_owned(), "b".to_owned(), "c".to_owned()])
+    }
+
+    #[test]
+    fn menu_navigation() {
+        let mut menu = new_menu();
+        assert_eq!(menu.key_down(KeyCode::Up), None);
+        assert_eq!(menu.selected(), 2);
+        menu.key_down(KeyCode::Down);
+        assert_eq!(menu.selected(), 0);
+        menu.key_down(KeyCode::Down);
+        assert_eq!(menu.key_down(KeyCode::Return), Some(MenuAction::Activate(1)));
+        assert_eq!(menu.key_down(KeyCode::Left), Some(MenuAction::Change(1, -1)));
+        assert_eq!(menu.key_down(KeyCode::Escape), Some(MenuAction::Back));
+    }
+
+    #[test]
+    fn menu_set_items() {
+        let mut menu = new_menu();
+        menu.key_down(KeyCode::Up);
+        menu.set_items(vec!["a".to_owned()]);
+        assert_eq!(menu.selected(), 0);
+    }
+}
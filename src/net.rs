@@ -0,0 +1,904 @@
+use crate::outcome::MatchResult;
+use crate::settings::{Control, MatchSettings};
+use crate::simulation::TickInput;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::io::{self, Read, Write};
+use std::net::{Shutdown, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+pub const DEFAULT_PORT: u16 = 7878;
+/// Bumped whenever the messages or the simulation change, peers of
+/// different versions can't play together.
+pub const PROTOCOL_VERSION: u32 = 8;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+/// Longest line a client may send. What it sends the host or the server is
+/// a few hundred bytes at most, anything longer is someone flooding it.
+const MAX_LINE: usize = 16 * 1024;
+/// Longest line from the host, whose `Watch` carries the whole match.
+const MAX_HOST_LINE: usize = 64 * 1024 * 1024;
+
+/// What peers send each other, one JSON object per line.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Message {
+    /// The first message of a client, spectators only watch the match.
+    Hello { version: u32, spectator: bool },
+    /// The host starts the match, `teams` are the ones the receiver plays.
+    /// On an `authoritative` server the clients only simulate what the
+    /// server sends back, their own inputs included.
+    Start {
+        settings: MatchSettings,
+        teams: Vec<usize>,
+        authoritative: bool,
+    },
+    /// The match as it is now, for a spectator joining it. The inputs of
+    /// the ticks after it follow.
+    Watch {
+        settings: MatchSettings,
+        state: serde_json::Value,
+        authoritative: bool,
+    },
+    /// What the active team did during `tick`.
+    Input { tick: u64, input: TickInput },
+    /// Hash of the simulation after `tick`.
+    Hash { tick: u64, hash: u64 },
+    /// The simulations of the peers went different ways by `tick`.
+    Desync { tick: u64 },
+    /// How the match ended, decided by the server.
+    Result { result: MatchResult },
+}
+
+/// A TCP stream that sends and receives whole messages without blocking.
+pub struct Connection {
+    stream: TcpStream,
+    incoming: Vec<u8>,
+    outgoing: Vec<u8>,
+    closed: bool,
+    /// Longest line taken in before the connection is dropped.
+    max_line: usize,
+}
+
+impl Connection {
+    pub fn new(stream: TcpStream) -> io::Result<Self> {
+        stream.set_nonblocking(true)?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            incoming: vec![],
+            outgoing: vec![],
+            closed: false,
+            max_line: MAX_LINE,
+        })
+    }
+
+    /// Connects to `address`, given as `host:port` or just `host`.
+    pub fn connect(address: &str) -> io::Result<Self> {
+        let address = if address.contains(':') {
+            address.to_owned()
+        } else {
+            format!("{}:{}", address, DEFAULT_PORT)
+        };
+        let mut last_error = io::Error::new(io::ErrorKind::NotFound, "unknown address");
+        for addr in address.to_socket_addrs()? {
+            match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+                Ok(stream) => {
+                    let mut connection = Connection::new(stream)?;
+                    connection.max_line = MAX_HOST_LINE;
+                    return Ok(connection);
+                }
+                Err(e) => last_error = e,
+            }
+        }
+        Err(last_error)
+    }
+
+    pub fn peer_addr(&self) -> Option<SocketAddr> {
+        self.stream.peer_addr().ok()
+    }
+
+    pub fn send(&mut self, message: &Message) -> io::Result<()> {
+        serde_json::to_writer(&mut self.outgoing, message)?;
+        self.outgoing.push(b'\n');
+        self.flush()
+    }
+
+    /// Writes as much of the queued messages as the socket takes.
+    pub fn flush(&mut self) -> io::Result<()> {
+        while !self.outgoing.is_empty() {
+            match self.stream.write(&self.outgoing) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(written) => {
+                    self.outgoing.drain(..written);
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(())
+    }
+
+    /// The messages that arrived since the last call. Fails once the other
+    /// side is gone and everything it sent was received, or as soon as it
+    /// sends a line longer than `max_line`, which drops the connection.
+    pub fn receive(&mut self) -> io::Result<Vec<Message>> {
+        self.flush()?;
+        let mut buffer = [0; 4096];
+        while !self.closed {
+            match self.stream.read(&mut buffer) {
+                Ok(0) => self.closed = true,
+                Ok(read) => {
+                    self.incoming.extend_from_slice(&buffer[..read]);
+                    let start = self.incoming.iter().rposition(|&byte| byte == b'\n').map_or(0, |end| end + 1);
+                    if self.incoming.len() - start > self.max_line {
+                        return Err(self.drop_overlong());
+                    }
+                }
+                Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => break,
+                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => (),
+                Err(e) => return Err(e),
+            }
+        }
+        let mut messages = vec![];
+        while let Some(end) = self.incoming.iter().position(|&byte| byte == b'\n') {
+            if end > self.max_line {
+                return Err(self.drop_overlong());
+            }
+            let line: Vec<u8> = self.incoming.drain(..=end).collect();
+            messages.push(serde_json::from_slice(&line)?);
+        }
+        if self.closed && messages.is_empty() {
+            return Err(io::ErrorKind::ConnectionAborted.into());
+        }
+        Ok(messages)
+    }
+
+    /// Hangs up on a peer that sent a line longer than any message.
+    fn drop_overlong(&mut self) -> io::Error {
+        self.closed = true;
+        self.incoming.clear();
+        self.stream.shutdown(Shutdown::Both).ok();
+        io::Error::new(io::ErrorKind::InvalidData, "message too long")
+    }
+}
+
+/// The teams every peer plays, the host being peer 0. Human teams are dealt
+/// out in order, AI teams are simulated by everyone.
+pub fn assign_teams(settings: &MatchSettings, peers: usize) -> Vec<Vec<usize>> {
+    let mut teams = vec![vec![]; peers.max(1)];
+    let humans = settings
+        .teams
+        .iter()
+        .enumerate()
+        .filter(|(_, team)| team.control == Control::Human);
+    for (n, (i, _)) in humans.enumerate() {
+        teams[n % peers.max(1)].push(i);
+    }
+    teams
+}
+
+/// The clients of `pending` that said hello in our version, and whether
+/// they are spectators. Those that left or sent anything else are dropped.
+fn greet(pending: &mut Vec<Connection>) -> Vec<(Connection, bool)> {
+    let mut greeted = vec![];
+    let mut waiting = vec![];
+    for mut client in pending.drain(..) {
+        if let Ok(messages) = client.receive() {
+            match messages.first() {
+                Some(Message::Hello { version, spectator }) if *version == PROTOCOL_VERSION => {
+                    greeted.push((client, *spectator))
+                }
+                Some(_) => (),
+                None => waiting.push(client),
+            }
+        }
+    }
+    *pending = waiting;
+    greeted
+}
+
+/// Takes in the connections waiting on `listener`.
+fn accept_all(listener: &TcpListener, pending: &mut Vec<Connection>) -> io::Result<()> {
+    loop {
+        match listener.accept() {
+            Ok((stream, _)) => pending.push(Connection::new(stream)?),
+            Err(ref e) if e.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Nothing is expected from a client before the match starts, or from a
+/// spectator at all, so a failed read means it left.
+fn drop_leavers(connections: &mut Vec<Connection>) {
+    let mut staying = vec![];
+    for mut connection in connections.drain(..) {
+        if connection.receive().is_ok() {
+            staying.push(connection);
+        }
+    }
+    *connections = staying;
+}
+
+/// Waits for clients to join a match hosted on this machine.
+pub struct Host {
+    listener: TcpListener,
+    clients: Vec<Connection>,
+    spectators: Vec<Connection>,
+    /// Clients that connected but haven't said hello yet.
+    pending: Vec<Connection>,
+}
+
+impl Host {
+    /// Listens on every interface, port 0 picks a free one.
+    pub fn bind(port: u16) -> io::Result<Self> {
+        let listener = TcpListener::bind(("0.0.0.0", port))?;
+        listener.set_nonblocking(true)?;
+        Ok(Host {
+            listener,
+            clients: vec![],
+            spectators: vec![],
+            pending: vec![],
+        })
+    }
+
+    pub fn port(&self) -> u16 {
+        self.listener.local_addr().map_or(0, |addr| addr.port())
+    }
+
+    /// Addresses of the clients that joined.
+    pub fn clients(&self) -> Vec<String> {
+        self.clients
+            .iter()
+            .map(|client| client.peer_addr().map_or(String::from("?"), |addr| addr.to_string()))
+            .collect()
+    }
+
+    /// Spectators waiting for the match.
+    pub fn spectators(&self) -> usize {
+        self.spectators.len()
+    }
+
+    /// Takes in new clients and drops the ones that left or don't speak our
+    /// version.
+    pub fn accept(&mut self) -> io::Result<()> {
+        accept_all(&self.listener, &mut self.pending)?;
+        for (client, spectator) in greet(&mut self.pending) {
+            if spectator {
+                self.spectators.push(client);
+            } else {
+                self.clients.push(client);
+            }
+        }
+        drop_leavers(&mut self.clients);
+        drop_leavers(&mut self.spectators);
+        Ok(())
+    }
+
+    /// Tells every client what the match is and which teams it plays.
+    pub fn start(mut self, settings: &MatchSettings) -> io::Result<Lockstep> {
+        let teams = assign_teams(settings, self.clients.len() + 1);
+        for (client, teams) in self.clients.iter_mut().zip(teams.iter().skip(1)) {
+            client.send(&Message::Start {
+                settings: settings.clone(),
+                teams: teams.clone(),
+                authoritative: false,
+            })?;
+        }
+        let mut lockstep = Lockstep::new(Role::Host(self.clients), teams[0].clone(), false);
+        lockstep.spectators = Spectators::new(Some(self.listener), self.spectators);
+        Ok(lockstep)
+    }
+
+    /// The clients that joined with their addresses, and the spectators, for
+    /// a server that runs the match itself.
+    pub fn into_clients(self) -> (Vec<(Connection, String)>, Spectators) {
+        let addresses = self.clients();
+        let spectators = Spectators::new(Some(self.listener), self.spectators);
+        (self.clients.into_iter().zip(addresses).collect(), spectators)
+    }
+}
+
+/// Spectators of a running match. They can join at any time: they get the
+/// match as it is once, then every message the players get.
+pub struct Spectators {
+    listener: Option<TcpListener>,
+    /// Connected, but haven't said hello yet.
+    pending: Vec<Connection>,
+    /// Waiting for the state of the match.
+    joining: Vec<Connection>,
+    watching: Vec<Connection>,
+}
+
+impl Spectators {
+    fn new(listener: Option<TcpListener>, joining: Vec<Connection>) -> Self {
+        Spectators {
+            listener,
+            pending: vec![],
+            joining,
+            watching: vec![],
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.joining.len() + self.watching.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Takes in new spectators and drops the ones that left. Players can't
+    /// join a running match and are turned away.
+    pub fn accept(&mut self) -> io::Result<()> {
+        if let Some(listener) = &self.listener {
+            accept_all(listener, &mut self.pending)?;
+        }
+        for (client, spectator) in greet(&mut self.pending) {
+            if spectator {
+                self.joining.push(client);
+            }
+        }
+        drop_leavers(&mut self.joining);
+        drop_leavers(&mut self.watching);
+        Ok(())
+    }
+
+    /// Whether someone is waiting for `send_state`.
+    pub fn wants_state(&self) -> bool {
+        !self.joining.is_empty()
+    }
+
+    /// Sends the match as it is now to the spectators that joined since the
+    /// last call, with the messages already sent for the ticks after it.
+    pub fn send_state(
+        &mut self,
+        settings: &MatchSettings,
+        state: &serde_json::Value,
+        authoritative: bool,
+        queued: &[Message],
+    ) {
+        let watch = Message::Watch {
+            settings: settings.clone(),
+            state: state.clone(),
+            authoritative,
+        };
+        for mut spectator in self.joining.drain(..) {
+            let sent = std::iter::once(&watch)
+                .chain(queued)
+                .try_for_each(|message| spectator.send(message));
+            if sent.is_ok() {
+                self.watching.push(spectator);
+            }
+        }
+    }
+
+    /// Sends `message` to the spectators watching, dropping those that left.
+    pub fn broadcast(&mut self, message: &Message) {
+        let mut watching = vec![];
+        for mut spectator in self.watching.drain(..) {
+            if spectator.send(message).is_ok() {
+                watching.push(spectator);
+            }
+        }
+        self.watching = watching;
+    }
+}
+
+/// A client waiting for the host to start the match.
+pub struct Join {
+    connection: Connection,
+}
+
+impl Join {
+    pub fn connect(address: &str) -> io::Result<Self> {
+        Self::open(address, false)
+    }
+
+    /// Joins to watch the match, also once it started.
+    pub fn spectate(address: &str) -> io::Result<Self> {
+        Self::open(address, true)
+    }
+
+    fn open(address: &str, spectator: bool) -> io::Result<Self> {
+        let mut connection = Connection::connect(address)?;
+        connection.send(&Message::Hello {
+            version: PROTOCOL_VERSION,
+            spectator,
+        })?;
+        Ok(Join { connection })
+    }
+
+    /// The match once the host started it, or the join back while waiting.
+    /// Spectators find the state to start from in the lockstep.
+    pub fn poll(mut self) -> io::Result<Result<(MatchSettings, Lockstep), Join>> {
+        let mut messages = self.connection.receive()?.into_iter();
+        while let Some(message) = messages.next() {
+            let (settings, mut lockstep) = match message {
+                Message::Start {
+                    settings,
+                    teams,
+                    authoritative,
+                } => (settings, Lockstep::new(Role::Client(self.connection), teams, authoritative)),
+                Message::Watch {
+                    settings,
+                    state,
+                    authoritative,
+                } => {
+                    let mut lockstep = Lockstep::new(Role::Client(self.connection), vec![], authoritative);
+                    lockstep.spectating = true;
+                    lockstep.state = Some(state);
+                    (settings, lockstep)
+                }
+                _ => continue,
+            };
+            // The first ticks may have come along with the start.
+            for message in messages {
+                lockstep.handle(0, message);
+            }
+            return Ok(Ok((settings, lockstep)));
+        }
+        Ok(Err(self))
+    }
+}
+
+enum Role {
+    /// Relays the inputs of every client to the others and checks the hashes.
+    Host(Vec<Connection>),
+    Client(Connection),
+}
+
+/// Keeps the simulations of the peers of a match in step: the peer playing
+/// the active team sends what it does every tick, the others simulate the
+/// tick once it arrived. Every `HASH_INTERVAL` ticks the peers send the host
+/// a hash of their simulation, which tells everyone when they drifted apart.
+///
+/// Against an authoritative server the client never simulates its own input
+/// right away: the server picks the input of every tick and sends it, along
+/// with its hashes and the result, to all clients.
+pub struct Lockstep {
+    role: Role,
+    /// The teams played on this peer.
+    teams: Vec<usize>,
+    authoritative: bool,
+    /// Watching only, the peer never sends anything.
+    spectating: bool,
+    /// Where a spectator starts from, until the game takes it.
+    state: Option<serde_json::Value>,
+    /// Spectators of the match, on the host.
+    spectators: Spectators,
+    inputs: BTreeMap<u64, TickInput>,
+    /// Hashes of this peer, and of the clients until the host has its own.
+    hashes: BTreeMap<u64, u64>,
+    remote_hashes: Vec<(u64, u64)>,
+    desync: Option<u64>,
+    error: Option<String>,
+    /// The result sent by an authoritative server.
+    result: Option<MatchResult>,
+}
+
+impl Lockstep {
+    pub const HASH_INTERVAL: u64 = 30;
+
+    fn new(role: Role, teams: Vec<usize>, authoritative: bool) -> Self {
+        Lockstep {
+            role,
+            teams,
+            authoritative,
+            spectating: false,
+            state: None,
+            spectators: Spectators::new(None, vec![]),
+            inputs: BTreeMap::new(),
+            hashes: BTreeMap::new(),
+            remote_hashes: vec![],
+            desync: None,
+            error: None,
+            result: None,
+        }
+    }
+
+    pub fn is_host(&self) -> bool {
+        matches!(self.role, Role::Host(_))
+    }
+
+    /// Whether the other side is a server that decides every tick.
+    pub fn is_authoritative(&self) -> bool {
+        self.authoritative
+    }
+
+    pub fn is_spectating(&self) -> bool {
+        self.spectating
+    }
+
+    /// The match as it was when a spectator joined.
+    pub fn take_state(&mut self) -> Option<serde_json::Value> {
+        self.state.take()
+    }
+
+    /// Whether a spectator is waiting for `send_state`.
+    pub fn wants_state(&self) -> bool {
+        self.spectators.wants_state()
+    }
+
+    /// Lets the spectators that joined since the last call in on the match
+    /// at `state`, with the inputs received for later ticks.
+    pub fn send_state(&mut self, settings: &MatchSettings, state: &serde_json::Value) {
+        let queued: Vec<Message> = self
+            .inputs
+            .iter()
+            .map(|(&tick, input)| Message::Input {
+                tick,
+                input: input.clone(),
+            })
+            .collect();
+        self.spectators.send_state(settings, state, self.authoritative, &queued);
+    }
+
+    pub fn teams(&self) -> &[usize] {
+        &self.teams
+    }
+
+    pub fn is_local(&self, team: usize) -> bool {
+        self.teams.contains(&team)
+    }
+
+    /// The tick where the peers drifted apart.
+    pub fn desync(&self) -> Option<u64> {
+        self.desync
+    }
+
+    /// Why the match can't go on, e.g. a peer left.
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    /// How the match ended according to an authoritative server.
+    pub fn result(&self) -> Option<MatchResult> {
+        self.result
+    }
+
+    /// Inputs received and not simulated yet.
+    pub fn queued(&self) -> usize {
+        self.inputs.len()
+    }
+
+    pub fn send_input(&mut self, tick: u64, input: &TickInput) {
+        self.broadcast(&Message::Input {
+            tick,
+            input: input.clone(),
+        });
+    }
+
+    /// The input of another peer for `tick`, once it arrived. An
+    /// authoritative server sends the input of every tick.
+    pub fn take_input(&mut self, tick: u64) -> Option<TickInput> {
+        self.inputs.remove(&tick)
+    }
+
+    /// Whether the hash of the simulation after `tick` is compared, only
+    /// every `HASH_INTERVAL` ticks. Hashing the whole state every tick
+    /// would be a waste.
+    pub fn wants_hash(&self, tick: u64) -> bool {
+        tick.is_multiple_of(Self::HASH_INTERVAL) && (self.authoritative || !self.spectating)
+    }
+
+    /// Compares the hash of this peer's simulation after `tick` with the
+    /// others, when it `wants_hash`.
+    pub fn record_hash(&mut self, tick: u64, hash: u64) {
+        if !self.wants_hash(tick) {
+            return;
+        }
+        match &mut self.role {
+            Role::Client(connection) if !self.authoritative => {
+                if let Err(e) = connection.send(&Message::Hash { tick, hash }) {
+                    self.error = Some(format!("Lost the host: {}", e));
+                }
+            }
+            _ => {
+                self.hashes.insert(tick, hash);
+                let remote = std::mem::take(&mut self.remote_hashes);
+                for (tick, hash) in remote {
+                    self.check_hash(tick, hash);
+                }
+            }
+        }
+    }
+
+    /// Receives what the other peers sent.
+    pub fn poll(&mut self) {
+        let mut received = vec![];
+        match &mut self.role {
+            Role::Host(clients) => {
+                // Spectators come and go, that doesn't stop the match.
+                self.spectators.accept().ok();
+                for (i, client) in clients.iter_mut().enumerate() {
+                    match client.receive() {
+                        Ok(messages) => received.extend(messages.into_iter().map(|m| (i, m))),
+                        Err(e) => self.error = Some(format!("A player left: {}", e)),
+                    }
+                }
+            }
+            Role::Client(connection) => match connection.receive() {
+                Ok(messages) => received.extend(messages.into_iter().map(|m| (0, m))),
+                Err(e) => self.error = Some(format!("Lost the host: {}", e)),
+            },
+        }
+        for (from, message) in received {
+            self.handle(from, message);
+        }
+    }
+
+    /// Handles a message from peer `from`, 0 on the clients.
+    fn handle(&mut self, from: usize, message: Message) {
+        match message {
+            Message::Input { tick, input } => {
+                if let Role::Host(clients) = &mut self.role {
+                    let relayed = Message::Input {
+                        tick,
+                        input: input.clone(),
+                    };
+                    for (i, client) in clients.iter_mut().enumerate() {
+                        if i != from {
+                            if let Err(e) = client.send(&relayed) {
+                                self.error = Some(format!("A player left: {}", e));
+                            }
+                        }
+                    }
+                    self.spectators.broadcast(&relayed);
+                }
+                self.inputs.insert(tick, input);
+            }
+            Message::Hash { tick, hash } => self.check_hash(tick, hash),
+            Message::Desync { tick } => self.desync = Some(tick),
+            Message::Result { result } => self.result = Some(result),
+            Message::Hello { .. } | Message::Start { .. } | Message::Watch { .. } => (),
+        }
+    }
+
+    /// Checks the hash a client sent on the host, or the one the server sent
+    /// on its clients.
+    fn check_hash(&mut self, tick: u64, hash: u64) {
+        match self.hashes.get(&tick) {
+            None => self.remote_hashes.push((tick, hash)),
+            Some(&own) if own != hash && self.desync.is_none() => {
+                self.desync = Some(tick);
+                self.broadcast(&Message::Desync { tick });
+            }
+            Some(_) => (),
+        }
+    }
+
+    fn broadcast(&mut self, message: &Message) {
+        let result = match &mut self.role {
+            Role::Host(clients) => {
+                self.spectators.broadcast(message);
+                clients.iter_mut().try_for_each(|client| client.send(message))
+            }
+            Role::Client(connection) => connection.send(message),
+        };
+        if let Err(e) = result {
+            self.error = Some(format!("Connection lost: {}", e));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+    use crate::map::Map;
+    use crate::settings::MapSource;
+    use crate::simulation::{Command, Simulation};
+    use crate::terrain;
+    use crate::weapon::WeaponType;
+
+    /// Polls until `done` or a couple of seconds passed.
+    fn wait(mut done: impl FnMut() -> bool) {
+        for _ in 0..300 {
+            if done() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("timed out");
+    }
+
+    fn connect() -> (MatchSettings, Lockstep, Lockstep) {
+        let mut host = Host::bind(0).unwrap();
+        let mut join = Some(Join::connect(&format!("127.0.0.1:{}", host.port())).unwrap());
+        wait(|| {
+            host.accept().unwrap();
+            host.clients().len() == 1
+        });
+        let mut settings = MatchSettings::new(&CONFIG);
+        settings.map = MapSource::Generated { seed: 11 };
+        let host = host.start(&settings).unwrap();
+        let mut client = None;
+        wait(|| match join.take().unwrap().poll().unwrap() {
+            Ok(started) => {
+                client = Some(started);
+                true
+            }
+            Err(waiting) => {
+                join = Some(waiting);
+                false
+            }
+        });
+        let (received, client) = client.unwrap();
+        assert_eq!(received, settings);
+        (settings, host, client)
+    }
+
+    #[test]
+    fn net_drops_overlong_lines() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let mut flood = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let mut connection = Connection::new(listener.accept().unwrap().0).unwrap();
+        let line = serde_json::to_vec(&Message::Hash { tick: 1, hash: 2 }).unwrap();
+        flood.write_all(&line).unwrap();
+        flood.write_all(b"\n").unwrap();
+        let mut received = vec![];
+        wait(|| {
+            received.extend(connection.receive().unwrap());
+            !received.is_empty()
+        });
+        assert_eq!(received, vec![Message::Hash { tick: 1, hash: 2 }]);
+
+        // No newline ever comes.
+        let garbage = vec![b'1'; MAX_LINE];
+        let mut error = None;
+        wait(|| {
+            flood.write_all(&garbage).ok();
+            match connection.receive() {
+                Ok(messages) => {
+                    assert!(messages.is_empty());
+                    false
+                }
+                Err(e) => {
+                    error = Some(e.kind());
+                    true
+                }
+            }
+        });
+        assert_eq!(error, Some(io::ErrorKind::InvalidData));
+        assert!(connection.incoming.is_empty());
+    }
+
+    #[test]
+    fn net_assign_teams() {
+        let mut settings = MatchSettings::new(&CONFIG);
+        settings.add_team(&CONFIG);
+        settings.add_team(&CONFIG);
+        settings.teams[3].control = Control::Human;
+        assert_eq!(assign_teams(&settings, 2), vec![vec![0, 3], vec![1]]);
+        assert_eq!(assign_teams(&settings, 1), vec![vec![0, 1, 3]]);
+    }
+
+    #[test]
+    fn net_lockstep_over_loopback() {
+        let (settings, mut host, mut client) = connect();
+        assert!(host.is_host() && !client.is_host());
+        assert_eq!(host.teams(), &[0]);
+        assert_eq!(client.teams(), &[1]);
+
+        let (width, height) = (CONFIG.screen.width as u16, CONFIG.screen.height as u16);
+        let new_simulation = || {
+            let map = Map::new(&terrain::generate(width, height, 11), width, height);
+            Simulation::new(&CONFIG, settings.clone(), map, None).unwrap()
+        };
+        let (mut on_host, mut on_client) = (new_simulation(), new_simulation());
+        let script = |tick: u64| TickInput {
+            movement: if tick % 40 < 20 { 1.0 } else { -1.0 },
+            aim: -0.5,
+            fine_aim: false,
+            commands: match tick {
+                5 => vec![Command::SelectWeapon(WeaponType::Bazooka)],
+                20 => vec![Command::Fire { power: 0.8 }],
+                _ => vec![],
+            },
+        };
+        for _ in 0..150 {
+            let tick = on_host.tick() + 1;
+            let team = on_host.active_player_idx();
+            let (player, watcher, playing, watching) = if host.is_local(team) {
+                (&mut host, &mut client, &mut on_host, &mut on_client)
+            } else {
+                (&mut client, &mut host, &mut on_client, &mut on_host)
+            };
+            let input = script(tick);
+            player.send_input(tick, &input);
+            playing.step(&input);
+            let mut received = None;
+            wait(|| {
+                watcher.poll();
+                received = watcher.take_input(tick);
+                received.is_some()
+            });
+            assert_eq!(received.as_ref(), Some(&input));
+            watching.step(&input);
+            host.record_hash(tick, on_host.state_hash());
+            client.record_hash(tick, on_client.state_hash());
+        }
+        assert!(on_host.players()[0].current_crab().is_some());
+        host.poll();
+        assert_eq!(host.desync(), None);
+        assert_eq!(host.error(), None);
+        assert_eq!(on_host.state_hash(), on_client.state_hash());
+    }
+
+    #[test]
+    fn net_detects_desync() {
+        let (_, mut host, mut client) = connect();
+        client.record_hash(Lockstep::HASH_INTERVAL, 1);
+        client.record_hash(2 * Lockstep::HASH_INTERVAL, 2);
+        host.record_hash(Lockstep::HASH_INTERVAL, 1);
+        host.record_hash(2 * Lockstep::HASH_INTERVAL, 3);
+        wait(|| {
+            host.poll();
+            host.desync().is_some()
+        });
+        assert_eq!(host.desync(), Some(2 * Lockstep::HASH_INTERVAL));
+        wait(|| {
+            client.poll();
+            client.desync().is_some()
+        });
+        assert_eq!(client.desync(), Some(2 * Lockstep::HASH_INTERVAL));
+    }
+
+    #[test]
+    fn net_reports_leaving_peer() {
+        let (_, mut host, client) = connect();
+        drop(client);
+        wait(|| {
+            host.poll();
+            host.error().is_some()
+        });
+    }
+
+    #[test]
+    fn net_spectator_gets_state_and_inputs() {
+        let mut host = Host::bind(0).unwrap();
+        let address = format!("127.0.0.1:{}", host.port());
+        let _client = Join::connect(&address).unwrap();
+        let mut spectator = Some(Join::spectate(&address).unwrap());
+        wait(|| {
+            host.accept().unwrap();
+            host.clients().len() == 1 && host.spectators() == 1
+        });
+        let settings = MatchSettings::new(&CONFIG);
+        let mut host = host.start(&settings).unwrap();
+        assert!(host.wants_state());
+        let input = TickInput {
+            movement: -1.0,
+            ..TickInput::default()
+        };
+        // Received from the client and not simulated yet when the state goes out.
+        host.inputs.insert(8, input.clone());
+        let state = serde_json::json!({ "tick": 7 });
+        host.send_state(&settings, &state);
+        assert!(!host.wants_state());
+        host.send_input(9, &input);
+
+        let mut watching = None;
+        wait(|| match spectator.take().unwrap().poll().unwrap() {
+            Ok(started) => {
+                watching = Some(started);
+                true
+            }
+            Err(waiting) => {
+                spectator = Some(waiting);
+                false
+            }
+        });
+        let (received, mut watcher) = watching.unwrap();
+        assert_eq!(received, settings);
+        assert!(watcher.is_spectating() && !watcher.is_authoritative());
+        assert_eq!(watcher.take_state(), Some(state));
+        wait(|| {
+            watcher.poll();
+            watcher.queued() == 2
+        });
+        assert_eq!(watcher.take_input(8), Some(input.clone()));
+        assert_eq!(watcher.take_input(9), Some(input));
+    }
+}
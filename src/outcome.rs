@@ -0,0 +1,108 @@
+use crate::player::Player;
+use serde::{Deserialize, Serialize};
+
+/// How a match ended. The winner is a team of the winning side, its allies
+/// won with it.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MatchResult {
+    Win { winner: usize },
+    Draw,
+    Surrender { winner: usize, loser: usize },
+}
+
+impl MatchResult {
+    /// The result once at most one side has crabs left, with the `sides` of
+    /// every team.
+    pub fn check(players: &[Player], sides: &[usize]) -> Option<MatchResult> {
+        let mut alive = players.iter().enumerate().filter(|(_, p)| p.is_alive());
+        let winner = match alive.next() {
+            None => return Some(MatchResult::Draw),
+            Some((winner, _)) => winner,
+        };
+        if alive.all(|(i, _)| sides[i] == sides[winner]) {
+            Some(MatchResult::Win { winner })
+        } else {
+            None
+        }
+    }
+
+    /// The teams that won, none for a draw.
+    pub fn winners(&self, sides: &[usize]) -> Vec<usize> {
+        match *self {
+            MatchResult::Win { winner } | MatchResult::Surrender { winner, .. } => {
+                (0..sides.len()).filter(|&i| sides[i] == sides[winner]).collect()
+            }
+            MatchResult::Draw => vec![],
+        }
+    }
+
+    pub fn describe(&self, names: &[&str], sides: &[usize]) -> String {
+        let winners: Vec<&str> = self.winners(sides).iter().map(|&i| names[i]).collect();
+        let wins = match winners.len() {
+            1 => format!("{} wins", winners[0]),
+            _ => format!("{} win", winners.join(" and ")),
+        };
+        match *self {
+            MatchResult::Win { .. } => wins,
+            MatchResult::Draw => String::from("Draw"),
+            MatchResult::Surrender { loser, .. } => format!("{} surrendered, {}", names[loser], wins),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::crab::Crab;
+    use ggez::graphics::Rect;
+
+    fn new_players() -> Vec<Player> {
+        vec![
+            Player::new("ivan", vec![Crab::new("gosho", Rect::default())]),
+            Player::new("petar", vec![Crab::new("pesho", Rect::default())]),
+        ]
+    }
+
+    #[test]
+    fn match_result_check() {
+        let mut players = new_players();
+        assert_eq!(MatchResult::check(&players, &[0, 1]), None);
+
+        players[0].kill_crab("gosho".to_owned());
+        assert_eq!(MatchResult::check(&players, &[0, 1]), Some(MatchResult::Win { winner: 1 }));
+
+        players[1].kill_crab("pesho".to_owned());
+        assert_eq!(MatchResult::check(&players, &[0, 1]), Some(MatchResult::Draw));
+    }
+
+    #[test]
+    fn match_result_check_by_side() {
+        let mut players = new_players();
+        players.push(Player::new("stoyan", vec![Crab::new("tosho", Rect::default())]));
+        let sides = [0, 1, 0];
+        assert_eq!(MatchResult::check(&players, &sides), None);
+        players[1].kill_crab("pesho".to_owned());
+        assert_eq!(MatchResult::check(&players, &sides), Some(MatchResult::Win { winner: 0 }));
+        players[0].kill_crab("gosho".to_owned());
+        assert_eq!(MatchResult::check(&players, &sides), Some(MatchResult::Win { winner: 2 }));
+        assert_eq!(MatchResult::Win { winner: 2 }.winners(&sides), vec![0, 2]);
+    }
+
+    #[test]
+    fn match_result_describe() {
+        let names = ["ivan", "petar"];
+        let sides = [0, 1];
+        assert_eq!(MatchResult::Win { winner: 1 }.describe(&names, &sides), "petar wins");
+        assert_eq!(MatchResult::Draw.describe(&names, &sides), "Draw");
+        assert_eq!(
+            MatchResult::Surrender { winner: 0, loser: 1 }.describe(&names, &sides),
+            "petar surrendered, ivan wins"
+        );
+        let names = ["ivan", "petar", "stoyan"];
+        assert_eq!(
+            MatchResult::Surrender { winner: 0, loser: 1 }.describe(&names, &[0, 1, 0]),
+            "petar surrendered, ivan and stoyan win"
+        );
+    }
+}
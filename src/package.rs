@@ -0,0 +1,187 @@
+use ggez::graphics::Rect;
+use ggez::{filesystem, Context, GameError, GameResult};
+use serde::Deserialize;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// A map as shipped in `resources/maps`: a TOML manifest describing the
+/// image layers and the rules of the map.
+///
+/// Image paths starting with `/` are resource paths, any other path is
+/// relative to the directory of the manifest.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct MapPackage {
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    pub foreground: String,
+    pub background: Option<String>,
+    /// Image whose opaque pixels can't be destroyed.
+    pub indestructible: Option<String>,
+    /// Everything below this row is water.
+    pub water_level: Option<f32>,
+    #[serde(default = "MapPackage::default_players")]
+    pub recommended_players: u8,
+    #[serde(default)]
+    pub spawn_zones: Vec<SpawnZone>,
+}
+
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct SpawnZone {
+    pub team: usize,
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+/// A map found in `/maps`, as listed in the match setup.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MapEntry {
+    pub manifest: String,
+    pub name: String,
+    pub recommended_players: u8,
+}
+
+impl SpawnZone {
+    pub fn rect(&self) -> Rect {
+        Rect::new(self.x, self.y, self.width, self.height)
+    }
+}
+
+impl MapPackage {
+    fn default_players() -> u8 {
+        2
+    }
+
+    pub fn load(ctx: &mut Context, manifest: &str) -> GameResult<Self> {
+        let mut file = filesystem::open(ctx, manifest)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+        let mut package = Self::parse(&contents)?;
+        package.resolve(manifest);
+        Ok(package)
+    }
+
+    /// Loads a manifest without a window, from the resources directory on
+    /// disk.
+    pub fn load_file(resources: &Path, manifest: &str) -> GameResult<Self> {
+        let contents = std::fs::read_to_string(Self::file(resources, manifest))?;
+        let mut package = Self::parse(&contents)?;
+        package.resolve(manifest);
+        Ok(package)
+    }
+
+    /// Where the resource `path` is on disk.
+    pub fn file(resources: &Path, path: &str) -> PathBuf {
+        resources.join(path.trim_start_matches('/'))
+    }
+
+    /// The maps shipped in `/maps`, sorted by name.
+    pub fn discover(ctx: &mut Context) -> Vec<MapEntry> {
+        let dirs: Vec<_> = match filesystem::read_dir(ctx, "/maps") {
+            Ok(dirs) => dirs.collect(),
+            Err(_) => return vec![],
+        };
+        let mut maps = vec![];
+        for dir in dirs {
+            let manifest = dir.join("map.toml").to_string_lossy().into_owned();
+            if !filesystem::is_file(ctx, &manifest) {
+                continue;
+            }
+            match Self::load(ctx, &manifest) {
+                Ok(package) => maps.push(MapEntry {
+                    manifest,
+                    name: package.name,
+                    recommended_players: package.recommended_players,
+                }),
+                Err(e) => eprintln!("Skipping map {}: {}", manifest, e),
+            }
+        }
+        maps.sort_by(|a, b| a.name.cmp(&b.name));
+        maps
+    }
+
+    pub fn parse(contents: &str) -> GameResult<Self> {
+        toml::from_str(contents)
+            .map_err(|e| GameError::ResourceLoadError(format!("invalid map manifest: {}", e)))
+    }
+
+    /// Spawn zones of `team`, empty when the whole map can be used.
+    pub fn team_zones(&self, team: usize) -> Vec<Rect> {
+        self.spawn_zones
+            .iter()
+            .filter(|zone| zone.team == team)
+            .map(|zone| zone.rect())
+            .collect()
+    }
+
+    fn resolve(&mut self, manifest: &str) {
+        let dir = Path::new(manifest).parent().unwrap_or_else(|| Path::new("/"));
+        let resolve = |image: &mut String| {
+            if !image.starts_with('/') {
+                *image = dir.join(&image).to_string_lossy().into_owned();
+            }
+        };
+        resolve(&mut self.foreground);
+        if let Some(background) = &mut self.background {
+            resolve(background);
+        }
+        if let Some(indestructible) = &mut self.indestructible {
+            resolve(indestructible);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const MANIFEST: &str = r#"
+        name = "Island"
+        author = "gosho"
+        foreground = "foreground.png"
+        background = "/sky.png"
+        water_level = 900.0
+
+        [[spawn_zones]]
+        team = 1
+        x = 10.0
+        y = 20.0
+        width = 30.0
+        height = 40.0
+    "#;
+
+    #[test]
+    fn package_parse() {
+        let package = MapPackage::parse(MANIFEST).unwrap();
+        assert_eq!(package.name, "Island");
+        assert_eq!(package.author, "gosho");
+        assert_eq!(package.water_level, Some(900.0));
+        assert_eq!(package.recommended_players, 2);
+        assert_eq!(package.indestructible, None);
+        assert!(package.team_zones(0).is_empty());
+        assert_eq!(package.team_zones(1), vec![Rect::new(10.0, 20.0, 30.0, 40.0)]);
+    }
+
+    #[test]
+    fn package_parse_shipped() {
+        let package = MapPackage::parse(include_str!("../resources/maps/island/map.toml")).unwrap();
+        assert_eq!(package.team_zones(0).len(), 1);
+        assert_eq!(package.team_zones(1).len(), 1);
+    }
+
+    #[test]
+    fn package_parse_invalid() {
+        assert!(MapPackage::parse("name = 1").is_err());
+    }
+
+    #[test]
+    fn package_resolve() {
+        let mut package = MapPackage::parse(MANIFEST).unwrap();
+        package.resolve("/maps/island/map.toml");
+        assert_eq!(package.foreground, "/maps/island/foreground.png");
+        assert_eq!(package.background, Some("/sky.png".to_owned()));
+    }
+}
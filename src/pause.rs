@@ -0,0 +1,133 @@
+use crate::menu::{Menu, MenuAction};
+use crate::settings::Options;
+use ggez::event::KeyCode;
+use ggez::{Context, GameResult};
+
+/// The menu shown over a paused match.
+pub struct PauseMenu {
+    menu: Menu,
+    options: Options,
+    in_settings: bool,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PauseAction {
+    Resume,
+    Restart,
+    QuitToMenu,
+    /// The options were changed from the settings page.
+    Options(Options),
+}
+
+impl PauseMenu {
+    const ITEMS: [&'static str; 4] = ["Resume", "Restart match", "Settings", "Quit to menu"];
+
+    pub fn new(options: Options) -> Self {
+        let mut pause = PauseMenu {
+            menu: Menu::new("Paused", vec![]),
+            options,
+            in_settings: false,
+        };
+        pause.refresh();
+        pause
+    }
+
+    pub fn key_down(&mut self, keycode: KeyCode) -> Option<PauseAction> {
+        let action = self.menu.key_down(keycode)?;
+        if self.in_settings {
+            return self.settings_action(action);
+        }
+        match action {
+            MenuAction::Activate(0) | MenuAction::Back => Some(PauseAction::Resume),
+            MenuAction::Activate(1) => Some(PauseAction::Restart),
+            MenuAction::Activate(2) => {
+                self.in_settings = true;
+                self.refresh();
+                self.menu.select(0);
+                None
+            }
+            MenuAction::Activate(_) => Some(PauseAction::QuitToMenu),
+            MenuAction::Change(..) => None,
+        }
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)
+    }
+
+    fn settings_action(&mut self, action: MenuAction) -> Option<PauseAction> {
+        match action {
+            MenuAction::Activate(idx) if idx < Options::ITEMS => self.options.change(idx, 0),
+            MenuAction::Change(idx, delta) if idx < Options::ITEMS => self.options.change(idx, delta),
+            MenuAction::Change(..) => return None,
+            MenuAction::Activate(_) | MenuAction::Back => {
+                self.in_settings = false;
+                self.refresh();
+                self.menu.select(2);
+                return None;
+            }
+        }
+        self.refresh();
+        Some(PauseAction::Options(self.options))
+    }
+
+    fn refresh(&mut self) {
+        let items = if self.in_settings {
+            let mut items = self.options.items();
+            items.push(String::from("Back"));
+            items
+        } else {
+            Self::ITEMS.iter().map(|item| (*item).to_owned()).collect()
+        };
+        self.menu.set_items(items);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn new_pause() -> PauseMenu {
+        PauseMenu::new(Options {
+            scorch: true,
+            fullscreen: false,
+            music_volume: 50,
+            effects_volume: 50,
+            muted: false,
+        })
+    }
+
+    #[test]
+    fn pause_actions() {
+        let mut pause = new_pause();
+        assert_eq!(pause.key_down(KeyCode::Escape), Some(PauseAction::Resume));
+        pause.key_down(KeyCode::Down);
+        assert_eq!(pause.key_down(KeyCode::Return), Some(PauseAction::Restart));
+        pause.key_down(KeyCode::Up);
+        pause.key_down(KeyCode::Up);
+        assert_eq!(pause.key_down(KeyCode::Return), Some(PauseAction::QuitToMenu));
+    }
+
+    #[test]
+    fn pause_settings() {
+        let mut pause = new_pause();
+        pause.key_down(KeyCode::Down);
+        pause.key_down(KeyCode::Down);
+        assert_eq!(pause.key_down(KeyCode::Return), None);
+        pause.key_down(KeyCode::Down);
+        let changed = pause.key_down(KeyCode::Return);
+        assert_eq!(
+            changed,
+            Some(PauseAction::Options(Options {
+                scorch: true,
+                fullscreen: true,
+                music_volume: 50,
+                effects_volume: 50,
+                muted: false,
+            }))
+        );
+        pause.key_down(KeyCode::Escape);
+        assert_eq!(pause.key_down(KeyCode::Escape), Some(PauseAction::Resume));
+    }
+}
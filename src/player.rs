@@ -1,14 +1,22 @@
 use crate::crab::Crab;
 use crate::map::Map;
+use crate::settings::Targets;
 use crate::shot::Shot;
+use crate::tool::{Dig, Girder};
 use crate::weapon::WeaponType;
+use ggez::graphics::Rect;
 use ggez::nalgebra::Vector2;
-use std::collections::HashSet;
+use serde::{Deserialize, Serialize};
 
+#[derive(Serialize, Deserialize)]
 pub struct Player {
     pub name: String,
     pub crabs: Vec<Crab>,
     active_crab_idx: usize,
+    /// The active crab died during the turn of the team, and the crab that
+    /// took its index is next in the rotation already.
+    #[serde(default)]
+    replaced: bool,
 }
 
 impl Player {
@@ -17,80 +25,224 @@ impl Player {
             name: String::from(name),
             crabs,
             active_crab_idx: 0,
+            replaced: false,
         }
     }
 
+    pub fn active_crab_idx(&self) -> usize {
+        self.active_crab_idx
+    }
+
     pub fn update_crab(&mut self, direction: Vector2<f32>, seconds: f32, map: &Map) {
-        if self.crabs.len() > 0 {
-            self.active_crab().update(direction, seconds, map)
+        if let Some(crab) = self.current_crab_mut() {
+            crab.update(direction, seconds, map)
         }
     }
 
     pub fn switch_crab(&mut self) {
-        self.active_crab_idx = (self.active_crab_idx + 1) % self.crabs.len();
+        if self.is_alive() {
+            self.active_crab_idx = (self.active_crab_idx + 1) % self.crabs.len();
+        }
+    }
+
+    /// Makes crab `idx` the active one, when there is such a crab.
+    pub fn select_crab(&mut self, idx: usize) {
+        if idx < self.crabs.len() {
+            self.active_crab_idx = idx;
+        }
+    }
+
+    /// Called as the turn of the team starts, the active crab plays it.
+    pub fn start_turn(&mut self) {
+        self.replaced = false;
+    }
+
+    /// Counts the turn that ends off the statuses of the crabs.
+    pub fn tick_statuses(&mut self) {
+        let (active, played) = (self.active_crab_idx, !self.replaced);
+        for (i, crab) in self.crabs.iter_mut().enumerate() {
+            crab.tick_statuses(played && i == active);
+        }
+    }
+
+    /// Moves the rotation on to the crab after the one that played the turn.
+    pub fn end_turn(&mut self) {
+        if self.replaced {
+            self.replaced = false;
+        } else {
+            self.switch_crab();
+        }
+    }
+
+    /// Keeps the crabs `keep` returns true for. The active crab stays active
+    /// when it is kept, otherwise the crab after it takes its place.
+    fn retain_crabs(&mut self, mut keep: impl FnMut(&Crab) -> bool) {
+        let active = self.active_crab_idx;
+        let (mut idx, mut removed_before, mut removed_active) = (0, 0, false);
+        self.crabs.retain(|crab| {
+            let kept = keep(crab);
+            if !kept && idx < active {
+                removed_before += 1;
+            } else if !kept && idx == active {
+                removed_active = true;
+            }
+            idx += 1;
+            kept
+        });
+        self.active_crab_idx = match self.crabs.len() {
+            0 => 0,
+            len => (active - removed_before) % len,
+        };
+        self.replaced |= removed_active;
+    }
+
+    pub fn is_alive(&self) -> bool {
+        !self.crabs.is_empty()
+    }
+
+    pub fn surrender(&mut self) {
+        self.retain_crabs(|_| false);
     }
 
     pub fn set_weapon(&mut self, weapon: WeaponType) {
-        self.active_crab().set_weapon(weapon)
+        if let Some(crab) = self.current_crab_mut() {
+            crab.set_weapon(weapon)
+        }
     }
 
-    pub fn set_weapon_direction(&mut self, seconds: f32) {
-        self.active_crab().set_weapon_direction(seconds)
+    pub fn rotate_aim(&mut self, angle: f32) {
+        if let Some(crab) = self.current_crab_mut() {
+            crab.rotate_aim(angle)
+        }
     }
 
-    pub fn has_weapon(&mut self) -> bool {
-        self.active_crab().has_weapon()
+    pub fn has_weapon(&self) -> bool {
+        self.current_crab().is_some_and(Crab::has_weapon)
     }
 
     pub fn fire(&mut self, power: f32) -> Vec<Shot> {
-        self.active_crab().fire(power)
+        match self.current_crab() {
+            Some(crab) => crab.fire(power),
+            None => vec![],
+        }
+    }
+
+    /// The crab playing the turn, none once the team has no crabs left.
+    pub fn current_crab(&self) -> Option<&Crab> {
+        self.crabs.get(self.active_crab_idx)
+    }
+
+    fn current_crab_mut(&mut self) -> Option<&mut Crab> {
+        self.crabs.get_mut(self.active_crab_idx)
+    }
+
+    pub fn jump(&mut self, map: &Map) {
+        if let Some(crab) = self.current_crab_mut() {
+            crab.jump(map)
+        }
+    }
+
+    pub fn aim(&mut self, direction: Vector2<f32>) {
+        if let Some(crab) = self.current_crab_mut() {
+            crab.aim(direction)
+        }
+    }
+
+    pub fn weapon_kind(&self) -> Option<WeaponType> {
+        self.current_crab()?.weapon.as_ref().map(|weapon| weapon.kind())
+    }
+
+    pub fn start_dig(&mut self) -> Option<Dig> {
+        let crab = self.current_crab()?;
+        Dig::new(crab.weapon.as_ref()?.kind(), crab.facing())
+    }
+
+    pub fn dig(&mut self, dig: &mut Dig, seconds: f32, map: &mut Map) {
+        if let Some(crab) = self.current_crab_mut() {
+            dig.update(crab, seconds, map)
+        }
+    }
+
+    pub fn can_place_girder(&self, girder: &Girder, crabs: &[Rect], map: &Map) -> bool {
+        self.current_crab().is_some_and(|crab| girder.can_place(crab, crabs, map))
+    }
+
+    pub fn weapon_angle(&self) -> f32 {
+        match self.current_crab().and_then(|crab| crab.weapon.as_ref()) {
+            None => 0.0,
+            Some(weapon) => weapon.direction().y.atan2(weapon.direction().x),
+        }
     }
 
     pub fn kill_crab(&mut self, name: String) {
-        self.crabs.retain(|crab| crab.name != name);
+        self.retain_crabs(|crab| crab.name != name);
+    }
+
+    /// Removes the crabs that sank below the water level.
+    pub fn drown_crabs(&mut self, map: &Map) {
+        self.retain_crabs(|crab| !map.under_water(crab.get_center()));
     }
 
-    pub fn handle_collisions(&mut self, shot: Shot, skip_active: bool) -> bool {
+    /// Removes the crabs that are dying and returns where they were.
+    pub fn remove_dead(&mut self) -> Vec<Rect> {
+        let dead = self.crabs.iter().filter(|crab| crab.is_dying()).map(Crab::get_rect).collect();
+        self.retain_crabs(|crab| !crab.is_dying());
+        dead
+    }
+
+    pub fn has_dying(&self) -> bool {
+        self.crabs.iter().any(Crab::is_dying)
+    }
+
+    /// Hurts the crabs of team `team` that `shot` overlaps and is allowed to
+    /// hit by `targets`, see `Targets::can_hit` for `cleared`. Killed crabs
+    /// stay until `remove_dead` and aren't hit again.
+    pub fn handle_collisions(&mut self, shot: Shot, team: usize, targets: &Targets, cleared: bool) -> bool {
         let mut hit = false;
-        let mut killed = HashSet::new();
-        let active_crab = self.active_crab().name.clone();
         self.crabs.iter_mut().for_each(|crab| {
-            if skip_active && crab.name == active_crab {
+            if crab.is_dying() || !targets.can_hit(team, &crab.name, cleared) {
                 return;
             }
             if crab.get_rect().overlaps(&shot.get_rect()) {
-                crab.reduce_health(shot.damage());
-                hit = true;
-                if crab.get_health() <= 0.0 {
-                    killed.insert(crab.name.clone());
+                crab.take_damage(shot.hit_damage());
+                if let Some(status) = shot.status() {
+                    crab.add_status(status);
                 }
+                hit = true;
             }
         });
-        self.crabs.retain(|crab| !killed.contains(&crab.name));
         hit
     }
 
     pub fn total_health(&self) -> f32 {
-        self.crabs.iter().map(|crab| crab.get_health()).sum()
+        self.crabs.iter().map(|crab| crab.get_health().max(0.0)).sum()
     }
 
     pub fn is_crab_active(&self, name: &str) -> bool {
-        self.crabs[self.active_crab_idx].name == name
+        match self.crabs.get(self.active_crab_idx) {
+            Some(crab) => crab.name == name,
+            None => false,
+        }
     }
 
-    fn active_crab(&mut self) -> &mut Crab {
-        &mut self.crabs[self.active_crab_idx]
-    }
 }
 
 #[cfg(test)]
+#[allow(clippy::bool_assert_comparison)]
 mod tests {
 
     use super::*;
     use crate::shot::{ShotType, ShotConfig};
+    use crate::status::{Status, StatusKind};
     use ggez::nalgebra::Point2;
     use ggez::graphics::Rect;
 
+    impl Player {
+        fn active_crab(&mut self) -> &mut Crab {
+            self.current_crab_mut().unwrap()
+        }
+    }
+
     fn new_player() -> Player {
         let crabs = vec![
             Crab::new("gosho", Rect::new(2.0, 2.0, 2.0, 2.0)),
@@ -110,6 +262,17 @@ mod tests {
         Shot::new(cfg, ShotType::Pistol, pos, Vector2::new(0.0, 0.0))
     }
 
+    /// The targets of `shooter` of `team`, with the player as team 0.
+    fn new_targets(team: usize, shooter: &str) -> Targets {
+        Targets {
+            sides: vec![0, 1],
+            team,
+            shooter: shooter.to_owned(),
+            friendly_fire: true,
+            self_damage: false,
+        }
+    }
+
     #[test]
     fn player_new() {
         let mut player = new_player();
@@ -133,6 +296,17 @@ mod tests {
         assert_eq!(player.active_crab().name, "gosho");
     }
 
+    #[test]
+    fn player_surrender() {
+        let mut player = new_player();
+        player.switch_crab();
+        player.surrender();
+        assert!(!player.is_alive());
+        assert!(!player.is_crab_active("pesho"));
+        player.switch_crab();
+        assert_eq!(player.total_health(), 0.0);
+    }
+
     #[test]
     fn player_kill_crab() {
         let mut player = new_player();
@@ -140,14 +314,68 @@ mod tests {
         assert_eq!(player.total_health(), Crab::HEALTH);
     }
 
+    #[test]
+    fn player_rotation_skips_dead_crabs() {
+        let mut player = new_player();
+        player.crabs.push(Crab::new("tosho", Rect::new(90.0, 90.0, 1.0, 1.0)));
+        player.switch_crab();
+        player.kill_crab("gosho".to_owned());
+        assert_eq!(player.active_crab().name, "pesho");
+
+        // The active crab dies during its own turn, the next one plays next.
+        player.start_turn();
+        player.kill_crab("pesho".to_owned());
+        assert_eq!(player.active_crab().name, "tosho");
+        player.end_turn();
+        assert_eq!(player.active_crab().name, "tosho");
+
+        // It dies during another turn, the next one plays and the rotation goes on.
+        player.crabs.push(Crab::new("misho", Rect::new(70.0, 70.0, 1.0, 1.0)));
+        player.kill_crab("tosho".to_owned());
+        player.start_turn();
+        assert_eq!(player.active_crab().name, "misho");
+        player.select_crab(5);
+        player.end_turn();
+        assert_eq!(player.active_crab().name, "misho");
+        assert_eq!(player.active_crab_idx(), 0);
+    }
+
+    #[test]
+    fn player_without_crabs() {
+        let mut player = new_player();
+        player.set_weapon(WeaponType::Pistol);
+        player.surrender();
+        player.set_weapon(WeaponType::Bazooka);
+        player.rotate_aim(1.0);
+        assert_eq!(player.weapon_kind(), None);
+        assert_eq!(player.weapon_angle(), 0.0);
+        assert!(!player.has_weapon());
+        assert!(player.fire(1.0).is_empty());
+        assert!(player.start_dig().is_none());
+    }
+
+    #[test]
+    fn player_drown_crabs() {
+        let mut player = new_player();
+        player.switch_crab();
+        let mut map = Map::new(&[0; 4], 1, 1);
+        player.drown_crabs(&map);
+        assert_eq!(player.crabs.len(), 2);
+
+        map.set_water_level(Some(10.0));
+        player.drown_crabs(&map);
+        assert_eq!(player.crabs.len(), 1);
+        assert_eq!(player.active_crab().name, "gosho");
+    }
+
     #[test]
     fn player_handle_collisions_no() {
         let mut player = new_player();
-        assert_eq!(player.handle_collisions(new_shot(Point2::new(100.0, 100.0)), false), false);
+        assert_eq!(player.handle_collisions(new_shot(Point2::new(100.0, 100.0)), 0, &new_targets(1, "x"), false), false);
         assert!(player.crabs[0].get_health() == Crab::HEALTH);
         assert!(player.crabs[1].get_health() == Crab::HEALTH);
 
-        assert_eq!(player.handle_collisions(new_shot(Point2::new(2.0, 2.0)), true), false);
+        assert_eq!(player.handle_collisions(new_shot(Point2::new(2.0, 2.0)), 0, &new_targets(0, "gosho"), false), false);
         assert!(player.crabs[0].get_health() == Crab::HEALTH);
         assert!(player.crabs[1].get_health() == Crab::HEALTH);
     }
@@ -155,16 +383,37 @@ mod tests {
     #[test]
     fn player_handle_collisions_overlapping() {
         let mut player = new_player();
-        assert_eq!(player.handle_collisions(new_shot(Point2::new(3.0, 3.0)), false), true);
+        assert_eq!(player.handle_collisions(new_shot(Point2::new(3.0, 3.0)), 0, &new_targets(1, "x"), false), true);
         assert!(player.crabs[0].get_health() < Crab::HEALTH);
         assert!(player.crabs[1].get_health() == Crab::HEALTH);
     }
 
+    #[test]
+    fn player_handle_collisions_statuses() {
+        let mut player = new_player();
+        player.crabs[0].add_status(Status::new(StatusKind::Shield));
+        let shot = new_shot(Point2::new(3.0, 3.0)).with_status(Status::new(StatusKind::Poison));
+        assert!(player.handle_collisions(shot, 0, &new_targets(1, "x"), false));
+        assert_eq!(player.crabs[0].get_health(), Crab::HEALTH);
+        assert!(player.crabs[0].has_status(StatusKind::Poison));
+
+        player.tick_statuses();
+        assert_eq!(player.crabs[0].get_health(), Crab::HEALTH - Status::POISON_DAMAGE);
+        assert!(!player.crabs[1].has_status(StatusKind::Poison));
+    }
+
     #[test]
     fn player_handle_collisions_kills() {
         let mut player = new_player();
-        player.active_crab().reduce_health(Crab::HEALTH);
-        assert_eq!(player.handle_collisions(new_shot(Point2::new(2.0, 2.0)), false), true);
+        player.active_crab().reduce_health(Crab::HEALTH - 1.0);
+        assert!(player.handle_collisions(new_shot(Point2::new(2.0, 2.0)), 0, &new_targets(1, "x"), false));
+        assert_eq!(player.crabs.len(), 2);
+        assert!(player.has_dying());
+        assert_eq!(player.total_health(), Crab::HEALTH);
+        assert!(!player.handle_collisions(new_shot(Point2::new(2.0, 2.0)), 0, &new_targets(1, "x"), false));
+
+        assert_eq!(player.remove_dead(), vec![Rect::new(2.0, 2.0, 2.0, 2.0)]);
+        assert!(!player.has_dying());
         assert_eq!(player.crabs.len(), 1);
         assert_eq!(player.active_crab().name, "pesho")
     }
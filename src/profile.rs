@@ -0,0 +1,187 @@
+use crate::outcome::MatchResult;
+use crate::stats::TeamStats;
+use ggez::{filesystem, Context, GameError, GameResult};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// The record of a team name over every match it played at this machine.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub name: String,
+    pub wins: u32,
+    pub losses: u32,
+    pub draws: u32,
+    /// Lifetime totals of the stats of every match.
+    pub totals: TeamStats,
+}
+
+impl Profile {
+    pub fn matches(&self) -> u32 {
+        self.wins + self.losses + self.draws
+    }
+
+    /// Wins out of the matches played, 0.0 to 1.0.
+    pub fn win_rate(&self) -> f32 {
+        match self.matches() {
+            0 => 0.0,
+            matches => self.wins as f32 / matches as f32,
+        }
+    }
+
+    /// Shots that hit a crab out of the shots fired, 0.0 to 1.0.
+    pub fn accuracy(&self) -> f32 {
+        match self.totals.shots_fired {
+            0 => 0.0,
+            shots => self.totals.hits as f32 / shots as f32,
+        }
+    }
+}
+
+/// The profiles of the teams played at this machine, saved in the user
+/// config directory.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Profiles {
+    #[serde(default)]
+    profiles: Vec<Profile>,
+}
+
+impl Profiles {
+    pub const FILE: &'static str = "/profiles.toml";
+
+    /// The saved profiles, none when there are none yet or they can't be read.
+    pub fn load(ctx: &mut Context) -> Self {
+        if !filesystem::is_file(ctx, Self::FILE) {
+            return Profiles::default();
+        }
+        let mut contents = String::new();
+        let result = filesystem::open(ctx, Self::FILE)
+            .and_then(|mut file| Ok(file.read_to_string(&mut contents)?))
+            .and_then(|_| Self::parse(&contents));
+        result.unwrap_or_else(|e| {
+            eprintln!("Ignoring the saved profiles: {}", e);
+            Profiles::default()
+        })
+    }
+
+    pub fn save(&self, ctx: &mut Context) -> GameResult {
+        let mut file = filesystem::create(ctx, Self::FILE)?;
+        file.write_all(self.to_toml()?.as_bytes())?;
+        Ok(())
+    }
+
+    pub fn parse(contents: &str) -> GameResult<Self> {
+        toml::from_str(contents).map_err(|e| GameError::ResourceLoadError(format!("invalid profiles: {}", e)))
+    }
+
+    pub fn to_toml(&self) -> GameResult<String> {
+        toml::to_string(self).map_err(|e| GameError::ResourceLoadError(format!("invalid profiles: {}", e)))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Profile> {
+        self.profiles.iter().find(|profile| profile.name == name)
+    }
+
+    fn get_mut(&mut self, name: &str) -> &mut Profile {
+        match self.profiles.iter().position(|profile| profile.name == name) {
+            Some(idx) => &mut self.profiles[idx],
+            None => {
+                self.profiles.push(Profile {
+                    name: name.to_owned(),
+                    ..Profile::default()
+                });
+                self.profiles.last_mut().unwrap()
+            }
+        }
+    }
+
+    /// Adds a finished match to the profiles of the `recorded` teams, out of
+    /// the teams named in `names` on `sides`.
+    pub fn record(
+        &mut self,
+        names: &[&str],
+        sides: &[usize],
+        stats: &[TeamStats],
+        result: MatchResult,
+        recorded: &[usize],
+    ) {
+        let winners = result.winners(sides);
+        for &team in recorded {
+            let profile = self.get_mut(names[team]);
+            if result == MatchResult::Draw {
+                profile.draws += 1;
+            } else if winners.contains(&team) {
+                profile.wins += 1;
+            } else {
+                profile.losses += 1;
+            }
+            profile.totals.add(&stats[team]);
+        }
+    }
+
+    /// The profiles with the most wins first, then the best win rate, then
+    /// the most damage dealt.
+    pub fn leaderboard(&self) -> Vec<&Profile> {
+        let mut profiles: Vec<&Profile> = self.profiles.iter().collect();
+        profiles.sort_by(|a, b| {
+            b.wins
+                .cmp(&a.wins)
+                .then(b.win_rate().total_cmp(&a.win_rate()))
+                .then(b.totals.damage_dealt.total_cmp(&a.totals.damage_dealt))
+        });
+        profiles
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::weapon::WeaponType;
+
+    fn new_stats(damage: f32) -> TeamStats {
+        let mut stats = TeamStats {
+            damage_dealt: damage,
+            ..TeamStats::default()
+        };
+        stats.fired(Some(WeaponType::Bazooka), 2);
+        stats.hit(Some(WeaponType::Bazooka));
+        stats
+    }
+
+    #[test]
+    fn profiles_record() {
+        let mut profiles = Profiles::default();
+        let names = ["ivan", "petar", "ai"];
+        let stats = [new_stats(40.0), new_stats(10.0), new_stats(0.0)];
+        let sides = [0, 1, 2];
+        profiles.record(&names, &sides, &stats, MatchResult::Win { winner: 0 }, &[0, 1]);
+        profiles.record(&names, &sides, &stats, MatchResult::Surrender { winner: 1, loser: 0 }, &[0, 1]);
+        profiles.record(&names, &sides, &stats, MatchResult::Draw, &[0]);
+
+        let ivan = profiles.get("ivan").unwrap();
+        assert_eq!((ivan.wins, ivan.losses, ivan.draws), (1, 1, 1));
+        assert_eq!(ivan.totals.damage_dealt, 120.0);
+        assert_eq!(ivan.totals.weapon(WeaponType::Bazooka).unwrap().shots, 6);
+        assert_eq!(ivan.accuracy(), 0.5);
+        let petar = profiles.get("petar").unwrap();
+        assert_eq!((petar.wins, petar.losses, petar.matches()), (1, 1, 2));
+        assert_eq!(profiles.get("ai"), None);
+
+        let ranked: Vec<&str> = profiles.leaderboard().iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(ranked, vec!["petar", "ivan"]);
+
+        profiles.record(&names, &[0, 1, 0], &stats, MatchResult::Win { winner: 2 }, &[0, 1]);
+        assert_eq!(profiles.get("ivan").unwrap().wins, 2);
+        assert_eq!(profiles.get("petar").unwrap().losses, 2);
+    }
+
+    #[test]
+    fn profiles_round_trip() {
+        let mut profiles = Profiles::default();
+        profiles.record(&["ivan"], &[0], &[new_stats(12.5)], MatchResult::Win { winner: 0 }, &[0]);
+        let loaded = Profiles::parse(&profiles.to_toml().unwrap()).unwrap();
+        assert_eq!(loaded, profiles);
+        assert!(Profiles::parse("profiles = 3").is_err());
+    }
+}
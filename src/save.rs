@@ -0,0 +1,103 @@
+use crate::config::GameConfig;
+use crate::simulation::Simulation;
+use ggez::{filesystem, Context, GameError, GameResult};
+use serde::{Deserialize, Serialize};
+use std::io::{Read, Write};
+
+/// The quick-save slot, in the user config directory.
+pub const QUICK_SAVE: &str = "/quicksave.json";
+
+/// Bumped whenever the saved state changes, older saves can't be loaded.
+const VERSION: u32 = 3;
+
+/// A match saved to be resumed later: the map mask, every team and crab,
+/// whose turn it is, the inventories and the settings. Their seed is all the
+/// randomness a match has, so nothing else needs saving for it.
+#[derive(Serialize, Deserialize)]
+struct SaveFile {
+    version: u32,
+    state: serde_json::Value,
+}
+
+/// The contents of a save file for `sim`.
+pub fn to_json(sim: &Simulation) -> GameResult<String> {
+    let file = SaveFile {
+        version: VERSION,
+        state: sim.snapshot().map_err(invalid)?,
+    };
+    serde_json::to_string(&file).map_err(invalid)
+}
+
+/// The match saved in `contents`.
+pub fn parse(cfg: &'static GameConfig, contents: &str) -> GameResult<Simulation> {
+    let file: SaveFile = serde_json::from_str(contents).map_err(invalid)?;
+    if file.version != VERSION {
+        return Err(GameError::ResourceLoadError(format!(
+            "the save is from another version of the game ({})",
+            file.version
+        )));
+    }
+    Simulation::restore(cfg, file.state).map_err(invalid)
+}
+
+pub fn save(ctx: &mut Context, path: &str, sim: &Simulation) -> GameResult {
+    let contents = to_json(sim)?;
+    let mut file = filesystem::create(ctx, path)?;
+    file.write_all(contents.as_bytes())?;
+    Ok(())
+}
+
+pub fn load(ctx: &mut Context, cfg: &'static GameConfig, path: &str) -> GameResult<Simulation> {
+    let mut contents = String::new();
+    filesystem::open(ctx, path)?.read_to_string(&mut contents)?;
+    parse(cfg, &contents)
+}
+
+pub fn exists(ctx: &mut Context, path: &str) -> bool {
+    filesystem::is_file(ctx, path)
+}
+
+fn invalid(e: serde_json::Error) -> GameError {
+    GameError::ResourceLoadError(format!("invalid save: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+    use crate::map::Map;
+    use crate::settings::{MapSource, MatchSettings};
+    use crate::simulation::{Command, TickInput};
+    use crate::terrain;
+    use crate::weapon::WeaponType;
+
+    #[test]
+    fn save_round_trip() {
+        let mut settings = MatchSettings::new(&CONFIG);
+        settings.map = MapSource::Generated { seed: 2 };
+        let (width, height) = (CONFIG.screen.width as u16, CONFIG.screen.height as u16);
+        let map = Map::new(&terrain::generate(width, height, 2), width, height);
+        let mut sim = Simulation::new(&CONFIG, settings, map, None).unwrap();
+        sim.step(&TickInput {
+            commands: vec![Command::SelectWeapon(WeaponType::Drill), Command::Fire { power: 0.0 }],
+            ..TickInput::default()
+        });
+        for _ in 0..20 {
+            sim.step(&TickInput::default());
+        }
+
+        let loaded = parse(&CONFIG, &to_json(&sim).unwrap()).unwrap();
+        assert_eq!(loaded.state_hash(), sim.state_hash());
+        assert_eq!(loaded.settings(), sim.settings());
+        assert_eq!(loaded.inventory(0).left(WeaponType::Drill), Some(1));
+        assert_eq!(loaded.active_player().weapon_kind(), Some(WeaponType::Drill));
+    }
+
+    #[test]
+    fn save_rejects_other_versions() {
+        let contents = r#"{"version": 0, "state": {}}"#;
+        assert!(parse(&CONFIG, contents).is_err());
+        assert!(parse(&CONFIG, "not a save").is_err());
+    }
+}
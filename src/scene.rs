@@ -0,0 +1,433 @@
+use crate::audio::{Audio, Music};
+use crate::config::GameConfig;
+use crate::controls::{Action, Bindings};
+use crate::game::Game;
+use crate::leaderboard::Leaderboard;
+use crate::lobby::Lobby;
+use crate::menu::{Menu, MenuAction};
+use crate::package::MapPackage;
+use crate::profile::Profiles;
+use crate::save;
+use crate::settings::{MatchSettings, Options};
+use crate::setup::MatchSetup;
+use ggez::event::{self, Axis, Button, KeyCode, KeyMods};
+use ggez::input::gamepad::GamepadId;
+use ggez::input::mouse::MouseButton;
+use ggez::{graphics, Context, GameResult};
+
+/// Where to go after the current scene.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Transition {
+    Menu,
+    Setup,
+    Options,
+    Controls,
+    Lobby,
+    Leaderboard,
+    Play(MatchSettings),
+    /// Resumes the quick-saved match.
+    Resume,
+    Quit,
+}
+
+/// Runs the scene that is currently shown and switches between scenes.
+pub struct App {
+    cfg: &'static GameConfig,
+    settings: MatchSettings,
+    options: Options,
+    bindings: Bindings,
+    audio: Audio,
+    scene: Scene,
+}
+
+enum Scene {
+    Menu(MainMenu),
+    Setup(MatchSetup),
+    Options(OptionsScreen),
+    Controls(ControlsScreen),
+    Lobby(Box<Lobby>),
+    Leaderboard(Leaderboard),
+    Match(Box<Game>),
+}
+
+impl App {
+    pub fn new(ctx: &mut Context, cfg: &'static GameConfig) -> Self {
+        let options = Options::new(cfg);
+        let mut audio = Audio::new(ctx, &cfg.audio, options);
+        audio.play_music(Music::Menu);
+        App {
+            cfg,
+            settings: MatchSettings::new(cfg),
+            options,
+            bindings: Bindings::load(ctx, &cfg.controls),
+            audio,
+            scene: Scene::Menu(MainMenu::new()),
+        }
+    }
+
+    fn switch(&mut self, ctx: &mut Context, transition: Transition) {
+        let mut lockstep = None;
+        match &mut self.scene {
+            Scene::Setup(setup) => self.settings = setup.settings().clone(),
+            Scene::Lobby(lobby) => lockstep = lobby.take_lockstep(),
+            Scene::Options(options) => self.options = options.options,
+            Scene::Controls(controls) => self.bindings = controls.bindings.clone(),
+            Scene::Match(game) => self.options = game.options(),
+            _ => (),
+        }
+        self.scene = match transition {
+            Transition::Menu => Scene::Menu(MainMenu::new()),
+            Transition::Setup => {
+                let maps = MapPackage::discover(ctx);
+                Scene::Setup(MatchSetup::new(self.cfg, self.settings.clone(), maps))
+            }
+            Transition::Options => Scene::Options(OptionsScreen::new(self.options)),
+            Transition::Controls => {
+                let defaults = Bindings::new(&self.cfg.controls);
+                Scene::Controls(ControlsScreen::new(self.bindings.clone(), defaults))
+            }
+            Transition::Lobby => Scene::Lobby(Box::new(Lobby::new(self.settings.clone()))),
+            Transition::Leaderboard => Scene::Leaderboard(Leaderboard::new(Profiles::load(ctx))),
+            Transition::Play(mut settings) => {
+                // Network matches keep the seed all the peers got.
+                if lockstep.is_none() {
+                    self.settings = settings.clone();
+                    settings.seed = rand::random();
+                }
+                let game = Game::new(ctx, self.cfg, settings, self.options, self.bindings.clone())
+                    .and_then(|mut game| {
+                        if let Some(lockstep) = lockstep {
+                            game.set_lockstep(lockstep)?;
+                        }
+                        Ok(game)
+                    });
+                match game {
+                    Ok(game) => Scene::Match(Box::new(game)),
+                    Err(e) => {
+                        let mut menu = MainMenu::new();
+                        menu.menu.set_message(Some(format!("Could not start the match: {}", e)));
+                        Scene::Menu(menu)
+                    }
+                }
+            }
+            Transition::Resume => {
+                let (options, bindings) = (self.options, self.bindings.clone());
+                match Game::load(ctx, self.cfg, options, bindings, save::QUICK_SAVE) {
+                    Ok(game) => Scene::Match(Box::new(game)),
+                    Err(e) => {
+                        let mut menu = MainMenu::new();
+                        menu.menu.set_message(Some(format!("Could not resume the match: {}", e)));
+                        Scene::Menu(menu)
+                    }
+                }
+            }
+            Transition::Quit => {
+                event::quit(ctx);
+                return;
+            }
+        };
+        self.audio.set_options(self.options);
+        match self.scene {
+            Scene::Match(_) => self.audio.play_music(Music::Match),
+            _ => self.audio.play_music(Music::Menu),
+        }
+    }
+
+    /// Plays the sounds of the match and switches scenes when it is left.
+    fn poll_game(&mut self, ctx: &mut Context) {
+        let transition = match &mut self.scene {
+            Scene::Match(game) => {
+                for sound in game.take_sounds() {
+                    self.audio.play(sound);
+                }
+                self.audio.set_options(game.options());
+                game.transition()
+            }
+            _ => None,
+        };
+        if let Some(transition) = transition {
+            self.switch(ctx, transition);
+        }
+    }
+}
+
+impl event::EventHandler for App {
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        match &mut self.scene {
+            Scene::Match(game) => game.update(ctx)?,
+            Scene::Lobby(lobby) => {
+                if let Some(transition) = lobby.update() {
+                    self.switch(ctx, transition);
+                }
+            }
+            _ => (),
+        }
+        self.poll_game(ctx);
+        Ok(())
+    }
+
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+        if let Scene::Match(game) = &mut self.scene {
+            return game.draw(ctx);
+        }
+        graphics::clear(ctx, graphics::Color::new(0.1, 0.15, 0.25, 1.0));
+        match &self.scene {
+            Scene::Menu(menu) => menu.menu.draw(ctx)?,
+            Scene::Setup(setup) => setup.draw(ctx)?,
+            Scene::Options(options) => options.menu.draw(ctx)?,
+            Scene::Controls(controls) => controls.menu.draw(ctx)?,
+            Scene::Lobby(lobby) => lobby.draw(ctx)?,
+            Scene::Leaderboard(leaderboard) => leaderboard.draw(ctx)?,
+            Scene::Match(_) => (),
+        }
+        graphics::present(ctx)
+    }
+
+    fn key_down_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymods: KeyMods, repeat: bool) {
+        let transition = match &mut self.scene {
+            Scene::Menu(menu) => menu.key_down(keycode, &self.settings),
+            Scene::Setup(setup) => setup.key_down(keycode),
+            Scene::Options(options) => {
+                let transition = options.key_down(ctx, keycode);
+                self.audio.set_options(options.options);
+                transition
+            }
+            Scene::Controls(controls) => controls.key_down(ctx, keycode),
+            Scene::Lobby(lobby) => lobby.key_down(keycode),
+            Scene::Leaderboard(leaderboard) => leaderboard.key_down(keycode),
+            Scene::Match(game) => {
+                game.key_down_event(ctx, keycode, keymods, repeat);
+                None
+            }
+        };
+        if let Some(transition) = transition {
+            self.switch(ctx, transition);
+        }
+        self.poll_game(ctx);
+    }
+
+    fn text_input_event(&mut self, _ctx: &mut Context, ch: char) {
+        if let Scene::Lobby(lobby) = &mut self.scene {
+            lobby.text_input(ch);
+        }
+    }
+
+    fn key_up_event(&mut self, ctx: &mut Context, keycode: KeyCode, keymods: KeyMods) {
+        if let Scene::Match(game) = &mut self.scene {
+            game.key_up_event(ctx, keycode, keymods);
+        }
+        self.poll_game(ctx);
+    }
+
+    fn mouse_button_down_event(&mut self, ctx: &mut Context, button: MouseButton, x: f32, y: f32) {
+        if let Scene::Match(game) = &mut self.scene {
+            game.mouse_button_down_event(ctx, button, x, y);
+        }
+        self.poll_game(ctx);
+    }
+
+    fn mouse_motion_event(&mut self, ctx: &mut Context, x: f32, y: f32, dx: f32, dy: f32) {
+        if let Scene::Match(game) = &mut self.scene {
+            game.mouse_motion_event(ctx, x, y, dx, dy);
+        }
+    }
+
+    fn mouse_wheel_event(&mut self, ctx: &mut Context, x: f32, y: f32) {
+        if let Scene::Match(game) = &mut self.scene {
+            game.mouse_wheel_event(ctx, x, y);
+        }
+    }
+
+    fn gamepad_button_down_event(&mut self, ctx: &mut Context, button: Button, id: GamepadId) {
+        match &mut self.scene {
+            Scene::Match(game) => game.gamepad_button_down_event(ctx, button, id),
+            Scene::Controls(controls) if controls.waiting.is_some() => {
+                controls.button_down(ctx, button)
+            }
+            _ => {
+                // Menus are driven by the keys the gamepad actions stand for.
+                let keycode = self.bindings.button_action(button).and_then(Action::menu_key);
+                if let Some(keycode) = keycode {
+                    self.key_down_event(ctx, keycode, KeyMods::default(), false);
+                }
+                return;
+            }
+        }
+        self.poll_game(ctx);
+    }
+
+    fn gamepad_button_up_event(&mut self, ctx: &mut Context, button: Button, id: GamepadId) {
+        if let Scene::Match(game) = &mut self.scene {
+            game.gamepad_button_up_event(ctx, button, id);
+        }
+        self.poll_game(ctx);
+    }
+
+    fn gamepad_axis_event(&mut self, ctx: &mut Context, axis: Axis, value: f32, id: GamepadId) {
+        if let Scene::Match(game) = &mut self.scene {
+            game.gamepad_axis_event(ctx, axis, value, id);
+        }
+    }
+}
+
+struct MainMenu {
+    menu: Menu,
+}
+
+impl MainMenu {
+    const ITEMS: [&'static str; 7] = [
+        "Play",
+        "Resume saved match",
+        "Match setup",
+        "Network game",
+        "Leaderboard",
+        "Options",
+        "Quit",
+    ];
+
+    fn new() -> Self {
+        let items = Self::ITEMS.iter().map(|item| (*item).to_owned()).collect();
+        MainMenu {
+            menu: Menu::new("Crabs!", items),
+        }
+    }
+
+    fn key_down(&mut self, keycode: KeyCode, settings: &MatchSettings) -> Option<Transition> {
+        match self.menu.key_down(keycode)? {
+            MenuAction::Activate(0) => Some(Transition::Play(settings.clone())),
+            MenuAction::Activate(1) => Some(Transition::Resume),
+            MenuAction::Activate(2) => Some(Transition::Setup),
+            MenuAction::Activate(3) => Some(Transition::Lobby),
+            MenuAction::Activate(4) => Some(Transition::Leaderboard),
+            MenuAction::Activate(5) => Some(Transition::Options),
+            MenuAction::Activate(_) | MenuAction::Back => Some(Transition::Quit),
+            MenuAction::Change(..) => None,
+        }
+    }
+}
+
+struct OptionsScreen {
+    options: Options,
+    menu: Menu,
+}
+
+impl OptionsScreen {
+    fn new(options: Options) -> Self {
+        let mut screen = OptionsScreen {
+            options,
+            menu: Menu::new("Options", vec![]),
+        };
+        screen.refresh();
+        screen
+    }
+
+    fn refresh(&mut self) {
+        let mut items = self.options.items();
+        items.push(String::from("Controls"));
+        items.push(String::from("Back"));
+        self.menu.set_items(items);
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode) -> Option<Transition> {
+        let (idx, delta) = match self.menu.key_down(keycode)? {
+            MenuAction::Activate(idx) => (idx, 0),
+            MenuAction::Change(idx, delta) => (idx, delta),
+            MenuAction::Back => return Some(Transition::Menu),
+        };
+        match idx {
+            idx if idx < Options::ITEMS => {
+                let fullscreen = self.options.fullscreen;
+                self.options.change(idx, delta);
+                if self.options.fullscreen != fullscreen {
+                    if let Err(e) = self.options.apply(ctx) {
+                        eprintln!("Failed to change the window mode: {}", e);
+                    }
+                }
+            }
+            idx if idx == Options::ITEMS => return Some(Transition::Controls),
+            _ => return Some(Transition::Menu),
+        }
+        self.refresh();
+        None
+    }
+}
+
+/// Lists the bindings of every action and rebinds them.
+struct ControlsScreen {
+    bindings: Bindings,
+    defaults: Bindings,
+    menu: Menu,
+    /// The action waiting for a key or a button.
+    waiting: Option<Action>,
+}
+
+impl ControlsScreen {
+    fn new(bindings: Bindings, defaults: Bindings) -> Self {
+        let mut screen = ControlsScreen {
+            bindings,
+            defaults,
+            menu: Menu::new("Controls", vec![]),
+            waiting: None,
+        };
+        screen.refresh();
+        screen
+    }
+
+    fn refresh(&mut self) {
+        let mut items: Vec<String> = Action::ALL
+            .iter()
+            .map(|&action| {
+                let keys: Vec<String> =
+                    self.bindings.keys(action).iter().map(|key| format!("{:?}", key)).collect();
+                let buttons: Vec<String> =
+                    self.bindings.buttons(action).iter().map(|b| format!("{:?}", b)).collect();
+                format!("{}: {} / {}", action.label(), keys.join(", "), buttons.join(", "))
+            })
+            .collect();
+        items.push(String::from("Reset to defaults"));
+        items.push(String::from("Back"));
+        self.menu.set_items(items);
+        self.menu.set_message(
+            self.waiting
+                .map(|action| format!("Press a key or a button for {} (Esc cancels)", action.label())),
+        );
+    }
+
+    fn key_down(&mut self, ctx: &mut Context, keycode: KeyCode) -> Option<Transition> {
+        if let Some(action) = self.waiting.take() {
+            if keycode != KeyCode::Escape {
+                self.bindings.bind_key(action, keycode, true);
+                self.save(ctx);
+            }
+            self.refresh();
+            return None;
+        }
+        match self.menu.key_down(keycode)? {
+            MenuAction::Activate(idx) if idx < Action::ALL.len() => {
+                self.waiting = Some(Action::ALL[idx]);
+            }
+            MenuAction::Activate(idx) if idx == Action::ALL.len() => {
+                self.bindings = self.defaults.clone();
+                self.save(ctx);
+            }
+            MenuAction::Activate(_) | MenuAction::Back => return Some(Transition::Options),
+            MenuAction::Change(..) => (),
+        }
+        self.refresh();
+        None
+    }
+
+    fn button_down(&mut self, ctx: &mut Context, button: Button) {
+        if let Some(action) = self.waiting.take() {
+            self.bindings.bind_button(action, button, true);
+            self.save(ctx);
+            self.refresh();
+        }
+    }
+
+    fn save(&self, ctx: &mut Context) {
+        if let Err(e) = self.bindings.save(ctx) {
+            eprintln!("Failed to save the controls: {}", e);
+        }
+    }
+}
@@ -0,0 +1,457 @@
+use crate::config::GameConfig;
+use crate::map::Map;
+use crate::net::{self, Connection, Lockstep, Message, Spectators};
+use crate::outcome::MatchResult;
+use crate::package::MapPackage;
+use crate::settings::{MapSource, MatchSettings};
+use crate::simulation::{Command, Simulation, TickInput};
+use crate::terrain;
+use ggez::GameResult;
+use std::collections::VecDeque;
+use std::path::Path;
+
+/// Loads the map of a match without a window, reading the images of map
+/// packages from the `resources` directory.
+pub fn load_map(
+    cfg: &GameConfig,
+    settings: &MatchSettings,
+    resources: &Path,
+) -> GameResult<(Map, Option<MapPackage>)> {
+    let load_image = |path: &str| -> GameResult<(Vec<u8>, u16, u16)> {
+        let image = image::open(MapPackage::file(resources, path))?.to_rgba();
+        let (width, height) = (image.width() as u16, image.height() as u16);
+        Ok((image.into_raw(), width, height))
+    };
+    let (mut map, package, water_level) = match &settings.map {
+        MapSource::Package(manifest) => {
+            let package = MapPackage::load_file(resources, manifest)?;
+            let (pixels, width, height) = load_image(&package.foreground)?;
+            let mut map = Map::new(&pixels, width, height);
+            if let Some(indestructible) = &package.indestructible {
+                map.set_indestructible(&load_image(indestructible)?.0);
+            }
+            let water_level = package.water_level;
+            (map, Some(package), water_level)
+        }
+        MapSource::Generated { seed } => {
+            let (width, height) = (cfg.screen.width as u16, cfg.screen.height as u16);
+            let map = Map::new(&terrain::generate(width, height, *seed), width, height);
+            (map, None, Some(height as f32 - terrain::WATER_DEPTH))
+        }
+    };
+    if settings.rules.water {
+        map.set_water_level(water_level);
+    }
+    Ok((map, package))
+}
+
+/// Runs a match for clients that only send what their players do. The
+/// server alone simulates the match: it takes the input of the active team
+/// from the client playing it, drops everything else, and sends every tick
+/// it simulated to all clients, so no client can decide on its own what
+/// happens to the crabs or the map.
+pub struct Server {
+    sim: Simulation,
+    clients: Vec<Client>,
+    spectators: Spectators,
+    /// Inputs of the active team waiting for their tick.
+    queue: VecDeque<TickInput>,
+    /// Ticks the active team didn't send anything for.
+    waited: u32,
+    log: Vec<String>,
+}
+
+struct Client {
+    connection: Connection,
+    address: String,
+    teams: Vec<usize>,
+    connected: bool,
+}
+
+impl Server {
+    /// Ticks to wait for the active team before simulating it idle, so a
+    /// slow client doesn't stop the turn timer.
+    const INPUT_TIMEOUT: u32 = Simulation::FPS / 2;
+    const MAX_QUEUED: usize = Simulation::FPS as usize;
+
+    /// Starts the match, dealing out the human teams to the clients.
+    pub fn start(sim: Simulation, (clients, spectators): (Vec<(Connection, String)>, Spectators)) -> Self {
+        let teams = net::assign_teams(sim.settings(), clients.len());
+        let mut server = Server {
+            sim,
+            clients: clients
+                .into_iter()
+                .zip(teams)
+                .map(|((connection, address), teams)| Client {
+                    connection,
+                    address,
+                    teams,
+                    connected: true,
+                })
+                .collect(),
+            spectators,
+            queue: VecDeque::new(),
+            waited: 0,
+            log: vec![],
+        };
+        for i in 0..server.clients.len() {
+            let start = Message::Start {
+                settings: server.sim.settings().clone(),
+                teams: server.clients[i].teams.clone(),
+                authoritative: true,
+            };
+            server.send(i, &start);
+            let teams: Vec<&str> = server.clients[i]
+                .teams
+                .iter()
+                .map(|&team| server.sim.settings().teams[team].name.as_str())
+                .collect();
+            let line = format!("{} plays {}", server.clients[i].address, teams.join(", "));
+            server.log.push(line);
+        }
+        server
+    }
+
+    pub fn simulation(&self) -> &Simulation {
+        &self.sim
+    }
+
+    pub fn result(&self) -> Option<MatchResult> {
+        self.sim.result()
+    }
+
+    /// Clients still in the match.
+    pub fn connected(&self) -> usize {
+        self.clients.iter().filter(|client| client.connected).count()
+    }
+
+    /// Spectators watching the match.
+    pub fn spectators(&self) -> usize {
+        self.spectators.len()
+    }
+
+    /// What happened since the last call, one line per event.
+    pub fn take_log(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.log)
+    }
+
+    /// Receives from the clients and simulates the next tick once the
+    /// active team acted or took too long, meant to be called
+    /// `Simulation::FPS` times a second.
+    pub fn update(&mut self) {
+        self.poll();
+        self.let_spectators_in();
+        if self.sim.result().is_some() {
+            return;
+        }
+        let input = match self.next_input() {
+            Some(input) => input,
+            None => return,
+        };
+        let team = self.sim.active_player_idx();
+        self.sim.step(&input);
+        self.sim.take_events();
+        if self.sim.active_player_idx() != team {
+            // What was sent for the last turn is too late now.
+            self.queue.clear();
+            self.waited = 0;
+        }
+        let tick = self.sim.tick();
+        self.broadcast(&Message::Input { tick, input });
+        if tick.is_multiple_of(Lockstep::HASH_INTERVAL) {
+            let hash = self.sim.state_hash();
+            self.broadcast(&Message::Hash { tick, hash });
+        }
+        if let Some(result) = self.sim.result() {
+            self.broadcast(&Message::Result { result });
+            let names: Vec<&str> = self.sim.settings().teams.iter().map(|t| t.name.as_str()).collect();
+            let description = result.describe(&names, &self.sim.settings().sides());
+            let line = format!("Match over after {} ticks: {}", tick, description);
+            self.log.push(line);
+        }
+    }
+
+    /// Sends what is still queued, e.g. the result before shutting down.
+    pub fn flush(&mut self) {
+        for client in self.clients.iter_mut().filter(|client| client.connected) {
+            client.connection.flush().ok();
+        }
+    }
+
+    fn let_spectators_in(&mut self) {
+        if let Err(e) = self.spectators.accept() {
+            self.log.push(format!("Could not take in spectators: {}", e));
+        }
+        if !self.spectators.wants_state() {
+            return;
+        }
+        match self.sim.snapshot() {
+            Ok(state) => {
+                let mut queued = vec![];
+                if let Some(result) = self.sim.result() {
+                    queued.push(Message::Result { result });
+                }
+                self.spectators.send_state(self.sim.settings(), &state, true, &queued);
+                let line = format!("Spectators joined, {} watching", self.spectators.len());
+                self.log.push(line);
+            }
+            Err(e) => self.log.push(format!("Could not send the match to spectators: {}", e)),
+        }
+    }
+
+    fn next_input(&mut self) -> Option<TickInput> {
+        if !self.sim.is_human_turn() {
+            return Some(TickInput::default());
+        }
+        let team = self.sim.active_player_idx();
+        let played = self
+            .clients
+            .iter()
+            .any(|client| client.connected && client.teams.contains(&team));
+        if !played {
+            let name = &self.sim.active_player().name;
+            self.log.push(format!("{} has nobody left to play it and surrenders", name));
+            return Some(TickInput {
+                commands: vec![Command::Surrender],
+                ..TickInput::default()
+            });
+        }
+        if let Some(input) = self.queue.pop_front() {
+            self.waited = 0;
+            return Some(input);
+        }
+        if self.waited < Self::INPUT_TIMEOUT {
+            self.waited += 1;
+            return None;
+        }
+        Some(TickInput::default())
+    }
+
+    fn poll(&mut self) {
+        for i in 0..self.clients.len() {
+            if !self.clients[i].connected {
+                continue;
+            }
+            let messages = match self.clients[i].connection.receive() {
+                Ok(messages) => messages,
+                Err(e) => {
+                    self.disconnect(i, &e.to_string());
+                    continue;
+                }
+            };
+            for message in messages {
+                match message {
+                    Message::Input { input, .. } => self.receive_input(i, input),
+                    Message::Desync { tick } => {
+                        let line = format!("{} went out of sync at tick {}", self.clients[i].address, tick);
+                        self.log.push(line);
+                    }
+                    _ => (),
+                }
+            }
+        }
+    }
+
+    /// Queues the input if it's from the client playing the active team.
+    fn receive_input(&mut self, client: usize, input: TickInput) {
+        let address = &self.clients[client].address;
+        let team = self.sim.active_player_idx();
+        if !self.sim.is_human_turn() || !self.clients[client].teams.contains(&team) {
+            self.log.push(format!("Ignored input from {}: not their turn", address));
+            return;
+        }
+        let sanitized = input.sanitized();
+        if sanitized != input {
+            self.log.push(format!("Dropped invalid values from {}", address));
+        }
+        if self.queue.len() >= Self::MAX_QUEUED {
+            self.log.push(format!("Ignored input from {}: too many inputs", address));
+            return;
+        }
+        self.queue.push_back(sanitized);
+    }
+
+    fn send(&mut self, client: usize, message: &Message) {
+        if let Err(e) = self.clients[client].connection.send(message) {
+            self.disconnect(client, &e.to_string());
+        }
+    }
+
+    fn broadcast(&mut self, message: &Message) {
+        self.spectators.broadcast(message);
+        for i in 0..self.clients.len() {
+            if self.clients[i].connected {
+                self.send(i, message);
+            }
+        }
+    }
+
+    fn disconnect(&mut self, client: usize, reason: &str) {
+        self.clients[client].connected = false;
+        self.log.push(format!("{} left: {}", self.clients[client].address, reason));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+    use crate::net::{Host, Join};
+    use std::time::Duration;
+
+    fn wait(mut done: impl FnMut() -> bool) {
+        for _ in 0..300 {
+            if done() {
+                return;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        panic!("timed out");
+    }
+
+    /// A server with a client for each of the two teams, and its address.
+    fn start() -> (Server, String, Lockstep, Lockstep) {
+        let mut host = Host::bind(0).unwrap();
+        let address = format!("127.0.0.1:{}", host.port());
+        let mut joins = vec![Some(Join::connect(&address).unwrap())];
+        wait(|| {
+            host.accept().unwrap();
+            host.clients().len() == 1
+        });
+        joins.push(Some(Join::connect(&address).unwrap()));
+        wait(|| {
+            host.accept().unwrap();
+            host.clients().len() == 2
+        });
+        let mut settings = MatchSettings::new(&CONFIG);
+        settings.map = MapSource::Generated { seed: 5 };
+        let (map, package) = load_map(&CONFIG, &settings, Path::new("resources")).unwrap();
+        let sim = Simulation::new(&CONFIG, settings, map, package.as_ref()).unwrap();
+        let server = Server::start(sim, host.into_clients());
+        let mut started = vec![];
+        for join in joins.iter_mut() {
+            wait(|| match join.take().unwrap().poll().unwrap() {
+                Ok((_, lockstep)) => {
+                    started.push(lockstep);
+                    true
+                }
+                Err(waiting) => {
+                    *join = Some(waiting);
+                    false
+                }
+            });
+        }
+        let second = started.pop().unwrap();
+        let first = started.pop().unwrap();
+        (server, address, first, second)
+    }
+
+    #[test]
+    fn server_takes_input_of_active_team_only() {
+        let (mut server, _, mut first, mut second) = start();
+        assert!(first.is_authoritative());
+        assert_eq!(first.teams(), &[0]);
+        assert_eq!(second.teams(), &[1]);
+        let cheat = TickInput {
+            commands: vec![Command::Surrender],
+            ..TickInput::default()
+        };
+        second.send_input(1, &cheat);
+        let mut log = vec![];
+        wait(|| {
+            server.update();
+            log.extend(server.take_log());
+            log.iter().any(|line| line.contains("not their turn"))
+        });
+        assert_eq!(server.simulation().tick(), 0);
+        let input = TickInput {
+            movement: 5.0,
+            ..TickInput::default()
+        };
+        first.send_input(1, &input);
+        wait(|| {
+            server.update();
+            log.extend(server.take_log());
+            server.simulation().tick() == 1
+        });
+        assert!(log.iter().any(|line| line.contains("invalid values")), "{:?}", log);
+        assert_eq!(server.result(), None);
+        let mut received = None;
+        wait(|| {
+            second.poll();
+            received = second.take_input(1);
+            received.is_some()
+        });
+        assert_eq!(received, Some(input.sanitized()));
+    }
+
+    #[test]
+    fn server_decides_result() {
+        let (mut server, _, first, mut second) = start();
+        drop(first);
+        wait(|| {
+            server.update();
+            server.result().is_some()
+        });
+        let result = Some(MatchResult::Surrender { winner: 1, loser: 0 });
+        assert_eq!(server.result(), result);
+        assert_eq!(server.connected(), 1);
+        wait(|| {
+            second.poll();
+            second.result().is_some()
+        });
+        assert_eq!(second.result(), result);
+    }
+
+    #[test]
+    fn server_lets_spectators_in() {
+        let (mut server, address, mut first, _second) = start();
+        let input = TickInput {
+            movement: 1.0,
+            ..TickInput::default()
+        };
+        for tick in 1..=5 {
+            first.send_input(tick, &input);
+            wait(|| {
+                server.update();
+                server.simulation().tick() == tick
+            });
+        }
+        let mut join = Some(Join::spectate(&address).unwrap());
+        let mut watching = None;
+        wait(|| {
+            server.update();
+            match join.take().unwrap().poll().unwrap() {
+                Ok(started) => {
+                    watching = Some(started);
+                    true
+                }
+                Err(waiting) => {
+                    join = Some(waiting);
+                    false
+                }
+            }
+        });
+        let (_, mut watcher) = watching.unwrap();
+        assert!(watcher.is_spectating());
+        assert!(watcher.teams().is_empty());
+        assert_eq!(server.spectators(), 1);
+        let mut watched = Simulation::restore(&CONFIG, watcher.take_state().unwrap()).unwrap();
+        assert!(watched.tick() >= 5);
+
+        let until = server.simulation().tick() + 10;
+        while server.simulation().tick() < until {
+            first.send_input(server.simulation().tick() + 1, &input);
+            server.update();
+        }
+        wait(|| {
+            watcher.poll();
+            while let Some(input) = watcher.take_input(watched.tick() + 1) {
+                watched.step(&input);
+            }
+            watched.tick() == server.simulation().tick()
+        });
+        assert_eq!(watched.state_hash(), server.simulation().state_hash());
+    }
+}
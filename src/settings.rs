@@ -0,0 +1,524 @@
+use crate::config::GameConfig;
+use crate::weapon::WeaponType;
+use ggez::conf::FullscreenType;
+use ggez::{graphics, Context, GameResult};
+use serde::{Deserialize, Serialize};
+
+/// Everything chosen on the match setup screen.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct MatchSettings {
+    pub teams: Vec<TeamSettings>,
+    pub map: MapSource,
+    pub weapons: WeaponSet,
+    pub rules: Rules,
+    /// Seed of everything random in the match, e.g. where the crabs spawn.
+    pub seed: u64,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct TeamSettings {
+    pub name: String,
+    pub crabs: u8,
+    pub control: Control,
+    /// Index of the crab images in `GameConfig::players`.
+    pub style: usize,
+    /// Teams on the same side are allies and win together.
+    pub side: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Control {
+    Human,
+    Ai,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub enum MapSource {
+    /// Manifest of a map from `resources/maps`.
+    Package(String),
+    Generated { seed: u64 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum WeaponSet {
+    All,
+    NoTools,
+    PistolsOnly,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Rules {
+    /// Crabs drown below the water level of the map.
+    pub water: bool,
+    /// Seconds a team has to act before its turn is skipped.
+    pub turn_time: Option<f32>,
+    /// Draw where the aimed shot will land.
+    pub trajectory: bool,
+    /// Ranked matches never show the trajectory.
+    pub ranked: bool,
+    /// Shots hurt the crabs of the shooter's own side.
+    pub friendly_fire: bool,
+    /// Shots hurt the crab that fired them once they left it.
+    pub self_damage: bool,
+    pub crab_selection: CrabSelection,
+}
+
+/// Which crab a team plays on its turn.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum CrabSelection {
+    /// The crabs take turns in order.
+    Rotation,
+    /// Any crab, chosen before it walks, jumps or fires.
+    Free,
+    /// Like `Free`, on this many turns of the match, the rotation on the others.
+    Limited(u8),
+}
+
+/// Which crabs the shots of one crab can hit under the rules of a match.
+/// The others are immune and the shots fly through them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Targets {
+    /// Side of every team.
+    pub sides: Vec<usize>,
+    pub team: usize,
+    pub shooter: String,
+    pub friendly_fire: bool,
+    pub self_damage: bool,
+}
+
+/// Settings that are not part of a match.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Options {
+    pub scorch: bool,
+    pub fullscreen: bool,
+    /// Volumes in percent.
+    pub music_volume: u8,
+    pub effects_volume: u8,
+    pub muted: bool,
+}
+
+impl MatchSettings {
+    pub const MIN_TEAMS: usize = 2;
+    pub const MAX_TEAMS: usize = 4;
+    pub const MIN_CRABS: u8 = 1;
+    pub const MAX_CRABS: u8 = 8;
+
+    /// The match described by the static config.
+    pub fn new(cfg: &GameConfig) -> Self {
+        let teams = (0..cfg.players_count as usize)
+            .map(|i| TeamSettings {
+                name: cfg.players[i].name.to_owned(),
+                crabs: cfg.players[i].crabs_count,
+                control: Control::Human,
+                style: i,
+                side: i,
+            })
+            .collect();
+        MatchSettings {
+            teams,
+            map: MapSource::Package(cfg.map.package.to_owned()),
+            weapons: WeaponSet::All,
+            rules: Rules {
+                water: true,
+                turn_time: None,
+                trajectory: false,
+                ranked: false,
+                friendly_fire: true,
+                self_damage: false,
+                crab_selection: CrabSelection::Rotation,
+            },
+            seed: rand::random(),
+        }
+    }
+
+    pub fn add_team(&mut self, cfg: &GameConfig) {
+        if self.teams.len() < Self::MAX_TEAMS {
+            let i = self.teams.len();
+            self.teams.push(TeamSettings {
+                name: format!("Team {}", i + 1),
+                crabs: cfg.players[i % cfg.players.len()].crabs_count,
+                control: Control::Ai,
+                style: i % cfg.players.len(),
+                side: i,
+            });
+        }
+    }
+
+    pub fn remove_team(&mut self) {
+        if self.teams.len() > Self::MIN_TEAMS {
+            self.teams.pop();
+            if self.sides().iter().all(|&side| side == 0) {
+                self.set_sides(&(0..self.teams.len()).collect::<Vec<_>>());
+            }
+        }
+    }
+
+    /// The side of every team, numbered from 0 in the order they first
+    /// appear.
+    pub fn sides(&self) -> Vec<usize> {
+        let mut seen: Vec<usize> = vec![];
+        self.teams
+            .iter()
+            .map(|team| match seen.iter().position(|&side| side == team.side) {
+                Some(idx) => idx,
+                None => {
+                    seen.push(team.side);
+                    seen.len() - 1
+                }
+            })
+            .collect()
+    }
+
+    fn set_sides(&mut self, sides: &[usize]) {
+        for (team, &side) in self.teams.iter_mut().zip(sides.iter()) {
+            team.side = side;
+        }
+    }
+
+    /// Every way to split the teams into at least two sides, as `sides`
+    /// returns them, starting with each team on its own.
+    pub fn alliances(&self) -> Vec<Vec<usize>> {
+        let mut all: Vec<Vec<usize>> = vec![vec![]];
+        for _ in 0..self.teams.len() {
+            all = all
+                .into_iter()
+                .flat_map(|sides: Vec<usize>| {
+                    let count = sides.iter().max().map_or(0, |&max| max + 1);
+                    (0..=count).map(move |side| {
+                        let mut next = sides.clone();
+                        next.push(side);
+                        next
+                    })
+                })
+                .collect();
+        }
+        all.retain(|sides| sides.iter().any(|&side| side != 0));
+        all.reverse();
+        all
+    }
+
+    /// Moves on to the next split of `alliances`, or back for a negative `delta`.
+    pub fn next_alliance(&mut self, delta: i32) {
+        let all = self.alliances();
+        let count = all.len() as i32;
+        let current = all.iter().position(|sides| *sides == self.sides()).unwrap_or(0) as i32;
+        let next = ((current + delta) % count + count) % count;
+        self.set_sides(&all[next as usize]);
+    }
+
+    /// The names of the teams on each side, e.g. "Ivan + Petar vs Team 3".
+    pub fn describe_sides(&self) -> String {
+        let sides = self.sides();
+        let count = sides.iter().max().map_or(0, |&max| max + 1);
+        let names: Vec<String> = (0..count)
+            .map(|side| {
+                let teams: Vec<&str> = self
+                    .teams
+                    .iter()
+                    .zip(sides.iter())
+                    .filter(|&(_, &s)| s == side)
+                    .map(|(team, _)| team.name.as_str())
+                    .collect();
+                teams.join(" + ")
+            })
+            .collect();
+        names.join(" vs ")
+    }
+
+    /// Which crabs the shots of `shooter` of `team` can hit.
+    pub fn targets(&self, team: usize, shooter: &str) -> Targets {
+        Targets {
+            sides: self.sides(),
+            team,
+            shooter: shooter.to_owned(),
+            friendly_fire: self.rules.friendly_fire,
+            self_damage: self.rules.self_damage,
+        }
+    }
+}
+
+impl Targets {
+    /// Whether a shot can hit `crab` of `team`. The shooter itself can only
+    /// be hit once the shot `cleared` it.
+    pub fn can_hit(&self, team: usize, crab: &str, cleared: bool) -> bool {
+        if team == self.team && crab == self.shooter {
+            self.self_damage && cleared
+        } else {
+            self.friendly_fire || !self.is_ally(team)
+        }
+    }
+
+    /// Whether `team` is on the side of the shooter, its own team included.
+    pub fn is_ally(&self, team: usize) -> bool {
+        self.sides[team] == self.sides[self.team]
+    }
+}
+
+impl Control {
+    pub fn toggle(self) -> Self {
+        match self {
+            Control::Human => Control::Ai,
+            Control::Ai => Control::Human,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Control::Human => "Human",
+            Control::Ai => "AI",
+        }
+    }
+}
+
+impl WeaponSet {
+    pub fn next(self) -> Self {
+        match self {
+            WeaponSet::All => WeaponSet::NoTools,
+            WeaponSet::NoTools => WeaponSet::PistolsOnly,
+            WeaponSet::PistolsOnly => WeaponSet::All,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            WeaponSet::All => "All",
+            WeaponSet::NoTools => "No tools",
+            WeaponSet::PistolsOnly => "Pistols only",
+        }
+    }
+
+    pub fn allows(self, kind: WeaponType) -> bool {
+        match self {
+            WeaponSet::All => true,
+            WeaponSet::NoTools => !kind.is_tool(),
+            WeaponSet::PistolsOnly => kind == WeaponType::Pistol || kind == WeaponType::Skip,
+        }
+    }
+}
+
+impl CrabSelection {
+    pub fn next(self) -> Self {
+        match self {
+            CrabSelection::Rotation => CrabSelection::Free,
+            CrabSelection::Free => CrabSelection::Limited(1),
+            CrabSelection::Limited(1) => CrabSelection::Limited(3),
+            CrabSelection::Limited(3) => CrabSelection::Limited(5),
+            CrabSelection::Limited(_) => CrabSelection::Rotation,
+        }
+    }
+
+    pub fn name(self) -> String {
+        match self {
+            CrabSelection::Rotation => String::from("Rotation"),
+            CrabSelection::Free => String::from("Free choice"),
+            CrabSelection::Limited(count) => format!("{} free choices", count),
+        }
+    }
+}
+
+impl Rules {
+    pub const TURN_TIMES: [Option<f32>; 4] = [None, Some(30.0), Some(45.0), Some(60.0)];
+
+    pub fn next_turn_time(&mut self) {
+        let idx = Self::TURN_TIMES
+            .iter()
+            .position(|&time| time == self.turn_time)
+            .unwrap_or(0);
+        self.turn_time = Self::TURN_TIMES[(idx + 1) % Self::TURN_TIMES.len()];
+    }
+
+    pub fn shows_trajectory(&self) -> bool {
+        self.trajectory && !self.ranked
+    }
+}
+
+impl Options {
+    /// Number of rows `items` returns.
+    pub const ITEMS: usize = 5;
+    pub const VOLUME_STEP: u8 = 10;
+
+    pub fn new(cfg: &GameConfig) -> Self {
+        Options {
+            scorch: cfg.map.scorch,
+            fullscreen: false,
+            music_volume: cfg.audio.music_volume,
+            effects_volume: cfg.audio.effects_volume,
+            muted: false,
+        }
+    }
+
+    /// The rows of the options screens.
+    pub fn items(&self) -> Vec<String> {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        vec![
+            format!("Scorched craters: {}", on_off(self.scorch)),
+            format!("Fullscreen: {}", on_off(self.fullscreen)),
+            format!("Music volume: {}%", self.music_volume),
+            format!("Effects volume: {}%", self.effects_volume),
+            format!("Mute: {}", on_off(self.muted)),
+        ]
+    }
+
+    /// Changes the option of row `idx` of `items`. Volumes move by `delta`
+    /// steps, or go one step up and wrap around when `delta` is 0.
+    pub fn change(&mut self, idx: usize, delta: i32) {
+        let slide = |volume: u8| {
+            if delta == 0 && volume >= 100 {
+                0
+            } else if delta == 0 {
+                (volume + Self::VOLUME_STEP).min(100)
+            } else {
+                (volume as i32 + delta * Self::VOLUME_STEP as i32).clamp(0, 100) as u8
+            }
+        };
+        match idx {
+            0 => self.scorch = !self.scorch,
+            1 => self.fullscreen = !self.fullscreen,
+            2 => self.music_volume = slide(self.music_volume),
+            3 => self.effects_volume = slide(self.effects_volume),
+            4 => self.muted = !self.muted,
+            _ => (),
+        }
+    }
+
+    /// Applies the options that belong to the window.
+    pub fn apply(&self, ctx: &mut Context) -> GameResult {
+        let fullscreen = if self.fullscreen {
+            FullscreenType::Desktop
+        } else {
+            FullscreenType::Windowed
+        };
+        graphics::set_fullscreen(ctx, fullscreen)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+
+    #[test]
+    fn settings_new() {
+        let settings = MatchSettings::new(&CONFIG);
+        assert_eq!(settings.teams.len(), CONFIG.players_count as usize);
+        assert_eq!(settings.teams[1].name, CONFIG.players[1].name);
+        assert_eq!(settings.map, MapSource::Package(CONFIG.map.package.to_owned()));
+    }
+
+    #[test]
+    fn settings_teams() {
+        let mut settings = MatchSettings::new(&CONFIG);
+        settings.remove_team();
+        assert_eq!(settings.teams.len(), MatchSettings::MIN_TEAMS);
+        for _ in 0..5 {
+            settings.add_team(&CONFIG);
+        }
+        assert_eq!(settings.teams.len(), MatchSettings::MAX_TEAMS);
+        assert_eq!(settings.teams[3].control, Control::Ai);
+        assert!(settings.teams[3].style < CONFIG.players.len());
+    }
+
+    #[test]
+    fn weapon_set_allows() {
+        assert!(WeaponSet::All.allows(WeaponType::Drill));
+        assert!(!WeaponSet::NoTools.allows(WeaponType::Girder));
+        assert!(WeaponSet::NoTools.allows(WeaponType::Bazooka));
+        assert!(!WeaponSet::PistolsOnly.allows(WeaponType::Bazooka));
+        assert!(WeaponSet::PistolsOnly.allows(WeaponType::Skip));
+    }
+
+    #[test]
+    fn rules_next_turn_time() {
+        let mut rules = MatchSettings::new(&CONFIG).rules;
+        rules.next_turn_time();
+        assert_eq!(rules.turn_time, Some(30.0));
+        for _ in 0..3 {
+            rules.next_turn_time();
+        }
+        assert_eq!(rules.turn_time, None);
+    }
+
+    #[test]
+    fn options_change() {
+        let mut options = Options::new(&CONFIG);
+        assert_eq!(options.items().len(), Options::ITEMS);
+        options.change(2, -100);
+        assert_eq!(options.music_volume, 0);
+        options.change(2, 1);
+        assert_eq!(options.music_volume, Options::VOLUME_STEP);
+        options.change(3, 100);
+        assert_eq!(options.effects_volume, 100);
+        options.change(3, 0);
+        assert_eq!(options.effects_volume, 0);
+        options.change(4, 0);
+        assert!(options.muted);
+        assert_eq!(options.items()[4], "Mute: On");
+    }
+
+    #[test]
+    fn crab_selection_next() {
+        let mut selection = CrabSelection::Rotation;
+        for _ in 0..5 {
+            selection = selection.next();
+        }
+        assert_eq!(selection, CrabSelection::Rotation);
+        assert_eq!(CrabSelection::Limited(3).next().name(), "5 free choices");
+        // Picking crabs is opted into from the match setup.
+        assert_eq!(MatchSettings::new(&CONFIG).rules.crab_selection, CrabSelection::Rotation);
+    }
+
+    #[test]
+    fn settings_alliances() {
+        let mut settings = MatchSettings::new(&CONFIG);
+        settings.add_team(&CONFIG);
+        assert_eq!(settings.sides(), vec![0, 1, 2]);
+        let alliances = settings.alliances();
+        assert_eq!(alliances.len(), 4);
+        assert_eq!(alliances[0], vec![0, 1, 2]);
+        assert!(!alliances.contains(&vec![0, 0, 0]));
+
+        settings.next_alliance(1);
+        assert_eq!(settings.sides(), alliances[1]);
+        settings.next_alliance(-2);
+        assert_eq!(settings.sides(), alliances[3]);
+        settings.set_sides(&[0, 1, 1]);
+        assert_eq!(
+            settings.describe_sides(),
+            format!("{} vs {} + Team 3", settings.teams[0].name, settings.teams[1].name)
+        );
+        settings.set_sides(&[0, 0, 1]);
+        settings.remove_team();
+        assert_eq!(settings.sides(), vec![0, 1]);
+    }
+
+    #[test]
+    fn targets_follow_rules() {
+        let mut settings = MatchSettings::new(&CONFIG);
+        settings.add_team(&CONFIG);
+        settings.set_sides(&[0, 1, 0]);
+        let mut targets = settings.targets(0, "gosho");
+        assert!(targets.can_hit(0, "pesho", false));
+        assert!(targets.can_hit(2, "tosho", false));
+        assert!(!targets.can_hit(0, "gosho", true));
+
+        targets.friendly_fire = false;
+        targets.self_damage = true;
+        assert!(!targets.can_hit(0, "pesho", true));
+        assert!(!targets.can_hit(2, "tosho", true));
+        assert!(targets.can_hit(1, "tosho", false));
+        assert!(!targets.can_hit(0, "gosho", false));
+        assert!(targets.can_hit(0, "gosho", true));
+        assert!(targets.is_ally(2));
+    }
+
+    #[test]
+    fn rules_trajectory_off_in_ranked() {
+        let mut rules = MatchSettings::new(&CONFIG).rules;
+        assert!(!rules.shows_trajectory());
+        rules.trajectory = true;
+        assert!(rules.shows_trajectory());
+        rules.ranked = true;
+        assert!(!rules.shows_trajectory());
+    }
+}
@@ -0,0 +1,270 @@
+use crate::config::GameConfig;
+use crate::menu::{Menu, MenuAction};
+use crate::package::MapEntry;
+use crate::scene::Transition;
+use crate::settings::{MapSource, MatchSettings};
+use ggez::event::KeyCode;
+use ggez::{Context, GameResult};
+
+/// The match setup screen: teams, map, weapons and rules.
+pub struct MatchSetup {
+    cfg: &'static GameConfig,
+    settings: MatchSettings,
+    /// The available map packages.
+    maps: Vec<MapEntry>,
+    menu: Menu,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Row {
+    Team(usize),
+    Teams,
+    Sides,
+    Map,
+    Weapons,
+    Water,
+    TurnTime,
+    Trajectory,
+    Ranked,
+    FriendlyFire,
+    SelfDamage,
+    CrabSelection,
+    Start,
+    Back,
+}
+
+impl MatchSetup {
+    pub fn new(cfg: &'static GameConfig, settings: MatchSettings, maps: Vec<MapEntry>) -> Self {
+        let mut setup = MatchSetup {
+            cfg,
+            settings,
+            maps,
+            menu: Menu::new("Match setup", vec![]),
+        };
+        setup.refresh();
+        setup
+    }
+
+    pub fn settings(&self) -> &MatchSettings {
+        &self.settings
+    }
+
+    pub fn key_down(&mut self, keycode: KeyCode) -> Option<Transition> {
+        let transition = match self.menu.key_down(keycode)? {
+            MenuAction::Activate(idx) => self.activate(self.rows()[idx]),
+            MenuAction::Change(idx, delta) => {
+                self.change(self.rows()[idx], delta);
+                None
+            }
+            MenuAction::Back => Some(Transition::Menu),
+        };
+        self.refresh();
+        transition
+    }
+
+    pub fn draw(&self, ctx: &mut Context) -> GameResult {
+        self.menu.draw(ctx)
+    }
+
+    fn rows(&self) -> Vec<Row> {
+        let mut rows: Vec<Row> = (0..self.settings.teams.len()).map(Row::Team).collect();
+        rows.extend(&[
+            Row::Teams,
+            Row::Sides,
+            Row::Map,
+            Row::Weapons,
+            Row::Water,
+            Row::TurnTime,
+            Row::Trajectory,
+            Row::Ranked,
+            Row::FriendlyFire,
+            Row::SelfDamage,
+            Row::CrabSelection,
+            Row::Start,
+            Row::Back,
+        ]);
+        rows
+    }
+
+    fn refresh(&mut self) {
+        let items = self.rows().into_iter().map(|row| self.label(row)).collect();
+        self.menu.set_items(items);
+    }
+
+    fn label(&self, row: Row) -> String {
+        let on_off = |on: bool| if on { "On" } else { "Off" };
+        match row {
+            Row::Team(i) => {
+                let team = &self.settings.teams[i];
+                format!("{}: {}, {} crabs", team.name, team.control.name(), team.crabs)
+            }
+            Row::Teams => format!("Teams: {}", self.settings.teams.len()),
+            Row::Sides if self.settings.sides().iter().enumerate().all(|(i, &side)| i == side) => {
+                String::from("Sides: Every team for itself")
+            }
+            Row::Sides => format!("Sides: {}", self.settings.describe_sides()),
+            Row::Map => match &self.settings.map {
+                MapSource::Package(manifest) => match self.maps.iter().find(|map| &map.manifest == manifest) {
+                    Some(map) => format!("Map: {} ({} players)", map.name, map.recommended_players),
+                    None => format!("Map: {}", manifest),
+                },
+                MapSource::Generated { seed } => format!("Map: Generated #{}", seed),
+            },
+            Row::Weapons => format!("Weapons: {}", self.settings.weapons.name()),
+            Row::Water => format!("Water: {}", on_off(self.settings.rules.water)),
+            Row::TurnTime => match self.settings.rules.turn_time {
+                None => String::from("Turn time: Unlimited"),
+                Some(time) => format!("Turn time: {} s", time),
+            },
+            Row::Trajectory if self.settings.rules.ranked => String::from("Trajectory preview: Off (ranked)"),
+            Row::Trajectory => format!("Trajectory preview: {}", on_off(self.settings.rules.trajectory)),
+            Row::Ranked => format!("Ranked: {}", on_off(self.settings.rules.ranked)),
+            Row::FriendlyFire => format!("Friendly fire: {}", on_off(self.settings.rules.friendly_fire)),
+            Row::SelfDamage => format!("Self-damage: {}", on_off(self.settings.rules.self_damage)),
+            Row::CrabSelection => format!("Crab selection: {}", self.settings.rules.crab_selection.name()),
+            Row::Start => String::from("Start match"),
+            Row::Back => String::from("Back"),
+        }
+    }
+
+    fn activate(&mut self, row: Row) -> Option<Transition> {
+        match row {
+            Row::Team(i) => {
+                let team = &mut self.settings.teams[i];
+                team.control = team.control.toggle();
+            }
+            Row::Map => {
+                if let MapSource::Generated { .. } = self.settings.map {
+                    self.settings.map = MapSource::Generated {
+                        seed: rand::random::<u16>() as u64,
+                    };
+                } else {
+                    self.change(row, 1);
+                }
+            }
+            Row::Start => return Some(Transition::Play(self.settings.clone())),
+            Row::Back => return Some(Transition::Menu),
+            _ => self.change(row, 1),
+        }
+        None
+    }
+
+    fn change(&mut self, row: Row, delta: i32) {
+        match row {
+            Row::Team(i) => {
+                let team = &mut self.settings.teams[i];
+                team.crabs = (team.crabs as i32 + delta)
+                    .max(MatchSettings::MIN_CRABS as i32)
+                    .min(MatchSettings::MAX_CRABS as i32) as u8;
+            }
+            Row::Teams if delta > 0 => self.settings.add_team(self.cfg),
+            Row::Teams => self.settings.remove_team(),
+            Row::Sides => self.settings.next_alliance(delta),
+            Row::Map => {
+                // The generated map comes after the packages.
+                let count = self.maps.len() as i32 + 1;
+                let current = match &self.settings.map {
+                    MapSource::Package(manifest) => {
+                        self.maps.iter().position(|map| &map.manifest == manifest).unwrap_or(0) as i32
+                    }
+                    MapSource::Generated { .. } => count - 1,
+                };
+                let next = ((current + delta) % count + count) % count;
+                self.settings.map = match self.maps.get(next as usize) {
+                    Some(map) => {
+                        // Start from as many teams as the map is made for.
+                        let teams = (map.recommended_players as usize)
+                            .clamp(MatchSettings::MIN_TEAMS, MatchSettings::MAX_TEAMS);
+                        while self.settings.teams.len() < teams {
+                            self.settings.add_team(self.cfg);
+                        }
+                        while self.settings.teams.len() > teams {
+                            self.settings.remove_team();
+                        }
+                        MapSource::Package(map.manifest.clone())
+                    }
+                    None => MapSource::Generated {
+                        seed: rand::random::<u16>() as u64,
+                    },
+                };
+            }
+            Row::Weapons => self.settings.weapons = self.settings.weapons.next(),
+            Row::Water => self.settings.rules.water = !self.settings.rules.water,
+            Row::TurnTime => self.settings.rules.next_turn_time(),
+            Row::Trajectory => self.settings.rules.trajectory = !self.settings.rules.trajectory,
+            Row::Ranked => self.settings.rules.ranked = !self.settings.rules.ranked,
+            Row::FriendlyFire => self.settings.rules.friendly_fire = !self.settings.rules.friendly_fire,
+            Row::SelfDamage => self.settings.rules.self_damage = !self.settings.rules.self_damage,
+            Row::CrabSelection => self.settings.rules.crab_selection = self.settings.rules.crab_selection.next(),
+            Row::Start | Row::Back => (),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+    use crate::settings::{Control, WeaponSet};
+
+    fn new_setup() -> MatchSetup {
+        let maps = vec![MapEntry {
+            manifest: "/maps/island/map.toml".to_owned(),
+            name: "Crab Island".to_owned(),
+            recommended_players: 3,
+        }];
+        MatchSetup::new(&CONFIG, MatchSettings::new(&CONFIG), maps)
+    }
+
+    #[test]
+    fn setup_teams() {
+        let mut setup = new_setup();
+        setup.change(Row::Team(0), -10);
+        assert_eq!(setup.settings().teams[0].crabs, MatchSettings::MIN_CRABS);
+        setup.activate(Row::Team(1));
+        assert_eq!(setup.settings().teams[1].control, Control::Ai);
+        setup.change(Row::Teams, 1);
+        assert_eq!(setup.settings().teams.len(), 3);
+        assert_eq!(setup.rows().len(), 16);
+    }
+
+    #[test]
+    fn setup_sides() {
+        let mut setup = new_setup();
+        assert_eq!(setup.label(Row::Sides), "Sides: Every team for itself");
+        setup.change(Row::Teams, 1);
+        setup.activate(Row::Sides);
+        assert_eq!(setup.settings().sides(), vec![0, 1, 1]);
+        assert!(setup.label(Row::Sides).ends_with(" + Team 3"));
+        setup.activate(Row::FriendlyFire);
+        assert!(!setup.settings().rules.friendly_fire);
+    }
+
+    #[test]
+    fn setup_map_cycles_through_generated() {
+        let mut setup = new_setup();
+        setup.change(Row::Map, 1);
+        match setup.settings().map {
+            MapSource::Generated { .. } => (),
+            _ => panic!("expected a generated map"),
+        }
+        assert!(setup.label(Row::Map).starts_with("Map: Generated"));
+        setup.change(Row::Map, 1);
+        assert_eq!(setup.label(Row::Map), "Map: Crab Island (3 players)");
+        assert_eq!(setup.settings().teams.len(), 3);
+    }
+
+    #[test]
+    fn setup_rules_and_start() {
+        let mut setup = new_setup();
+        setup.activate(Row::Weapons);
+        setup.activate(Row::Water);
+        assert_eq!(setup.settings().weapons, WeaponSet::NoTools);
+        assert!(!setup.settings().rules.water);
+        match setup.activate(Row::Start) {
+            Some(Transition::Play(settings)) => assert_eq!(&settings, setup.settings()),
+            _ => panic!("expected the match to start"),
+        }
+    }
+}
@@ -1,18 +1,28 @@
 use ggez::graphics::Rect;
 use ggez::nalgebra::{Vector2, Point2};
+use crate::status::Status;
+use serde::{Deserialize, Serialize};
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum ShotType {
     Pistol,
     Bazooka,
 }
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Shot {
     cfg: ShotConfig,
+    #[serde(with = "crate::state::rect")]
     rect: Rect,
+    #[serde(with = "crate::state::vector")]
     velocity: Vector2<f32>,
     kind: ShotType,
+    /// What the shot does to the crabs it hits besides hurting them.
+    #[serde(default)]
+    status: Option<Status>,
+    /// Scales the damage to the crabs it hits, not the size of its blast.
+    #[serde(default = "Shot::default_multiplier")]
+    multiplier: f32,
 }
 
 impl Shot {
@@ -22,6 +32,19 @@ impl Shot {
             rect: Rect::new(pos.x, pos.y, cfg.width, cfg.height),
             velocity: cfg.speed * direction,
             kind,
+            status: None,
+            multiplier: 1.0,
+        }
+    }
+
+    fn default_multiplier() -> f32 {
+        1.0
+    }
+
+    pub fn with_status(self, status: Status) -> Self {
+        Shot {
+            status: Some(status),
+            ..self
         }
     }
 
@@ -35,16 +58,33 @@ impl Shot {
         self.cfg.damage
     }
 
+    pub fn set_multiplier(&mut self, multiplier: f32) {
+        self.multiplier = multiplier;
+    }
+
+    /// What the shot takes off the crabs it hits.
+    pub fn hit_damage(&self) -> f32 {
+        self.cfg.damage * self.multiplier
+    }
+
+    pub fn status(&self) -> Option<Status> {
+        self.status
+    }
+
     pub fn get_rect(&self) -> Rect {
         self.rect
     }
 
+    pub fn velocity(&self) -> Vector2<f32> {
+        self.velocity
+    }
+
     pub fn get_kind(&self) -> ShotType {
         self.kind
     }
 }
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, Serialize, Deserialize)]
 pub struct ShotConfig {
     pub speed: f32,
     pub damage: f32,
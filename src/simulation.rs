@@ -0,0 +1,1187 @@
+use crate::ai;
+use crate::audio::Sound;
+use crate::config::{CrabConfig, GameConfig, CONFIG};
+use crate::body::Body;
+use crate::crab::Crab;
+use crate::effects::Effect;
+use crate::gravestone::Gravestone;
+use crate::inventory::Inventory;
+use crate::map::{Map, TerrainEdit};
+use crate::outcome::MatchResult;
+use crate::package::MapPackage;
+use crate::player::Player;
+use crate::settings::{Control, CrabSelection, MatchSettings, TeamSettings};
+use crate::shot::{Shot, ShotType};
+use crate::spawn::{self, SpawnRules};
+use crate::state::StateHasher;
+use crate::stats::{self, DamageEvent, Snapshot, TeamStats};
+use crate::status::{Status, StatusKind};
+use crate::supply::SupplyCrate;
+use crate::tool::{Dig, Girder};
+use crate::weapon::WeaponType;
+use ggez::graphics::Rect;
+use ggez::nalgebra::{Point2, Vector2};
+use ggez::GameResult;
+use rand::rngs::StdRng;
+use rand::SeedableRng;
+use serde::{Deserialize, Serialize};
+use std::hash::{Hash, Hasher};
+
+/// What the active team does during one tick.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TickInput {
+    /// -1.0 to 1.0, left to right.
+    pub movement: f32,
+    /// -1.0 to 1.0, up to down.
+    pub aim: f32,
+    pub fine_aim: bool,
+    pub commands: Vec<Command>,
+}
+
+impl TickInput {
+    const MAX_COMMANDS: usize = 4;
+
+    /// The input with every value in its range and no more than a few
+    /// commands, whoever sent it.
+    pub fn sanitized(&self) -> TickInput {
+        let axis = |value: f32| if value.is_finite() { value.clamp(-1.0, 1.0) } else { 0.0 };
+        TickInput {
+            movement: axis(self.movement),
+            aim: axis(self.aim),
+            fine_aim: self.fine_aim,
+            commands: self
+                .commands
+                .iter()
+                .copied()
+                .filter(Command::is_valid)
+                .take(Self::MAX_COMMANDS)
+                .collect(),
+        }
+    }
+}
+
+/// One-off actions, applied at the start of a tick.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Command {
+    Jump,
+    Fire { power: f32 },
+    SelectWeapon(WeaponType),
+    NextCrab,
+    /// Picks a crab of the active team by its index.
+    SelectCrab(usize),
+    Surrender,
+    RotateAim(f32),
+    PlaceGirder { x: f32, y: f32 },
+}
+
+impl Command {
+    /// The most a single command turns the weapon.
+    const MAX_ROTATION: f32 = std::f32::consts::FRAC_PI_4;
+
+    fn is_valid(&self) -> bool {
+        match *self {
+            Command::Fire { power } => power.is_finite(),
+            Command::RotateAim(angle) => angle.is_finite() && angle.abs() <= Self::MAX_ROTATION,
+            Command::PlaceGirder { x, y } => x.is_finite() && y.is_finite(),
+            _ => true,
+        }
+    }
+}
+
+/// What happened during the ticks since the events were last taken.
+#[derive(Debug, Default)]
+pub struct Events {
+    pub effects: Vec<Effect>,
+    pub sounds: Vec<Sound>,
+    pub damage: Vec<DamageEvent>,
+    /// Crabs that fired a shot.
+    pub fired: Vec<String>,
+}
+
+/// The state of a match and its rules, advanced one tick at a time.
+///
+/// Nothing in here depends on the clock, the window or a random source other
+/// than the seed of the settings, so every peer that steps a simulation with
+/// the same inputs ends up in the same state.
+#[derive(Serialize, Deserialize)]
+pub struct Simulation {
+    /// Restored by `Simulation::restore`.
+    #[serde(skip, default = "Simulation::default_config")]
+    cfg: &'static GameConfig,
+    settings: MatchSettings,
+    map: Map,
+    players: Vec<Player>,
+    active_player_idx: usize,
+    shots: Vec<GameShot>,
+    shooting_in_progress: bool,
+    /// What the crabs that died so far left behind.
+    #[serde(default)]
+    gravestones: Vec<Gravestone>,
+    #[serde(default)]
+    crates: Vec<SupplyCrate>,
+    /// Time left until the dying crabs blow up.
+    #[serde(default)]
+    death_left: Option<f32>,
+    result: Option<MatchResult>,
+    stats: Vec<TeamStats>,
+    inventories: Vec<Inventory>,
+    /// Turns left on which every team may pick its crab, under
+    /// `CrabSelection::Limited`.
+    free_selects: Vec<u32>,
+    /// Whether the active team picked its crab this turn.
+    crab_selected: bool,
+    /// Whether the active crab walked, jumped or fired, after which it can't
+    /// be swapped for another one.
+    crab_locked: bool,
+    dig: Option<Dig>,
+    turn_elapsed: f32,
+    /// Damage every team took during the current turn.
+    turn_damage: Vec<f32>,
+    /// Time left to show the damage totals once the turn is resolved.
+    summary_left: Option<f32>,
+    /// Seconds the active crab walked since its last footstep.
+    footstep_elapsed: f32,
+    tick: u64,
+    #[serde(skip)]
+    events: Events,
+}
+
+impl Simulation {
+    pub const FPS: u32 = 30;
+    pub const TICK_SECONDS: f32 = 1.0 / Self::FPS as f32;
+    /// How long an AI team waits before taking its shot.
+    const AI_THINK_TIME: f32 = 1.0;
+    /// How long the damage totals stay up before the next turn.
+    const TURN_SUMMARY_TIME: f32 = 2.0;
+    const FOOTSTEP_INTERVAL: f32 = 0.3;
+    /// How long a killed crab plays its death before it blows up.
+    const DEATH_TIME: f32 = 1.0;
+    const DEATH_EXPLOSION_RADIUS: f32 = 40.0;
+    const DEATH_EXPLOSION_DAMAGE: f32 = 20.0;
+
+    /// Spawns the teams of `settings` on `map`, in the zones of `package` if
+    /// it has any.
+    pub fn new(
+        cfg: &'static GameConfig,
+        settings: MatchSettings,
+        map: Map,
+        package: Option<&MapPackage>,
+    ) -> GameResult<Self> {
+        let teams: Vec<spawn::Team> = settings
+            .teams
+            .iter()
+            .enumerate()
+            .map(|(i, team)| spawn::Team {
+                crabs: team.crabs as usize,
+                width: cfg.players[team.style].crab.width as f32,
+                height: cfg.players[team.style].crab.height as f32,
+                zones: package.map_or(vec![], |p| p.team_zones(i)),
+            })
+            .collect();
+        let mut rng = StdRng::seed_from_u64(settings.seed);
+        let points = spawn::spawn_points(&map, &teams, SpawnRules::default(), &mut rng)?;
+        let mut players: Vec<Player> = settings
+            .teams
+            .iter()
+            .zip(points.iter())
+            .map(|(team, points)| Self::new_player(team, &cfg.players[team.style].crab, points))
+            .collect();
+
+        // Necessary for placing players on the ground.
+        for player in players.iter_mut() {
+            for crab in player.crabs.iter_mut() {
+                crab.update(Vector2::new(0.0, 0.0), 0.0, &map);
+            }
+        }
+        let inventories = vec![Inventory::new(&cfg.weapons, settings.weapons); players.len()];
+        let free_selects = match settings.rules.crab_selection {
+            CrabSelection::Limited(count) => vec![count as u32; players.len()],
+            _ => vec![0; players.len()],
+        };
+        Ok(Simulation {
+            cfg,
+            settings,
+            map,
+            stats: players.iter().map(TeamStats::new).collect(),
+            inventories,
+            free_selects,
+            crab_selected: false,
+            crab_locked: false,
+            turn_damage: vec![0.0; players.len()],
+            players,
+            active_player_idx: 0,
+            shots: vec![],
+            shooting_in_progress: false,
+            gravestones: vec![],
+            crates: vec![],
+            death_left: None,
+            result: None,
+            dig: None,
+            turn_elapsed: 0.0,
+            summary_left: None,
+            footstep_elapsed: 0.0,
+            tick: 0,
+            events: Events {
+                sounds: vec![Sound::TurnStart],
+                ..Events::default()
+            },
+        })
+    }
+
+    pub fn settings(&self) -> &MatchSettings {
+        &self.settings
+    }
+
+    pub fn map(&self) -> &Map {
+        &self.map
+    }
+
+    /// The terrain changes since the last call, for the renderer.
+    pub fn take_map_edits(&mut self) -> Vec<TerrainEdit> {
+        self.map.take_edits()
+    }
+
+    pub fn players(&self) -> &[Player] {
+        &self.players
+    }
+
+    pub fn shots(&self) -> &[GameShot] {
+        &self.shots
+    }
+
+    pub fn gravestones(&self) -> &[Gravestone] {
+        &self.gravestones
+    }
+
+    pub fn crates(&self) -> &[SupplyCrate] {
+        &self.crates
+    }
+
+    pub fn active_player_idx(&self) -> usize {
+        self.active_player_idx
+    }
+
+    pub fn active_player(&self) -> &Player {
+        &self.players[self.active_player_idx]
+    }
+
+    pub fn is_shooting(&self) -> bool {
+        self.shooting_in_progress
+    }
+
+    pub fn result(&self) -> Option<MatchResult> {
+        self.result
+    }
+
+    pub fn stats(&self) -> &[TeamStats] {
+        &self.stats
+    }
+
+    pub fn inventory(&self, team: usize) -> &Inventory {
+        &self.inventories[team]
+    }
+
+    /// Whether the active team can still pick which crab plays the turn.
+    pub fn can_select_crab(&self) -> bool {
+        if self.shooting_in_progress || self.crab_locked || !self.active_player().is_alive() {
+            return false;
+        }
+        match self.settings.rules.crab_selection {
+            CrabSelection::Rotation => false,
+            CrabSelection::Free => true,
+            CrabSelection::Limited(_) => self.crab_selected || self.free_selects[self.active_player_idx] > 0,
+        }
+    }
+
+    /// Turns left on which `team` may pick its crab, when they are limited.
+    pub fn free_selects(&self, team: usize) -> Option<u32> {
+        match self.settings.rules.crab_selection {
+            CrabSelection::Limited(_) => Some(self.free_selects[team]),
+            _ => None,
+        }
+    }
+
+    pub fn turn_damage(&self) -> &[f32] {
+        &self.turn_damage
+    }
+
+    /// Whether the damage totals of the resolved turn are up.
+    pub fn shows_summary(&self) -> bool {
+        self.summary_left.is_some()
+    }
+
+    /// Number of ticks stepped so far.
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    pub fn take_events(&mut self) -> Events {
+        std::mem::take(&mut self.events)
+    }
+
+    /// Whether the active team is played by a person rather than the AI.
+    pub fn is_human_turn(&self) -> bool {
+        self.settings.teams[self.active_player_idx].control == Control::Human
+    }
+
+    /// Seconds left for the active team to act, when the turn is timed.
+    pub fn turn_time_left(&self) -> Option<f32> {
+        match self.settings.rules.turn_time {
+            Some(turn_time) if self.is_human_turn() && !self.shooting_in_progress => {
+                Some(turn_time - self.turn_elapsed)
+            }
+            _ => None,
+        }
+    }
+
+    /// The team after the active one that still has crabs.
+    pub fn next_player_idx(&self) -> usize {
+        let count = self.players.len();
+        (1..=count)
+            .map(|i| (self.active_player_idx + i) % count)
+            .find(|&i| self.players[i].is_alive())
+            .unwrap_or(self.active_player_idx)
+    }
+
+    pub fn crab(&self, name: &str) -> Option<&Crab> {
+        self.players
+            .iter()
+            .flat_map(|player| player.crabs.iter())
+            .find(|crab| crab.name == name)
+    }
+
+    pub fn is_crab_active(&self, name: &str) -> bool {
+        self.active_player().is_crab_active(name)
+    }
+
+    pub fn crab_rects(&self) -> Vec<Rect> {
+        self.players
+            .iter()
+            .flat_map(|player| player.crabs.iter().map(|crab| crab.get_rect()))
+            .collect()
+    }
+
+    /// The girder the active crab would place at `center`.
+    pub fn girder(&self, center: Point2<f32>) -> Girder {
+        Girder::new(center, self.active_player().weapon_angle())
+    }
+
+    pub fn can_place_girder(&self, girder: &Girder) -> bool {
+        self.active_player().can_place_girder(girder, &self.crab_rects(), &self.map)
+    }
+
+    /// A hash of everything that decides how the match goes on, to compare
+    /// the simulations of different peers.
+    pub fn state_hash(&self) -> u64 {
+        let mut hasher = StateHasher::new();
+        self.tick.hash(&mut hasher);
+        self.active_player_idx.hash(&mut hasher);
+        self.shooting_in_progress.hash(&mut hasher);
+        self.turn_elapsed.to_bits().hash(&mut hasher);
+        for player in self.players.iter() {
+            player.crabs.len().hash(&mut hasher);
+            for crab in player.crabs.iter() {
+                let rect = crab.get_rect();
+                crab.name.hash(&mut hasher);
+                for value in &[rect.x, rect.y, crab.get_health(), crab.facing(), crab.aim_degrees()] {
+                    value.to_bits().hash(&mut hasher);
+                }
+                crab.vertical_speed().map(f32::to_bits).hash(&mut hasher);
+            }
+            player.current_crab().map(|crab| &crab.name).hash(&mut hasher);
+        }
+        self.free_selects.hash(&mut hasher);
+        self.crab_locked.hash(&mut hasher);
+        self.inventories.hash(&mut hasher);
+        for shot in self.shots.iter() {
+            let rect = shot.get_rect();
+            rect.x.to_bits().hash(&mut hasher);
+            rect.y.to_bits().hash(&mut hasher);
+        }
+        for player in self.players.iter() {
+            for status in player.crabs.iter().flat_map(|crab| crab.statuses().iter()) {
+                status.kind.hash(&mut hasher);
+                status.turns.hash(&mut hasher);
+                status.shield.to_bits().hash(&mut hasher);
+            }
+        }
+        for supply in self.crates.iter() {
+            supply.status.hash(&mut hasher);
+        }
+        for body in self.bodies() {
+            let rect = body.get_rect();
+            rect.x.to_bits().hash(&mut hasher);
+            rect.y.to_bits().hash(&mut hasher);
+        }
+        self.death_left.map(f32::to_bits).hash(&mut hasher);
+        // They decide when the turn passes on.
+        self.summary_left.map(f32::to_bits).hash(&mut hasher);
+        for damage in self.turn_damage.iter() {
+            damage.to_bits().hash(&mut hasher);
+        }
+        self.map.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Everything needed to carry on with the match from here, e.g. for a
+    /// spectator joining in the middle of it.
+    pub fn snapshot(&self) -> serde_json::Result<serde_json::Value> {
+        serde_json::to_value(self)
+    }
+
+    /// The match as it was when `state` was taken by `snapshot`.
+    pub fn restore(cfg: &'static GameConfig, state: serde_json::Value) -> serde_json::Result<Self> {
+        let mut sim: Simulation = serde_json::from_value(state)?;
+        sim.cfg = cfg;
+        Ok(sim)
+    }
+
+    fn default_config() -> &'static GameConfig {
+        &CONFIG
+    }
+
+    /// Advances the match by one tick, with `input` from the active team.
+    /// The input is ignored when the active team is played by the AI.
+    pub fn step(&mut self, input: &TickInput) {
+        if self.result.is_some() {
+            return;
+        }
+        let seconds = Self::TICK_SECONDS;
+        self.tick += 1;
+        // A frozen crab sits its turn out.
+        let frozen = self
+            .active_player()
+            .current_crab()
+            .is_some_and(|crab| crab.has_status(StatusKind::Freeze));
+        if frozen && !self.shooting_in_progress {
+            self.skip_turn();
+        }
+        let input = if self.is_human_turn() && !frozen {
+            input.sanitized()
+        } else {
+            TickInput::default()
+        };
+        for &command in input.commands.iter() {
+            self.apply(command);
+            if self.result.is_some() {
+                return;
+            }
+        }
+
+        let idx = self.active_player_idx;
+        let walked_from = self.players[idx].current_crab().map(|crab| crab.get_pos());
+        self.players[idx].update_crab(Vector2::new(input.movement, 0.0), seconds, &self.map);
+        let (walking, moved) = match (walked_from, self.players[idx].current_crab()) {
+            (Some(from), Some(crab)) => {
+                let moved = from.x != crab.get_pos().x;
+                (moved && !crab.is_airborne(), moved && input.movement != 0.0)
+            }
+            _ => (false, false),
+        };
+        self.crab_locked |= moved;
+        if walking {
+            self.footstep_elapsed += seconds;
+            if self.footstep_elapsed >= Self::FOOTSTEP_INTERVAL {
+                self.footstep_elapsed = 0.0;
+                self.events.sounds.push(Sound::Footstep);
+            }
+        }
+
+        let aim_speed = if input.fine_aim { Crab::FINE_AIM_SPEED } else { Crab::AIM_SPEED };
+        self.players[idx].rotate_aim(input.aim * aim_speed * seconds);
+
+        if !self.shooting_in_progress {
+            self.turn_elapsed += seconds;
+            if !self.is_human_turn() {
+                if self.turn_elapsed >= Self::AI_THINK_TIME {
+                    self.play_ai_turn();
+                }
+            } else if let Some(turn_time) = self.settings.rules.turn_time {
+                if self.turn_elapsed >= turn_time {
+                    self.skip_turn();
+                }
+            }
+        }
+
+        for shot in self.shots.iter_mut() {
+            shot.update(seconds);
+        }
+
+        if let Some(dig) = &mut self.dig {
+            self.players[self.active_player_idx].dig(dig, seconds, &mut self.map);
+            if dig.is_done() {
+                self.dig = None;
+            }
+        }
+
+        // Crabs die once every shot landed, and the turn ends once they are
+        // all dead and nothing is flying around any more. The turn is passed
+        // on before the snapshot, so the statuses ticking at its end aren't
+        // credited to the team playing next.
+        let settled = self.shooting_in_progress && self.shots.is_empty() && self.dig.is_none();
+        let dying = self.players.iter().any(Player::has_dying);
+        if settled && !dying && self.bodies().all(|body| body.is_resting(&self.map)) {
+            self.resolve_turn(seconds);
+        }
+        let before = Snapshot::take(&self.players);
+        if settled && dying {
+            self.resolve_deaths(seconds);
+        }
+
+        self.handle_collisions();
+        if let Some(level) = self.map.get_water_level() {
+            let map = &self.map;
+            let sinking = self
+                .players
+                .iter()
+                .flat_map(|player| player.crabs.iter())
+                .filter(|crab| map.under_water(crab.get_center()));
+            for crab in sinking {
+                self.events.effects.push(Effect::Splash {
+                    pos: Point2::new(crab.get_center().x, level),
+                });
+                self.events.sounds.push(Sound::Splash);
+            }
+        }
+        for player in self.players.iter_mut() {
+            player.drown_crabs(&self.map);
+        }
+        let map = &self.map;
+        for body in self.gravestones.iter_mut().map(|gravestone| &mut gravestone.body) {
+            body.update(seconds, map);
+        }
+        for body in self.crates.iter_mut().map(|supply| &mut supply.body) {
+            body.update(seconds, map);
+        }
+        self.gravestones.retain(|gravestone| !gravestone.body.is_gone(map));
+        self.crates.retain(|supply| !supply.body.is_gone(map));
+        self.pick_up_crates();
+        let sides = self.settings.sides();
+        stats::record(&mut self.stats, &before, &self.players, &sides, Some(self.active_player_idx));
+        let damage = before.damage(&self.players);
+        for event in damage.iter() {
+            self.turn_damage[event.team] += event.amount;
+        }
+        if damage.iter().any(|event| self.crab(&event.crab).is_none()) {
+            self.events.sounds.push(Sound::Death);
+        }
+        if damage.iter().any(|event| self.crab(&event.crab).is_some()) {
+            self.events.sounds.push(Sound::Hurt);
+        }
+        self.events.damage.extend(damage);
+
+        let width = self.map.get_width() as f32;
+        let height = self.map.get_height() as f32;
+        let map = &self.map;
+        let effects = &mut self.events.effects;
+        let sounds = &mut self.events.sounds;
+        self.shots.retain(|shot| {
+            let pos: Point2<f32> = shot.get_rect().point().into();
+            if shot.is_alive && map.under_water(pos) {
+                if let Some(level) = map.get_water_level() {
+                    effects.push(Effect::Splash {
+                        pos: Point2::new(pos.x, level),
+                    });
+                    sounds.push(Sound::Splash);
+                }
+            }
+            !Self::is_outside(shot.get_rect(), width, height) && !map.under_water(pos) && shot.is_alive
+        });
+
+        self.result = MatchResult::check(&self.players, &sides);
+        // A team whose last crab died on its own turn has no one to play the
+        // rest of it.
+        if self.result.is_none() && !self.shooting_in_progress && !self.active_player().is_alive() {
+            self.switch_turn();
+        }
+    }
+
+    fn apply(&mut self, command: Command) {
+        let idle = !self.shooting_in_progress;
+        match command {
+            Command::Jump => {
+                let map = &self.map;
+                let player = &mut self.players[self.active_player_idx];
+                let airborne = player.current_crab().map(Crab::is_airborne);
+                player.jump(map);
+                if airborne == Some(false) && player.current_crab().map(Crab::is_airborne) == Some(true) {
+                    self.events.sounds.push(Sound::Jump);
+                    self.crab_locked = true;
+                }
+            }
+            Command::Fire { power } if idle => self.fire(power),
+            Command::SelectWeapon(weapon) if self.inventories[self.active_player_idx].has(weapon) => {
+                self.active_player_mut().set_weapon(weapon)
+            }
+            Command::NextCrab if self.can_select_crab() => {
+                self.use_crab_select();
+                self.active_player_mut().switch_crab();
+            }
+            Command::SelectCrab(idx)
+                if self.can_select_crab()
+                    && idx < self.active_player().crabs.len()
+                    && idx != self.active_player().active_crab_idx() =>
+            {
+                self.use_crab_select();
+                self.active_player_mut().select_crab(idx);
+            }
+            Command::Surrender => self.surrender(),
+            Command::RotateAim(angle) => self.active_player_mut().rotate_aim(angle),
+            Command::PlaceGirder { x, y }
+                if idle && self.active_player().weapon_kind() == Some(WeaponType::Girder) =>
+            {
+                let girder = self.girder(Point2::new(x, y));
+                if self.can_place_girder(&girder) && self.inventories[self.active_player_idx].take(WeaponType::Girder) {
+                    girder.place(&mut self.map);
+                    self.events.sounds.push(Sound::Girder);
+                    self.shooting_in_progress = true;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Counts the turn as one the active team picked its crab on.
+    fn use_crab_select(&mut self) {
+        if !self.crab_selected {
+            self.crab_selected = true;
+            let left = &mut self.free_selects[self.active_player_idx];
+            *left = left.saturating_sub(1);
+        }
+    }
+
+    fn fire(&mut self, power: f32) {
+        if self.active_player().current_crab().is_none() {
+            return;
+        }
+        let power = power.clamp(self.cfg.shots.power.min, self.cfg.shots.power.max);
+        if let Some(kind) = self.active_player().weapon_kind() {
+            // Girders are used up when they are placed.
+            if kind == WeaponType::Girder || !self.inventories[self.active_player_idx].take(kind) {
+                return;
+            }
+            if kind.is_tool() {
+                self.events.sounds.extend(Sound::fired_by(kind));
+                self.dig = self.active_player_mut().start_dig();
+                self.shooting_in_progress = true;
+                return;
+            }
+        }
+        let weapon = self.active_player().weapon_kind();
+        let shots = self.active_player_mut().fire(power + 1.0);
+        self.stats[self.active_player_idx].fired(weapon, shots.len() as u32);
+        self.spawn_shots(shots);
+    }
+
+    fn spawn_shots(&mut self, shots: Vec<Shot>) {
+        if !shots.is_empty() {
+            let weapon = self.active_player().weapon_kind();
+            self.events.sounds.extend(weapon.and_then(Sound::fired_by));
+            if let Some(crab) = self.active_player().current_crab() {
+                self.events.fired.push(crab.name.clone());
+            }
+        }
+        for shot in shots.iter() {
+            self.events.effects.push(Effect::MuzzleFlash {
+                pos: shot.get_rect().point().into(),
+                direction: shot.velocity(),
+            });
+        }
+        let weapon = self.active_player().weapon_kind();
+        self.shots = shots
+            .into_iter()
+            .map(|shot| GameShot {
+                is_alive: true,
+                shot,
+                weapon,
+                cleared: false,
+            })
+            .collect();
+        self.shooting_in_progress = true;
+    }
+
+    /// Ends the turn without doing anything, e.g. when its time runs out.
+    fn skip_turn(&mut self) {
+        self.spawn_shots(vec![]);
+    }
+
+    fn play_ai_turn(&mut self) {
+        let idx = self.active_player_idx;
+        let shooter = self.players[idx].current_crab().map(|crab| crab.name.clone()).unwrap_or_default();
+        let plan = ai::plan(
+            &self.map,
+            &self.players,
+            &self.settings.targets(idx, &shooter),
+            self.settings.weapons,
+            &self.cfg.shots.power,
+        );
+        let (weapon, shots) = match plan {
+            Some(plan) if self.inventories[idx].take(plan.weapon) => {
+                let player = &mut self.players[idx];
+                player.set_weapon(plan.weapon);
+                player.aim(plan.direction);
+                (Some(plan.weapon), player.fire(plan.power + 1.0))
+            }
+            _ => (None, vec![]),
+        };
+        self.stats[idx].fired(weapon, shots.len() as u32);
+        self.spawn_shots(shots);
+    }
+
+    fn surrender(&mut self) {
+        let loser = self.active_player_idx;
+        self.stats[loser].crabs_lost += self.players[loser].crabs.len() as u32;
+        self.active_player_mut().surrender();
+        self.shots.clear();
+        self.dig = None;
+        self.shooting_in_progress = false;
+        self.result = match MatchResult::check(&self.players, &self.settings.sides()) {
+            Some(MatchResult::Win { winner }) => Some(MatchResult::Surrender { winner, loser }),
+            result => result,
+        };
+        if self.result.is_none() {
+            self.switch_turn();
+        }
+    }
+
+    fn switch_turn(&mut self) {
+        self.turn_elapsed = 0.0;
+        self.turn_damage.iter_mut().for_each(|damage| *damage = 0.0);
+        self.summary_left = None;
+        for (stats, player) in self.stats.iter_mut().zip(self.players.iter()) {
+            stats.survived_turn(player);
+        }
+        self.crab_selected = false;
+        self.crab_locked = false;
+        // Poison hurts without anyone to credit it to.
+        let before = Snapshot::take(&self.players);
+        self.active_player_mut().tick_statuses();
+        stats::record(&mut self.stats, &before, &self.players, &self.settings.sides(), None);
+        self.active_player_mut().end_turn();
+        self.active_player_idx = self.next_player_idx();
+        self.active_player_mut().start_turn();
+        let mut rng = StdRng::seed_from_u64(self.settings.seed ^ self.tick);
+        self.crates.extend(SupplyCrate::drop(&self.map, &mut rng));
+        self.events.sounds.push(Sound::TurnStart);
+    }
+
+    /// Shows the damage totals of a turn that hurt someone for a while, then
+    /// passes the turn on.
+    fn resolve_turn(&mut self, seconds: f32) {
+        let left = match self.summary_left {
+            None if self.turn_damage.iter().any(|&damage| damage > 0.0) => Self::TURN_SUMMARY_TIME,
+            None => 0.0,
+            Some(left) => left - seconds,
+        };
+        if left > 0.0 {
+            self.summary_left = Some(left);
+        } else {
+            self.switch_turn();
+            self.shooting_in_progress = false;
+        }
+    }
+
+    /// Lets the killed crabs play their death, then blows them all up at
+    /// once. The blasts can kill more crabs, who die in turn.
+    fn resolve_deaths(&mut self, seconds: f32) {
+        let left = self.death_left.unwrap_or(Self::DEATH_TIME) - seconds;
+        if left > 0.0 {
+            self.death_left = Some(left);
+            return;
+        }
+        self.death_left = None;
+        let dead: Vec<(usize, Rect)> = self
+            .players
+            .iter_mut()
+            .enumerate()
+            .flat_map(|(team, player)| player.remove_dead().into_iter().map(move |rect| (team, rect)))
+            .collect();
+        for (team, rect) in dead {
+            self.explode(team, rect);
+        }
+    }
+
+    /// Blows up the crab of `team` that died at `rect`, hurting every crab
+    /// close enough, and leaves a gravestone there.
+    fn explode(&mut self, team: usize, rect: Rect) {
+        let center = Point2::new(rect.x + rect.w / 2.0, rect.y + rect.h / 2.0);
+        let radius = Self::DEATH_EXPLOSION_RADIUS;
+        self.map.carve_circle(center, radius / 2.0);
+        for crab in self.players.iter_mut().flat_map(|player| player.crabs.iter_mut()) {
+            let reach = radius + crab.get_rect().w / 2.0;
+            if !crab.is_dying() && (crab.get_center() - center).norm() < reach {
+                crab.take_damage(Self::DEATH_EXPLOSION_DAMAGE);
+            }
+        }
+        self.knock_bodies(center, radius);
+        self.gravestones.push(Gravestone::new(team, rect, &self.map));
+        self.events.effects.push(Effect::Impact {
+            pos: center,
+            radius,
+            kind: ShotType::Bazooka,
+            crater: true,
+        });
+        self.events.sounds.push(Sound::Death);
+        self.events.sounds.push(Sound::Explosion);
+    }
+
+    /// The gravestones and the crates.
+    fn bodies(&self) -> impl Iterator<Item = &Body> {
+        let gravestones = self.gravestones.iter().map(|gravestone| &gravestone.body);
+        gravestones.chain(self.crates.iter().map(|supply| &supply.body))
+    }
+
+    fn knock_bodies(&mut self, center: Point2<f32>, radius: f32) {
+        for body in self.gravestones.iter_mut().map(|gravestone| &mut gravestone.body) {
+            body.knock(center, radius);
+        }
+        for body in self.crates.iter_mut().map(|supply| &mut supply.body) {
+            body.knock(center, radius);
+        }
+    }
+
+    /// Gives every crate a crab touches to the first such crab.
+    fn pick_up_crates(&mut self) {
+        let players = &mut self.players;
+        self.crates.retain(|supply| {
+            let rect = supply.body.get_rect();
+            let crab = players
+                .iter_mut()
+                .flat_map(|player| player.crabs.iter_mut())
+                .find(|crab| !crab.is_dying() && crab.get_rect().overlaps(&rect));
+            match crab {
+                Some(crab) => {
+                    crab.add_status(Status::new(supply.status));
+                    false
+                }
+                None => true,
+            }
+        });
+    }
+
+    fn active_player_mut(&mut self) -> &mut Player {
+        &mut self.players[self.active_player_idx]
+    }
+
+    fn handle_collisions(&mut self) {
+        let shooter = self.active_player().current_crab().map(|crab| (crab.name.clone(), crab.get_rect()));
+        let targets = self
+            .settings
+            .targets(self.active_player_idx, shooter.as_ref().map_or("", |(name, _)| name.as_str()));
+        for shot in self.shots.iter_mut() {
+            let rect = shot.get_rect();
+            shot.cleared |= shooter.as_ref().is_none_or(|(_, crab)| !crab.overlaps(&rect));
+            let (mut player_hit, mut map_hit) = (false, false);
+            for (i, player) in self.players.iter_mut().enumerate() {
+                player_hit |= player.handle_collisions(shot.shot.clone(), i, &targets, shot.cleared);
+                map_hit |= self.map.handle_collisions(shot.shot.clone());
+            }
+            if player_hit {
+                self.stats[self.active_player_idx].hit(shot.weapon);
+            }
+            if player_hit || map_hit {
+                shot.is_alive = false;
+                for body in self.gravestones.iter_mut().map(|gravestone| &mut gravestone.body) {
+                    body.knock(rect.point().into(), shot.shot.damage());
+                }
+                for body in self.crates.iter_mut().map(|supply| &mut supply.body) {
+                    body.knock(rect.point().into(), shot.shot.damage());
+                }
+                let kind = shot.get_kind();
+                self.events.effects.push(Effect::Impact {
+                    pos: shot.get_rect().point().into(),
+                    radius: shot.shot.damage(),
+                    kind,
+                    crater: map_hit,
+                });
+                if kind == ShotType::Bazooka {
+                    self.events.sounds.push(Sound::Explosion);
+                }
+            }
+        }
+    }
+
+    fn is_outside(rect: Rect, width: f32, height: f32) -> bool {
+        rect.top() < 0.0 || rect.left() < 0.0 || rect.bottom() > height || rect.right() > width
+    }
+
+    fn new_player(team: &TeamSettings, crab_cfg: &CrabConfig, points: &[Point2<f32>]) -> Player {
+        let crabs = points
+            .iter()
+            .enumerate()
+            .map(|(i, point)| {
+                Crab::new(
+                    &format!("{}:{}", team.name, i),
+                    Rect::new(point.x, point.y, crab_cfg.width as f32, crab_cfg.height as f32),
+                )
+            })
+            .collect();
+        Player::new(&team.name, crabs)
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct GameShot {
+    shot: Shot,
+    is_alive: bool,
+    /// The weapon that fired the shot, for the stats.
+    #[serde(default)]
+    weapon: Option<WeaponType>,
+    /// Whether the shot left the crab that fired it, which it can hit from
+    /// then on when self-damage is on.
+    #[serde(default)]
+    cleared: bool,
+}
+
+impl GameShot {
+    pub fn shot(&self) -> &Shot {
+        &self.shot
+    }
+
+    pub fn get_kind(&self) -> ShotType {
+        self.shot.get_kind()
+    }
+
+    pub fn update(&mut self, seconds: f32) {
+        self.shot.update(seconds)
+    }
+
+    pub fn damage(&self) -> f32 {
+        self.shot.damage()
+    }
+
+    pub fn get_rect(&self) -> Rect {
+        self.shot.get_rect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+    use crate::settings::MapSource;
+    use crate::terrain;
+
+    fn new_simulation(seed: u64) -> Simulation {
+        let mut settings = MatchSettings::new(&CONFIG);
+        settings.map = MapSource::Generated { seed: 7 };
+        settings.seed = seed;
+        let (width, height) = (CONFIG.screen.width as u16, CONFIG.screen.height as u16);
+        let map = Map::new(&terrain::generate(width, height, 7), width, height);
+        Simulation::new(&CONFIG, settings, map, None).unwrap()
+    }
+
+    fn inputs() -> Vec<TickInput> {
+        let mut inputs: Vec<TickInput> = (0..90)
+            .map(|i| TickInput {
+                movement: if i < 20 { 1.0 } else { 0.0 },
+                aim: if i < 40 { -1.0 } else { 0.0 },
+                ..TickInput::default()
+            })
+            .collect();
+        inputs[10].commands.push(Command::Jump);
+        inputs[45].commands.push(Command::SelectWeapon(WeaponType::Bazooka));
+        inputs[50].commands.push(Command::Fire { power: 0.5 });
+        inputs
+    }
+
+    #[test]
+    fn simulation_is_deterministic() {
+        let (mut first, mut second) = (new_simulation(3), new_simulation(3));
+        assert_eq!(first.state_hash(), second.state_hash());
+        for input in inputs().iter() {
+            first.step(input);
+            second.step(input);
+            assert_eq!(first.state_hash(), second.state_hash());
+        }
+        assert_eq!(first.tick(), 90);
+        assert_eq!(first.take_events().fired.len(), 1);
+    }
+
+    #[test]
+    fn simulation_hash_follows_state() {
+        let (mut first, mut second) = (new_simulation(3), new_simulation(3));
+        first.step(&TickInput { movement: 1.0, ..TickInput::default() });
+        second.step(&TickInput::default());
+        assert_ne!(first.state_hash(), second.state_hash());
+        assert_ne!(new_simulation(3).state_hash(), new_simulation(4).state_hash());
+
+        let (mut first, mut second) = (new_simulation(3), new_simulation(3));
+        first.summary_left = Some(1.0);
+        assert_ne!(first.state_hash(), second.state_hash());
+        second.turn_damage[1] = 5.0;
+        assert_ne!(new_simulation(3).state_hash(), second.state_hash());
+    }
+
+    #[test]
+    fn simulation_ignores_commands_out_of_turn() {
+        let mut simulation = new_simulation(3);
+        simulation.step(&TickInput {
+            commands: vec![Command::SelectWeapon(WeaponType::Pistol), Command::Fire { power: 0.0 }],
+            ..TickInput::default()
+        });
+        assert!(simulation.is_shooting());
+        simulation.step(&TickInput {
+            commands: vec![Command::NextCrab, Command::Fire { power: 0.0 }],
+            ..TickInput::default()
+        });
+        assert_eq!(simulation.take_events().fired.len(), 1);
+    }
+
+    #[test]
+    fn simulation_limits_crab_selects() {
+        let mut simulation = new_simulation(3);
+        simulation.settings.rules.crab_selection = CrabSelection::Limited(1);
+        simulation.free_selects = vec![1; 2];
+        let select = |idx| TickInput {
+            commands: vec![Command::SelectCrab(idx)],
+            ..TickInput::default()
+        };
+        simulation.step(&select(1));
+        simulation.step(&select(2));
+        assert_eq!(simulation.active_player().active_crab_idx(), 2);
+        assert_eq!(simulation.free_selects(0), Some(0));
+
+        simulation.step(&TickInput { movement: 1.0, ..TickInput::default() });
+        assert!(!simulation.can_select_crab());
+        simulation.step(&select(0));
+        assert_eq!(simulation.active_player().active_crab_idx(), 2);
+
+        simulation.skip_turn();
+        while simulation.active_player_idx() == 0 {
+            simulation.step(&TickInput::default());
+        }
+        simulation.step(&select(1));
+        assert_eq!(simulation.active_player().active_crab_idx(), 1);
+        simulation.skip_turn();
+        while simulation.active_player_idx() == 1 {
+            simulation.step(&TickInput::default());
+        }
+        assert!(!simulation.can_select_crab());
+        simulation.step(&TickInput {
+            commands: vec![Command::NextCrab],
+            ..TickInput::default()
+        });
+        assert_eq!(simulation.active_player().active_crab_idx(), 0);
+    }
+
+    #[test]
+    fn simulation_passes_turn_of_dead_team() {
+        let mut settings = MatchSettings::new(&CONFIG);
+        settings.map = MapSource::Generated { seed: 7 };
+        settings.add_team(&CONFIG);
+        settings.add_team(&CONFIG);
+        let (width, height) = (CONFIG.screen.width as u16, CONFIG.screen.height as u16);
+        let map = Map::new(&terrain::generate(width, height, 7), width, height);
+        let mut simulation = Simulation::new(&CONFIG, settings, map, None).unwrap();
+
+        // The last crab of the team dies from its own turn.
+        simulation.step(&TickInput {
+            commands: vec![Command::SelectWeapon(WeaponType::Skip), Command::Fire { power: 0.0 }],
+            ..TickInput::default()
+        });
+        for crab in simulation.players[0].crabs.iter_mut() {
+            crab.reduce_health(Crab::HEALTH);
+        }
+        for _ in 0..1000 {
+            if simulation.active_player_idx() != 0 {
+                break;
+            }
+            simulation.step(&TickInput::default());
+            simulation.active_player().weapon_kind();
+            simulation.active_player().weapon_angle();
+        }
+        assert_eq!(simulation.active_player_idx(), 1);
+
+        // It drowns before it fires.
+        simulation.players[1].surrender();
+        simulation.step(&TickInput {
+            commands: vec![Command::Fire { power: 0.5 }],
+            ..TickInput::default()
+        });
+        assert_eq!(simulation.active_player_idx(), 2);
+        assert_eq!(simulation.result, None);
+    }
+
+    #[test]
+    fn simulation_chains_death_explosions() {
+        let mut simulation = new_simulation(3);
+        let map = &simulation.map;
+        let crabs = &mut simulation.players[1].crabs;
+        let offset = crabs[0].get_pos() - crabs[1].get_pos() + Vector2::new(10.0, 0.0);
+        crabs[1].move_by(offset, map);
+        crabs[0].reduce_health(Crab::HEALTH);
+        crabs[1].reduce_health(Crab::HEALTH - Simulation::DEATH_EXPLOSION_DAMAGE);
+        let count = crabs.len();
+        simulation.shooting_in_progress = true;
+
+        simulation.step(&TickInput::default());
+        assert_eq!(simulation.players[1].crabs.len(), count);
+        while simulation.players[1].crabs.len() == count {
+            simulation.step(&TickInput::default());
+        }
+        assert_eq!(simulation.gravestones().len(), 1);
+        assert!(simulation.players[1].has_dying());
+        assert!(simulation.is_shooting());
+        while simulation.players[1].has_dying() {
+            simulation.step(&TickInput::default());
+        }
+        assert_eq!(simulation.players[1].crabs.len(), count - 2);
+        assert_eq!(simulation.gravestones().len(), 2);
+        assert_eq!(simulation.stats()[0].kills, 2);
+        assert_eq!(simulation.stats()[1].crabs_lost, 2);
+
+        while simulation.active_player_idx() == 0 {
+            simulation.step(&TickInput::default());
+        }
+        assert!(simulation.gravestones().iter().all(|gravestone| gravestone.body.is_resting(simulation.map())));
+    }
+
+    #[test]
+    fn simulation_ticks_statuses() {
+        let mut simulation = new_simulation(3);
+        simulation.players[0].crabs[0].add_status(Status::new(StatusKind::Poison));
+        simulation.players[1].crabs[0].add_status(Status::new(StatusKind::Freeze));
+        simulation.step(&TickInput {
+            commands: vec![Command::SelectWeapon(WeaponType::Skip), Command::Fire { power: 0.0 }],
+            ..TickInput::default()
+        });
+        while simulation.active_player_idx() == 0 {
+            simulation.step(&TickInput::default());
+        }
+        assert_eq!(simulation.players[0].crabs[0].get_health(), Crab::HEALTH - Status::POISON_DAMAGE);
+        // The poison is nobody's doing and doesn't hold the next turn up.
+        assert_eq!(simulation.stats[0].damage_taken, Status::POISON_DAMAGE);
+        assert_eq!(simulation.stats[1].damage_dealt, 0.0);
+        assert!(simulation.turn_damage().iter().all(|&damage| damage == 0.0));
+        assert_eq!(simulation.summary_left, None);
+
+        // The frozen crab sits its turn out.
+        simulation.step(&TickInput::default());
+        assert!(simulation.is_shooting());
+        while simulation.active_player_idx() == 1 {
+            simulation.step(&TickInput::default());
+        }
+        assert!(!simulation.players[1].crabs[0].has_status(StatusKind::Freeze));
+        assert_eq!(simulation.take_events().fired.len(), 0);
+    }
+
+    #[test]
+    fn simulation_restores_snapshot() {
+        let inputs = inputs();
+        let mut simulation = new_simulation(4);
+        for input in inputs[..48].iter() {
+            simulation.step(input);
+        }
+        let text = serde_json::to_string(&simulation.snapshot().unwrap()).unwrap();
+        let mut restored = Simulation::restore(&CONFIG, serde_json::from_str(&text).unwrap()).unwrap();
+        assert_eq!(restored.state_hash(), simulation.state_hash());
+        for input in inputs[48..].iter() {
+            simulation.step(input);
+            restored.step(input);
+        }
+        assert!(restored.tick() > 48);
+        assert_eq!(restored.state_hash(), simulation.state_hash());
+    }
+}
@@ -0,0 +1,313 @@
+use crate::map::Map;
+use ggez::graphics::Rect;
+use ggez::nalgebra::{self as na, Point2};
+use ggez::GameError;
+use rand::Rng;
+use std::fmt;
+
+/// Where the crabs of a team may be placed and how many of them there are.
+pub struct Team {
+    pub crabs: usize,
+    pub width: f32,
+    pub height: f32,
+    /// Empty when the team may spawn anywhere on the map.
+    pub zones: Vec<Rect>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SpawnRules {
+    pub min_distance: f32,
+    pub min_enemy_distance: f32,
+    /// Empty space needed above a crab.
+    pub headroom: f32,
+    /// Highest bump or deepest dip under a crab.
+    pub max_slope: f32,
+    /// Spacing of the scanned columns.
+    pub step: f32,
+}
+
+impl Default for SpawnRules {
+    fn default() -> Self {
+        SpawnRules {
+            min_distance: 60.0,
+            min_enemy_distance: 150.0,
+            headroom: 20.0,
+            max_slope: 8.0,
+            step: 4.0,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum SpawnError {
+    NoValidSpot { team: usize },
+}
+
+impl fmt::Display for SpawnError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SpawnError::NoValidSpot { team } => {
+                write!(f, "the map has no valid spawn spot for team {}", team)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SpawnError {}
+
+impl From<SpawnError> for GameError {
+    fn from(e: SpawnError) -> GameError {
+        GameError::ResourceLoadError(e.to_string())
+    }
+}
+
+/// Picks the top-left corner of every crab of every team.
+///
+/// Teams take turns placing one crab at a time so they are spread fairly,
+/// and each crab goes to the candidate spot furthest from the crabs already
+/// placed. When no spot keeps the minimum distances they are relaxed, but
+/// never below the width of a crab so crabs don't overlap.
+pub fn spawn_points<R: Rng>(
+    map: &Map,
+    teams: &[Team],
+    rules: SpawnRules,
+    rng: &mut R,
+) -> Result<Vec<Vec<Point2<f32>>>, SpawnError> {
+    let candidates: Vec<Vec<Point2<f32>>> = teams
+        .iter()
+        .map(|team| candidate_spots(map, team, rules))
+        .collect();
+    let mut placed: Vec<Vec<Point2<f32>>> = vec![vec![]; teams.len()];
+    let rounds = teams.iter().map(|team| team.crabs).max().unwrap_or(0);
+    for round in 0..rounds {
+        for (i, team) in teams.iter().enumerate() {
+            if round >= team.crabs {
+                continue;
+            }
+            let spot = pick_spot(&candidates[i], &placed, i, team.width, rules, rng)
+                .ok_or(SpawnError::NoValidSpot { team: i })?;
+            placed[i].push(spot);
+        }
+    }
+    Ok(placed)
+}
+
+fn pick_spot<R: Rng>(
+    candidates: &[Point2<f32>],
+    placed: &[Vec<Point2<f32>>],
+    team: usize,
+    width: f32,
+    rules: SpawnRules,
+    rng: &mut R,
+) -> Option<Point2<f32>> {
+    for relax in [1.0, 0.5, 0.0].iter() {
+        let mut best: Option<(f32, Point2<f32>)> = None;
+        for &spot in candidates {
+            let mut score = f32::MAX;
+            let mut valid = true;
+            for (i, crabs) in placed.iter().enumerate() {
+                let min = if i == team {
+                    rules.min_distance
+                } else {
+                    rules.min_enemy_distance
+                };
+                for crab in crabs {
+                    let distance = na::distance(&spot, crab);
+                    valid &= distance >= (min * relax).max(width);
+                    score = score.min(distance);
+                }
+            }
+            if !valid {
+                continue;
+            }
+            // Jitter the score so equally good spots are picked at random.
+            let score = score.min(1.0e6) + rng.gen::<f32>() * rules.step;
+            let better = match best {
+                None => true,
+                Some((best, _)) => score > best,
+            };
+            if better {
+                best = Some((score, spot));
+            }
+        }
+        if let Some((_, spot)) = best {
+            return Some(spot);
+        }
+    }
+    None
+}
+
+/// Spots on solid ground, above water, with room for the crab above them.
+pub fn candidate_spots(map: &Map, team: &Team, rules: SpawnRules) -> Vec<Point2<f32>> {
+    let mut spots = vec![];
+    let max_x = map.get_width() as f32 - 1.0 - team.width;
+    let mut x = 0.0;
+    while x <= max_x {
+        let middle = x + team.width / 2.0;
+        let mut y = 0.0;
+        while let Some(ground) = map.first_solid_below(Point2::new(middle, y)) {
+            let spot = Point2::new(x, ground.y - team.height);
+            if fits(map, team, rules, spot) {
+                spots.push(spot);
+            }
+            match map.first_empty_below(ground) {
+                Some(empty) => y = empty.y,
+                None => break,
+            }
+        }
+        x += rules.step;
+    }
+    spots
+}
+
+fn fits(map: &Map, team: &Team, rules: SpawnRules, spot: Point2<f32>) -> bool {
+    let rect = Rect::new(spot.x, spot.y, team.width, team.height);
+    let surface = rect.bottom();
+    let top = rect.top() - rules.headroom;
+    if top < 0.0 || map.under_water(Point2::new(rect.x + rect.w / 2.0, surface)) {
+        return false;
+    }
+    let corner = Point2::new(rect.right(), rect.bottom());
+    if !team.zones.is_empty()
+        && !team
+            .zones
+            .iter()
+            .any(|zone| zone.contains(rect.point()) && zone.contains(corner))
+    {
+        return false;
+    }
+    let mut x = rect.left();
+    while x <= rect.right() {
+        let blocked = match map.first_solid_below(Point2::new(x, top)) {
+            Some(ground) => ground.y < surface - rules.max_slope,
+            None => false,
+        };
+        if blocked {
+            return false;
+        }
+        x += 1.0;
+    }
+    [rect.left(), rect.right()].iter().all(|&x| {
+        match map.first_solid_below(Point2::new(x, surface - rules.max_slope)) {
+            Some(ground) => ground.y <= surface + rules.max_slope,
+            None => false,
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+
+    // A map with a floor at row `floor` and a pit between `pit.0` and `pit.1`.
+    fn new_map(width: usize, height: usize, floor: usize, pit: (usize, usize)) -> Map {
+        let mut data = vec![];
+        for y in 0..height {
+            for x in 0..width {
+                let solid = y >= floor && (x < pit.0 || x >= pit.1);
+                data.extend(&[0, 0, 0, if solid { 255 } else { 0 }]);
+            }
+        }
+        Map::new(&data, width as u16, height as u16)
+    }
+
+    fn new_team(crabs: usize) -> Team {
+        Team {
+            crabs,
+            width: 10.0,
+            height: 8.0,
+            zones: vec![],
+        }
+    }
+
+    fn rules() -> SpawnRules {
+        SpawnRules {
+            min_distance: 20.0,
+            min_enemy_distance: 40.0,
+            headroom: 5.0,
+            max_slope: 2.0,
+            step: 2.0,
+        }
+    }
+
+    #[test]
+    fn spawn_on_ground() {
+        let map = new_map(200, 50, 40, (80, 120));
+        let mut rng = StdRng::seed_from_u64(1);
+        let points = spawn_points(&map, &[new_team(2), new_team(2)], rules(), &mut rng).unwrap();
+        assert_eq!(points.len(), 2);
+        for team in points.iter() {
+            assert_eq!(team.len(), 2);
+            for p in team {
+                assert_eq!(p.y, 32.0);
+                assert!(p.x + 10.0 < 80.0 || p.x >= 120.0);
+            }
+        }
+    }
+
+    #[test]
+    fn spawn_keeps_distance() {
+        let map = new_map(400, 50, 40, (0, 0));
+        let mut rng = StdRng::seed_from_u64(2);
+        let points = spawn_points(&map, &[new_team(2), new_team(2)], rules(), &mut rng).unwrap();
+        for (i, team) in points.iter().enumerate() {
+            for (j, other) in points.iter().enumerate() {
+                for a in team {
+                    for b in other {
+                        if a != b {
+                            let min = if i == j { 20.0 } else { 40.0 };
+                            assert!(na::distance(a, b) >= min);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spawn_in_zones_and_above_water() {
+        let mut map = new_map(200, 50, 40, (0, 0));
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut team = new_team(1);
+        team.zones = vec![Rect::new(150.0, 0.0, 50.0, 50.0)];
+        let points = spawn_points(&map, &[team], rules(), &mut rng).unwrap();
+        assert!(points[0][0].x >= 150.0);
+
+        // The whole crab has to be inside the zone, not only its corner.
+        let mut team = new_team(1);
+        team.zones = vec![Rect::new(150.0, 0.0, 20.0, 50.0)];
+        let spots = candidate_spots(&map, &team, rules());
+        assert!(!spots.is_empty());
+        assert!(spots.iter().all(|p| p.x >= 150.0 && p.x + 10.0 <= 170.0));
+
+        map.set_water_level(Some(30.0));
+        let result = spawn_points(&map, &[new_team(1)], rules(), &mut rng);
+        assert_eq!(result, Err(SpawnError::NoValidSpot { team: 0 }));
+    }
+
+    #[test]
+    fn spawn_fails_without_room_for_every_crab() {
+        // Only two crabs fit side by side on a map this small.
+        let map = new_map(30, 50, 40, (0, 0));
+        let mut rng = StdRng::seed_from_u64(4);
+        assert!(spawn_points(&map, &[new_team(2)], rules(), &mut rng).is_ok());
+        let result = spawn_points(&map, &[new_team(3)], rules(), &mut rng);
+        assert_eq!(result, Err(SpawnError::NoValidSpot { team: 0 }));
+    }
+
+    #[test]
+    fn spawn_needs_headroom() {
+        let mut map = new_map(100, 50, 40, (0, 0));
+        map.fill_polygon(&[
+            Point2::new(0.0, 30.0),
+            Point2::new(99.0, 30.0),
+            Point2::new(99.0, 31.0),
+            Point2::new(0.0, 31.0),
+        ]);
+        let spots = candidate_spots(&map, &new_team(1), rules());
+        assert!(spots.iter().all(|p| p.y == 22.0));
+    }
+}
@@ -0,0 +1,148 @@
+//! Serde support for the ggez and nalgebra types in the state of a match,
+//! used as `#[serde(with = "...")]` on the fields holding them, and the
+//! hasher peers compare the state with.
+
+use std::hash::Hasher;
+
+/// 64-bit FNV-1a, with integers written little-endian and `usize` as 64
+/// bits, so every build on every platform hashes the same state the same.
+pub struct StateHasher(u64);
+
+impl StateHasher {
+    const OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0100_0000_01b3;
+
+    pub fn new() -> Self {
+        StateHasher(Self::OFFSET)
+    }
+}
+
+impl Default for StateHasher {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Hasher for StateHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for &byte in bytes {
+            self.0 = (self.0 ^ byte as u64).wrapping_mul(Self::PRIME);
+        }
+    }
+
+    fn write_u16(&mut self, i: u16) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u32(&mut self, i: u32) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u64(&mut self, i: u64) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_u128(&mut self, i: u128) {
+        self.write(&i.to_le_bytes())
+    }
+
+    fn write_usize(&mut self, i: usize) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i16(&mut self, i: i16) {
+        self.write_u16(i as u16)
+    }
+
+    fn write_i32(&mut self, i: i32) {
+        self.write_u32(i as u32)
+    }
+
+    fn write_i64(&mut self, i: i64) {
+        self.write_u64(i as u64)
+    }
+
+    fn write_i128(&mut self, i: i128) {
+        self.write_u128(i as u128)
+    }
+
+    fn write_isize(&mut self, i: isize) {
+        self.write_u64(i as i64 as u64)
+    }
+}
+
+/// `Rect` as `[x, y, w, h]`.
+pub mod rect {
+    use ggez::graphics::Rect;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(rect: &Rect, serializer: S) -> Result<S::Ok, S::Error> {
+        [rect.x, rect.y, rect.w, rect.h].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rect, D::Error> {
+        let [x, y, w, h] = <[f32; 4]>::deserialize(deserializer)?;
+        Ok(Rect::new(x, y, w, h))
+    }
+}
+
+/// `Vector2` as `[x, y]`.
+pub mod vector {
+    use ggez::nalgebra::Vector2;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(v: &Vector2<f32>, serializer: S) -> Result<S::Ok, S::Error> {
+        [v.x, v.y].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vector2<f32>, D::Error> {
+        let [x, y] = <[f32; 2]>::deserialize(deserializer)?;
+        Ok(Vector2::new(x, y))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::StateHasher;
+    use ggez::graphics::Rect;
+    use ggez::nalgebra::Vector2;
+    use serde::{Deserialize, Serialize};
+    use std::hash::{Hash, Hasher};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Shape {
+        #[serde(with = "super::rect")]
+        rect: Rect,
+        #[serde(with = "super::vector")]
+        velocity: Vector2<f32>,
+    }
+
+    #[test]
+    fn state_round_trips_geometry() {
+        let shape = Shape {
+            rect: Rect::new(1.5, -2.0, 30.0, 0.1),
+            velocity: Vector2::new(0.3, -250.0),
+        };
+        let json = serde_json::to_string(&shape).unwrap();
+        assert_eq!(json, r#"{"rect":[1.5,-2.0,30.0,0.1],"velocity":[0.3,-250.0]}"#);
+        assert_eq!(serde_json::from_str::<Shape>(&json).unwrap(), shape);
+    }
+
+    #[test]
+    fn state_hasher_is_fnv() {
+        let hash = |value: &dyn Fn(&mut StateHasher)| {
+            let mut hasher = StateHasher::new();
+            value(&mut hasher);
+            hasher.finish()
+        };
+        assert_eq!(hash(&|_| ()), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(&|h| h.write(b"a")), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(&|h| 7usize.hash(h)), hash(&|h| 7u64.hash(h)));
+        assert_eq!(hash(&|h| 0x0102u16.hash(h)), hash(&|h| h.write(&[2, 1])));
+    }
+}
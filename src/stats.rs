@@ -0,0 +1,354 @@
+use crate::player::Player;
+use crate::weapon::WeaponType;
+use ggez::nalgebra::Point2;
+use serde::{Deserialize, Serialize};
+
+/// What a team did during a match, or over all the matches of a profile.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TeamStats {
+    pub damage_dealt: f32,
+    pub damage_taken: f32,
+    /// Damage the team did to itself, part of the damage taken.
+    pub self_damage: f32,
+    pub kills: u32,
+    pub crabs_lost: u32,
+    pub shots_fired: u32,
+    /// Shots that hit a crab.
+    pub hits: u32,
+    /// Turns that ended with the team still in the match.
+    pub turns_survived: u32,
+    pub weapons: Vec<WeaponStats>,
+    /// Empty in the totals of a profile, so it can go after the weapons in TOML.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub crabs: Vec<CrabStats>,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct WeaponStats {
+    pub weapon: WeaponType,
+    pub shots: u32,
+    pub hits: u32,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CrabStats {
+    pub name: String,
+    pub damage_dealt: f32,
+    pub damage_taken: f32,
+    pub self_damage: f32,
+    pub kills: u32,
+    pub turns_survived: u32,
+}
+
+impl TeamStats {
+    /// Empty stats for `player` and each of its crabs.
+    pub fn new(player: &Player) -> Self {
+        TeamStats {
+            crabs: player
+                .crabs
+                .iter()
+                .map(|crab| CrabStats {
+                    name: crab.name.clone(),
+                    ..CrabStats::default()
+                })
+                .collect(),
+            ..TeamStats::default()
+        }
+    }
+
+    pub fn weapon(&self, weapon: WeaponType) -> Option<&WeaponStats> {
+        self.weapons.iter().find(|stats| stats.weapon == weapon)
+    }
+
+    fn weapon_mut(&mut self, weapon: WeaponType) -> &mut WeaponStats {
+        match self.weapons.iter().position(|stats| stats.weapon == weapon) {
+            Some(idx) => &mut self.weapons[idx],
+            None => {
+                self.weapons.push(WeaponStats { weapon, shots: 0, hits: 0 });
+                self.weapons.last_mut().unwrap()
+            }
+        }
+    }
+
+    pub fn crab(&self, name: &str) -> Option<&CrabStats> {
+        self.crabs.iter().find(|crab| crab.name == name)
+    }
+
+    fn crab_mut(&mut self, name: &str) -> Option<&mut CrabStats> {
+        self.crabs.iter_mut().find(|crab| crab.name == name)
+    }
+
+    /// Counts `shots` fired with `weapon`.
+    pub fn fired(&mut self, weapon: Option<WeaponType>, shots: u32) {
+        self.shots_fired += shots;
+        if let (Some(weapon), true) = (weapon, shots > 0) {
+            self.weapon_mut(weapon).shots += shots;
+        }
+    }
+
+    /// Counts a shot of `weapon` that hit a crab.
+    pub fn hit(&mut self, weapon: Option<WeaponType>) {
+        self.hits += 1;
+        if let Some(weapon) = weapon {
+            self.weapon_mut(weapon).hits += 1;
+        }
+    }
+
+    /// Counts a turn the team and the crabs of `player` lived through.
+    pub fn survived_turn(&mut self, player: &Player) {
+        if !player.is_alive() {
+            return;
+        }
+        self.turns_survived += 1;
+        for crab in player.crabs.iter() {
+            if let Some(stats) = self.crab_mut(&crab.name) {
+                stats.turns_survived += 1;
+            }
+        }
+    }
+
+    /// Adds the team totals of `other`, e.g. a match to the lifetime totals
+    /// of a profile. The crabs are left out, they only play one match.
+    pub fn add(&mut self, other: &TeamStats) {
+        self.damage_dealt += other.damage_dealt;
+        self.damage_taken += other.damage_taken;
+        self.self_damage += other.self_damage;
+        self.kills += other.kills;
+        self.crabs_lost += other.crabs_lost;
+        self.shots_fired += other.shots_fired;
+        self.hits += other.hits;
+        self.turns_survived += other.turns_survived;
+        for weapon in other.weapons.iter() {
+            let stats = self.weapon_mut(weapon.weapon);
+            stats.shots += weapon.shots;
+            stats.hits += weapon.hits;
+        }
+    }
+}
+
+/// Health and crab count of every team, taken before something that may hurt them.
+pub struct Snapshot {
+    teams: Vec<(f32, usize)>,
+    /// The crab each team plays with.
+    active: Vec<Option<String>>,
+    /// Team, name, health and center of every crab.
+    crabs: Vec<(usize, String, f32, Point2<f32>)>,
+}
+
+/// Health a crab lost since a snapshot.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DamageEvent {
+    pub team: usize,
+    pub crab: String,
+    pub amount: f32,
+    /// Where the crab was in the snapshot, it may be gone by now.
+    pub pos: Point2<f32>,
+}
+
+impl Snapshot {
+    pub fn take(players: &[Player]) -> Self {
+        Snapshot {
+            teams: players
+                .iter()
+                .map(|player| (player.total_health(), player.crabs.len()))
+                .collect(),
+            active: players
+                .iter()
+                .map(|player| player.current_crab().map(|crab| crab.name.clone()))
+                .collect(),
+            crabs: players
+                .iter()
+                .enumerate()
+                .flat_map(|(i, player)| {
+                    player
+                        .crabs
+                        .iter()
+                        .map(move |crab| (i, crab.name.clone(), crab.get_health(), crab.get_center()))
+                })
+                .collect(),
+        }
+    }
+
+    /// The crabs that lost health since the snapshot. A crab that is gone
+    /// lost all the health it had.
+    pub fn damage(&self, players: &[Player]) -> Vec<DamageEvent> {
+        self.crabs
+            .iter()
+            .filter_map(|(team, name, health, pos)| {
+                let now = players[*team]
+                    .crabs
+                    .iter()
+                    .find(|crab| &crab.name == name)
+                    .map_or(0.0, |crab| crab.get_health().max(0.0));
+                let amount = health - now;
+                if amount > 0.0 {
+                    Some(DamageEvent {
+                        team: *team,
+                        crab: name.clone(),
+                        amount,
+                        pos: *pos,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Adds what happened since `before` to `stats`, crediting damage done to
+/// the other sides to the `attacker` and its active crab, and damage it did
+/// to itself as self-damage. Damage to allies, or without an attacker, is
+/// credited to nobody.
+pub fn record(stats: &mut [TeamStats], before: &Snapshot, players: &[Player], sides: &[usize], attacker: Option<usize>) {
+    for (i, (player, &(health, crabs))) in players.iter().zip(before.teams.iter()).enumerate() {
+        let damage = (health - player.total_health()).max(0.0);
+        let lost = crabs.saturating_sub(player.crabs.len()) as u32;
+        stats[i].damage_taken += damage;
+        stats[i].crabs_lost += lost;
+        match attacker {
+            Some(attacker) if sides[i] != sides[attacker] => {
+                stats[attacker].damage_dealt += damage;
+                stats[attacker].kills += lost;
+            }
+            Some(attacker) if i == attacker => stats[attacker].self_damage += damage,
+            _ => (),
+        }
+    }
+
+    for event in before.damage(players) {
+        let killed = !players[event.team].crabs.iter().any(|crab| crab.name == event.crab && !crab.is_dying());
+        if let Some(crab) = stats[event.team].crab_mut(&event.crab) {
+            crab.damage_taken += event.amount;
+        }
+        let attacker = match attacker {
+            Some(attacker) => attacker,
+            None => continue,
+        };
+        if let Some(crab) = before.active[attacker].as_deref().and_then(|name| stats[attacker].crab_mut(name)) {
+            if sides[event.team] != sides[attacker] {
+                crab.damage_dealt += event.amount;
+                crab.kills += killed as u32;
+            } else if event.team == attacker {
+                crab.self_damage += event.amount;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::crab::Crab;
+    use ggez::graphics::Rect;
+
+    fn new_players() -> Vec<Player> {
+        vec![
+            Player::new("ivan", vec![Crab::new("gosho", Rect::default())]),
+            Player::new(
+                "petar",
+                vec![
+                    Crab::new("pesho", Rect::default()),
+                    Crab::new("tosho", Rect::default()),
+                ],
+            ),
+        ]
+    }
+
+    #[test]
+    fn stats_record() {
+        let mut players = new_players();
+        let mut stats: Vec<TeamStats> = players.iter().map(TeamStats::new).collect();
+        let before = Snapshot::take(&players);
+        players[0].crabs[0].reduce_health(5.0);
+        players[1].crabs[0].reduce_health(30.0);
+        players[1].kill_crab("tosho".to_owned());
+        record(&mut stats, &before, &players, &[0, 1], Some(0));
+
+        assert_eq!(stats[0].damage_taken, 5.0);
+        assert_eq!(stats[0].damage_dealt, 30.0 + Crab::HEALTH);
+        assert_eq!(stats[0].kills, 1);
+        assert_eq!(stats[1].damage_taken, 30.0 + Crab::HEALTH);
+        assert_eq!(stats[1].crabs_lost, 1);
+        assert_eq!(stats[1].damage_dealt, 0.0);
+
+        let gosho = stats[0].crab("gosho").unwrap();
+        assert_eq!(gosho.damage_dealt, 30.0 + Crab::HEALTH);
+        assert_eq!(gosho.self_damage, 5.0);
+        assert_eq!(gosho.kills, 1);
+        assert_eq!(stats[0].self_damage, 5.0);
+        assert_eq!(stats[1].crab("tosho").unwrap().damage_taken, Crab::HEALTH);
+        assert_eq!(stats[1].crab("pesho").unwrap().damage_dealt, 0.0);
+    }
+
+    #[test]
+    fn stats_record_allies() {
+        let mut players = new_players();
+        let mut stats: Vec<TeamStats> = players.iter().map(TeamStats::new).collect();
+        let before = Snapshot::take(&players);
+        players[1].crabs[0].reduce_health(30.0);
+        record(&mut stats, &before, &players, &[0, 0], Some(0));
+
+        assert_eq!(stats[1].damage_taken, 30.0);
+        assert_eq!(stats[0].damage_dealt, 0.0);
+        assert_eq!(stats[0].self_damage, 0.0);
+        assert_eq!(stats[0].crab("gosho").unwrap().damage_dealt, 0.0);
+    }
+
+    #[test]
+    fn stats_record_without_attacker() {
+        let mut players = new_players();
+        let mut stats: Vec<TeamStats> = players.iter().map(TeamStats::new).collect();
+        let before = Snapshot::take(&players);
+        players[1].crabs[0].reduce_health(5.0);
+        record(&mut stats, &before, &players, &[0, 1], None);
+
+        assert_eq!(stats[1].damage_taken, 5.0);
+        assert_eq!(stats[1].crab("pesho").unwrap().damage_taken, 5.0);
+        assert_eq!(stats[0].damage_dealt, 0.0);
+        assert_eq!(stats[0].crab("gosho").unwrap().damage_dealt, 0.0);
+    }
+
+    #[test]
+    fn stats_weapons_and_totals() {
+        let players = new_players();
+        let mut stats = TeamStats::new(&players[1]);
+        stats.fired(Some(WeaponType::Bazooka), 1);
+        stats.fired(Some(WeaponType::Pistol), 3);
+        stats.fired(Some(WeaponType::Skip), 0);
+        stats.hit(Some(WeaponType::Pistol));
+        stats.survived_turn(&players[1]);
+        assert_eq!(stats.shots_fired, 4);
+        assert_eq!(stats.hits, 1);
+        assert_eq!(stats.weapon(WeaponType::Pistol), Some(&WeaponStats { weapon: WeaponType::Pistol, shots: 3, hits: 1 }));
+        assert_eq!(stats.weapon(WeaponType::Skip), None);
+        assert_eq!(stats.crab("tosho").unwrap().turns_survived, 1);
+
+        let mut totals = TeamStats::default();
+        totals.add(&stats);
+        totals.add(&stats);
+        assert_eq!(totals.shots_fired, 8);
+        assert_eq!(totals.turns_survived, 2);
+        assert_eq!(totals.weapon(WeaponType::Bazooka).unwrap().shots, 2);
+        assert!(totals.crabs.is_empty());
+    }
+
+    #[test]
+    fn snapshot_damage() {
+        let mut players = new_players();
+        let before = Snapshot::take(&players);
+        players[1].crabs[0].reduce_health(30.0);
+        players[1].kill_crab("tosho".to_owned());
+        let damage = before.damage(&players);
+
+        assert_eq!(damage.len(), 2);
+        assert_eq!(damage[0].team, 1);
+        assert_eq!(damage[0].crab, "pesho");
+        assert_eq!(damage[0].amount, 30.0);
+        assert_eq!(damage[1].crab, "tosho");
+        assert_eq!(damage[1].amount, Crab::HEALTH);
+    }
+}
@@ -0,0 +1,149 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum StatusKind {
+    /// Loses health at the end of every turn of its team.
+    Poison,
+    /// Skips the turns of its team it is the active crab on.
+    Freeze,
+    /// Takes the damage before the health does.
+    Shield,
+    /// Fires shots that deal twice the damage.
+    DoubleDamage,
+}
+
+impl StatusKind {
+    pub const ALL: [StatusKind; 4] = [
+        StatusKind::Poison,
+        StatusKind::Freeze,
+        StatusKind::Shield,
+        StatusKind::DoubleDamage,
+    ];
+
+    /// The ones found in supply crates.
+    pub const SUPPLIES: [StatusKind; 2] = [StatusKind::Shield, StatusKind::DoubleDamage];
+
+    /// Turns of its team the status lasts.
+    pub fn turns(self) -> u32 {
+        match self {
+            StatusKind::Poison => 3,
+            StatusKind::Freeze => 1,
+            StatusKind::Shield => 3,
+            StatusKind::DoubleDamage => 1,
+        }
+    }
+
+    /// A short label for the icon of the status.
+    pub fn label(self) -> &'static str {
+        match self {
+            StatusKind::Poison => "P",
+            StatusKind::Freeze => "F",
+            StatusKind::Shield => "S",
+            StatusKind::DoubleDamage => "2x",
+        }
+    }
+}
+
+/// A status effect on a crab, counted in turns of its team.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Status {
+    pub kind: StatusKind,
+    pub turns: u32,
+    /// Damage a shield still takes.
+    #[serde(default)]
+    pub shield: f32,
+}
+
+impl Status {
+    pub const POISON_DAMAGE: f32 = 5.0;
+    pub const SHIELD: f32 = 30.0;
+
+    pub fn new(kind: StatusKind) -> Self {
+        Status {
+            kind,
+            turns: kind.turns(),
+            shield: if kind == StatusKind::Shield { Self::SHIELD } else { 0.0 },
+        }
+    }
+}
+
+/// The statuses of a crab, at most one of every kind.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Statuses(Vec<Status>);
+
+impl Statuses {
+    pub fn iter(&self) -> impl Iterator<Item = &Status> {
+        self.0.iter()
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.0.iter().any(|status| status.kind == kind)
+    }
+
+    /// Adds `status`, starting it over when there already is one of its kind.
+    pub fn add(&mut self, status: Status) {
+        self.0.retain(|old| old.kind != status.kind);
+        self.0.push(status);
+    }
+
+    /// Lets the shield take what it can of `damage` and returns the rest.
+    pub fn absorb(&mut self, damage: f32) -> f32 {
+        let rest = match self.0.iter_mut().find(|status| status.kind == StatusKind::Shield) {
+            Some(shield) => {
+                let absorbed = shield.shield.min(damage);
+                shield.shield -= absorbed;
+                damage - absorbed
+            }
+            None => damage,
+        };
+        self.0.retain(|status| status.kind != StatusKind::Shield || status.shield > 0.0);
+        rest
+    }
+
+    /// Counts a turn of the team off every status and returns the poison
+    /// damage of the turn. Freeze only counts the turns the crab `played`,
+    /// which it skipped.
+    pub fn tick(&mut self, played: bool) -> f32 {
+        let damage = if self.has(StatusKind::Poison) { Status::POISON_DAMAGE } else { 0.0 };
+        for status in self.0.iter_mut() {
+            if played || status.kind != StatusKind::Freeze {
+                status.turns = status.turns.saturating_sub(1);
+            }
+        }
+        self.0.retain(|status| status.turns > 0);
+        damage
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn statuses_shield_absorbs_damage() {
+        let mut statuses = Statuses::default();
+        assert_eq!(statuses.absorb(10.0), 10.0);
+        statuses.add(Status::new(StatusKind::Shield));
+        assert_eq!(statuses.absorb(20.0), 0.0);
+        assert_eq!(statuses.absorb(25.0), 15.0);
+        assert!(!statuses.has(StatusKind::Shield));
+    }
+
+    #[test]
+    fn statuses_tick() {
+        let mut statuses = Statuses::default();
+        statuses.add(Status::new(StatusKind::Poison));
+        statuses.add(Status::new(StatusKind::Freeze));
+        statuses.add(Status::new(StatusKind::Freeze));
+        assert_eq!(statuses.iter().count(), 2);
+
+        assert_eq!(statuses.tick(false), Status::POISON_DAMAGE);
+        assert!(statuses.has(StatusKind::Freeze));
+        assert_eq!(statuses.tick(true), Status::POISON_DAMAGE);
+        assert!(!statuses.has(StatusKind::Freeze));
+        assert_eq!(statuses.tick(false), Status::POISON_DAMAGE);
+        assert_eq!(statuses.tick(false), 0.0);
+        assert_eq!(statuses.iter().count(), 0);
+    }
+}
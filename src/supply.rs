@@ -0,0 +1,40 @@
+use crate::body::Body;
+use crate::map::Map;
+use crate::status::StatusKind;
+use ggez::graphics::Rect;
+use ggez::nalgebra::Point2;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+/// A crate dropped from the sky. The first crab to touch it gets its status.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SupplyCrate {
+    pub status: StatusKind,
+    #[serde(flatten)]
+    pub body: Body,
+}
+
+impl SupplyCrate {
+    pub const SIZE: f32 = 20.0;
+    /// Chance of a crate falling at the start of a turn.
+    pub const CHANCE: f64 = 0.3;
+
+    /// Maybe a crate with a random supply falling from the top of `map`
+    /// somewhere over the ground.
+    pub fn drop(map: &Map, rng: &mut impl Rng) -> Option<Self> {
+        if !rng.gen_bool(Self::CHANCE) {
+            return None;
+        }
+        let status = StatusKind::SUPPLIES[rng.gen_range(0, StatusKind::SUPPLIES.len())];
+        let x = rng.gen_range(0.0, (map.get_width() as f32 - Self::SIZE).max(1.0));
+        let below = Point2::new(x + Self::SIZE / 2.0, 0.0);
+        let ground = map.first_solid_below(below)?;
+        if map.under_water(ground) {
+            return None;
+        }
+        Some(SupplyCrate {
+            status,
+            body: Body::new(Rect::new(x, -Self::SIZE, Self::SIZE, Self::SIZE), map),
+        })
+    }
+}
@@ -0,0 +1,71 @@
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const GRASS_COLOR: [u8; 3] = [70, 150, 50];
+const DIRT_COLOR: [u8; 3] = [130, 90, 50];
+const GRASS_DEPTH: usize = 6;
+const WAVES: usize = 4;
+/// Depth of the water on generated maps.
+pub const WATER_DEPTH: f32 = 30.0;
+
+/// RGBA pixels of rolling hills generated from `seed`.
+///
+/// The surface is a sum of sine waves, so the same seed always gives the
+/// same map.
+pub fn generate(width: u16, height: u16, seed: u64) -> Vec<u8> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    let (w, h) = (width as usize, height as usize);
+    let waves: Vec<(f32, f32, f32)> = (0..WAVES)
+        .map(|i| {
+            let amplitude = h as f32 * rng.gen_range(0.04, 0.12) / (i + 1) as f32;
+            let frequency = std::f32::consts::PI * 2.0 * (i + 1) as f32 * rng.gen_range(0.5, 1.5)
+                / w as f32;
+            let phase = rng.gen_range(0.0, std::f32::consts::PI * 2.0);
+            (amplitude, frequency, phase)
+        })
+        .collect();
+
+    let mut pixels = vec![0; 4 * w * h];
+    for x in 0..w {
+        let offset: f32 = waves
+            .iter()
+            .map(|(amplitude, frequency, phase)| amplitude * (frequency * x as f32 + phase).sin())
+            .sum();
+        let surface = (h as f32 * 0.55 + offset).max(h as f32 * 0.25).min(h as f32 * 0.9) as usize;
+        for y in surface..h {
+            let color = if y - surface < GRASS_DEPTH { GRASS_COLOR } else { DIRT_COLOR };
+            let shade = rng.gen_range(0, 16);
+            let idx = 4 * (y * w + x);
+            for c in 0..3 {
+                pixels[idx + c] = color[c].saturating_sub(shade);
+            }
+            pixels[idx + 3] = 255;
+        }
+    }
+    pixels
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::map::Map;
+    use ggez::nalgebra::Point2;
+
+    #[test]
+    fn generate_is_deterministic() {
+        assert_eq!(generate(64, 32, 7), generate(64, 32, 7));
+        assert_ne!(generate(64, 32, 7), generate(64, 32, 8));
+    }
+
+    #[test]
+    fn generate_has_ground_in_every_column() {
+        let map = Map::new(&generate(200, 100, 1), 200, 100);
+        for x in 0..200 {
+            assert_eq!(map.get(x, 0), Some(false));
+            assert_eq!(map.get(x, 99), Some(true));
+            let ground = map.first_solid_below(Point2::new(x as f32, 0.0)).unwrap();
+            assert!(ground.y >= 25.0 && ground.y <= 90.0);
+        }
+    }
+}
@@ -0,0 +1,253 @@
+use crate::map::{Map, TerrainEdit};
+use ggez::graphics::{self, DrawParam, Rect};
+use ggez::nalgebra::{Point2, Vector2};
+use ggez::{Context, GameResult};
+
+/// The map image regenerated from the collision mask. It is split into tiles
+/// so only the tiles touched by a terrain edit are uploaded again.
+pub struct MapTexture {
+    pixels: Vec<u8>,
+    width: u16,
+    height: u16,
+    scorch: bool,
+    tiles: Vec<Tile>,
+}
+
+struct Tile {
+    rect: Rect,
+    image: graphics::Image,
+    dirty: bool,
+}
+
+impl MapTexture {
+    pub const TILE_SIZE: u16 = 128;
+    pub const SCORCH_WIDTH: isize = 3;
+    pub const GIRDER_COLOR: [u8; 4] = [153, 89, 51, 255];
+
+    pub fn new(
+        ctx: &mut Context,
+        pixels: Vec<u8>,
+        width: u16,
+        height: u16,
+        scorch: bool,
+    ) -> GameResult<Self> {
+        let mut tiles = vec![];
+        for y in (0..height).step_by(Self::TILE_SIZE as usize) {
+            for x in (0..width).step_by(Self::TILE_SIZE as usize) {
+                let rect = Rect::new(
+                    x as f32,
+                    y as f32,
+                    Self::TILE_SIZE.min(width - x) as f32,
+                    Self::TILE_SIZE.min(height - y) as f32,
+                );
+                let data = crop(&pixels, width, rect);
+                let image = graphics::Image::from_rgba8(ctx, rect.w as u16, rect.h as u16, &data)?;
+                tiles.push(Tile {
+                    rect,
+                    image,
+                    dirty: false,
+                });
+            }
+        }
+        Ok(MapTexture {
+            pixels,
+            width,
+            height,
+            scorch,
+            tiles,
+        })
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+
+    pub fn height(&self) -> u16 {
+        self.height
+    }
+
+    /// Turns scorched crater edges on or off and redraws the whole map.
+    pub fn set_scorch(&mut self, scorch: bool) {
+        if self.scorch != scorch {
+            self.scorch = scorch;
+            for tile in self.tiles.iter_mut() {
+                tile.dirty = true;
+            }
+        }
+    }
+
+    /// Paints built terrain and marks the tiles touched by `edits` for upload.
+    pub fn apply(&mut self, edits: &[TerrainEdit]) {
+        for edit in edits {
+            if let TerrainEdit::Fill { spans, .. } = edit {
+                self.paint(spans, Self::GIRDER_COLOR);
+            }
+            let margin = Self::SCORCH_WIDTH as f32 + 1.0;
+            let mut bounds = edit.bounds();
+            bounds.translate(Vector2::new(-margin, -margin));
+            bounds.w += 2.0 * margin;
+            bounds.h += 2.0 * margin;
+            for tile in self.tiles.iter_mut() {
+                if tile.rect.overlaps(&bounds) {
+                    tile.dirty = true;
+                }
+            }
+        }
+    }
+
+    /// Catches up with a map that changed without edits, e.g. one restored
+    /// from a snapshot: ground the image has no pixels for was built.
+    pub fn sync(&mut self, map: &Map) {
+        for y in 0..self.height as usize {
+            for x in 0..self.width as usize {
+                let idx = 4 * (y * self.width as usize + x);
+                let built = map.get(x, y) == Some(true) && !map.is_indestructible(x, y);
+                if built && self.pixels[idx + 3] == 0 {
+                    self.pixels[idx..idx + 4].copy_from_slice(&Self::GIRDER_COLOR);
+                }
+            }
+        }
+        for tile in self.tiles.iter_mut() {
+            tile.dirty = true;
+        }
+    }
+
+    pub fn refresh(&mut self, ctx: &mut Context, map: &Map) -> GameResult {
+        for tile in self.tiles.iter_mut().filter(|tile| tile.dirty) {
+            let data = render(&self.pixels, self.width, map, tile.rect, self.scorch);
+            tile.image =
+                graphics::Image::from_rgba8(ctx, tile.rect.w as u16, tile.rect.h as u16, &data)?;
+            tile.dirty = false;
+        }
+        Ok(())
+    }
+
+    pub fn draw(&self, ctx: &mut Context, rect: Rect) -> GameResult {
+        let scale = Vector2::new(
+            rect.w / self.width as f32,
+            rect.h / self.height as f32,
+        );
+        for tile in self.tiles.iter() {
+            let dest = Point2::new(
+                rect.x + tile.rect.x * scale.x,
+                rect.y + tile.rect.y * scale.y,
+            );
+            graphics::draw(ctx, &tile.image, DrawParam::default().dest(dest).scale(scale))?;
+        }
+        Ok(())
+    }
+
+    /// Paints the pixels a fill made solid, the same ones as in the mask.
+    fn paint(&mut self, spans: &[(usize, usize, usize)], color: [u8; 4]) {
+        for &(y, x0, x1) in spans {
+            if y >= self.height as usize {
+                continue;
+            }
+            for x in x0..=x1.min(self.width as usize - 1) {
+                let idx = 4 * (y * self.width as usize + x);
+                self.pixels[idx..idx + 4].copy_from_slice(&color);
+            }
+        }
+    }
+}
+
+fn crop(pixels: &[u8], width: u16, rect: Rect) -> Vec<u8> {
+    let mut data = Vec::with_capacity(4 * (rect.w * rect.h) as usize);
+    for y in rect.y as usize..(rect.y + rect.h) as usize {
+        let start = 4 * (y * width as usize + rect.x as usize);
+        data.extend_from_slice(&pixels[start..start + 4 * rect.w as usize]);
+    }
+    data
+}
+
+/// Builds the RGBA data of `rect` from the map pixels, punching transparent
+/// holes where the mask has no ground and darkening ground next to craters
+/// when `scorch` is set.
+pub fn render(pixels: &[u8], width: u16, map: &Map, rect: Rect, scorch: bool) -> Vec<u8> {
+    let mut data = crop(pixels, width, rect);
+    for y in 0..rect.h as usize {
+        for x in 0..rect.w as usize {
+            let (mx, my) = (rect.x as usize + x, rect.y as usize + y);
+            let idx = 4 * (y * rect.w as usize + x);
+            if map.get(mx, my) != Some(true) {
+                data[idx..idx + 4].copy_from_slice(&[0, 0, 0, 0]);
+            } else if scorch && near_crater(pixels, width, map, mx, my) {
+                for c in data[idx..idx + 3].iter_mut() {
+                    *c /= 2;
+                }
+            }
+        }
+    }
+    data
+}
+
+// A crater is a pixel that has been drawn but no longer is ground.
+fn near_crater(pixels: &[u8], width: u16, map: &Map, x: usize, y: usize) -> bool {
+    let r = MapTexture::SCORCH_WIDTH;
+    for j in -r..=r {
+        for i in -r..=r {
+            if i * i + j * j > r * r {
+                continue;
+            }
+            let (nx, ny) = (x as isize + i, y as isize + j);
+            if nx < 0 || ny < 0 {
+                continue;
+            }
+            let (nx, ny) = (nx as usize, ny as usize);
+            if map.get(nx, ny) == Some(false) && pixels[4 * (ny * width as usize + nx) + 3] > 0 {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn new_pixels() -> Vec<u8> {
+        let x = vec![200, 100, 50, 255];
+        let o = vec![0, 0, 0, 0];
+
+        let image: Vec<&Vec<u8>> = vec![
+            &o, &o, &o, &o, &o, &o,
+            &x, &x, &x, &x, &x, &x,
+        ];
+        image.iter().flat_map(|color| color.iter().cloned()).collect()
+    }
+
+    #[test]
+    fn render_untouched() {
+        let pixels = new_pixels();
+        let map = Map::new(&pixels, 6, 2);
+        let data = render(&pixels, 6, &map, Rect::new(0.0, 0.0, 6.0, 2.0), true);
+        assert_eq!(data, pixels);
+    }
+
+    #[test]
+    fn render_punches_holes() {
+        let pixels = new_pixels();
+        let mut map = Map::new(&pixels, 6, 2);
+        map.carve_circle(Point2::new(0.0, 1.0), 0.0);
+        let data = render(&pixels, 6, &map, Rect::new(0.0, 1.0, 6.0, 1.0), false);
+        assert_eq!(&data[0..4], &[0, 0, 0, 0]);
+        assert_eq!(&data[4..8], &[200, 100, 50, 255]);
+        assert_eq!(&data[20..24], &[200, 100, 50, 255]);
+    }
+
+    #[test]
+    fn render_scorches_edges() {
+        let pixels = new_pixels();
+        let mut map = Map::new(&pixels, 6, 2);
+        map.carve_circle(Point2::new(0.0, 1.0), 0.0);
+        let data = render(&pixels, 6, &map, Rect::new(0.0, 1.0, 6.0, 1.0), true);
+        assert_eq!(&data[4..8], &[100, 50, 25, 255]);
+        assert_eq!(&data[20..24], &[200, 100, 50, 255]);
+    }
+}
@@ -0,0 +1,177 @@
+use crate::crab::Crab;
+use crate::map::Map;
+use crate::weapon::WeaponType;
+use ggez::graphics::Rect;
+use ggez::nalgebra::{Point2, Vector2};
+use serde::{Deserialize, Serialize};
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Girder {
+    pub center: Point2<f32>,
+    pub angle: f32,
+}
+
+impl Girder {
+    pub const LENGTH: f32 = 100.0;
+    pub const THICKNESS: f32 = 10.0;
+    pub const REACH: f32 = 200.0;
+
+    pub fn new(center: Point2<f32>, angle: f32) -> Self {
+        Girder { center, angle }
+    }
+
+    pub fn corners(&self) -> Vec<Point2<f32>> {
+        let along = Vector2::new(self.angle.cos(), self.angle.sin()) * Self::LENGTH / 2.0;
+        let across = Vector2::new(-self.angle.sin(), self.angle.cos()) * Self::THICKNESS / 2.0;
+        vec![
+            self.center - along - across,
+            self.center + along - across,
+            self.center + along + across,
+            self.center - along + across,
+        ]
+    }
+
+    pub fn bounding_rect(&self) -> Rect {
+        let corners = self.corners();
+        let mut rect = Rect::new(corners[0].x, corners[0].y, 0.0, 0.0);
+        for c in corners.iter() {
+            rect = rect.combine_with(Rect::new(c.x, c.y, 0.0, 0.0));
+        }
+        rect
+    }
+
+    /// A girder may only be placed near the crab, inside the map and away from other crabs.
+    pub fn can_place(&self, crab: &Crab, crabs: &[Rect], map: &Map) -> bool {
+        let rect = self.bounding_rect();
+        ggez::nalgebra::distance(&crab.get_center(), &self.center) <= Self::REACH
+            && rect.left() >= 0.0
+            && rect.top() >= 0.0
+            && rect.right() < map.get_width() as f32
+            && rect.bottom() < map.get_height() as f32
+            && !crabs.iter().any(|c| c.overlaps(&rect))
+    }
+
+    pub fn place(&self, map: &mut Map) {
+        map.fill_polygon(&self.corners());
+    }
+}
+
+/// Digging started by the blowtorch or the drill; it carves the terrain
+/// ahead of the crab and moves the crab along over several steps.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Dig {
+    #[serde(with = "crate::state::vector")]
+    direction: Vector2<f32>,
+    steps_left: u8,
+    elapsed: f32,
+}
+
+impl Dig {
+    pub const STEP_TIME: f32 = 0.05;
+    pub const STEP_LENGTH: f32 = 4.0;
+    pub const BLOWTORCH_STEPS: u8 = 25;
+    pub const DRILL_STEPS: u8 = 15;
+
+    pub fn new(kind: WeaponType, facing: f32) -> Option<Self> {
+        let (direction, steps_left) = match kind {
+            WeaponType::Blowtorch => (Vector2::new(facing.signum(), 0.0), Self::BLOWTORCH_STEPS),
+            WeaponType::Drill => (Vector2::new(0.0, 1.0), Self::DRILL_STEPS),
+            _ => return None,
+        };
+        Some(Dig {
+            direction,
+            steps_left,
+            elapsed: 0.0,
+        })
+    }
+
+    pub fn is_done(&self) -> bool {
+        self.steps_left == 0
+    }
+
+    pub fn update(&mut self, crab: &mut Crab, seconds: f32, map: &mut Map) {
+        self.elapsed += seconds;
+        while self.elapsed >= Self::STEP_TIME && !self.is_done() {
+            self.elapsed -= Self::STEP_TIME;
+            self.steps_left -= 1;
+            self.step(crab, map);
+        }
+    }
+
+    fn step(&self, crab: &mut Crab, map: &mut Map) {
+        let rect = crab.get_rect();
+        let (center, radius) = if self.direction.x == 0.0 {
+            let bottom = Point2::new(rect.x + rect.w / 2.0, rect.bottom());
+            (bottom + self.direction * Self::STEP_LENGTH, rect.w / 2.0)
+        } else {
+            let front = crab.get_center() + self.direction * rect.w / 2.0;
+            (front + self.direction * Self::STEP_LENGTH, rect.h / 2.0 + 2.0)
+        };
+        map.carve_circle(center, radius);
+        crab.move_by(self.direction * Self::STEP_LENGTH, map);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn new_map() -> Map {
+        let x = vec![1, 1, 1, 1];
+        let o = vec![0, 0, 0, 0];
+
+        let mut data: Vec<u8> = vec![];
+        for y in 0..20 {
+            for _ in 0..40 {
+                data.extend(if y < 5 { &o } else { &x });
+            }
+        }
+        Map::new(&data, 40, 20)
+    }
+
+    #[test]
+    fn dig_drill_goes_down() {
+        let mut map = new_map();
+        let mut crab = Crab::new("gosho", Rect::new(10.0, 1.0, 4.0, 4.0));
+        let mut dig = Dig::new(WeaponType::Drill, 1.0).unwrap();
+
+        dig.update(&mut crab, Dig::STEP_TIME * 2.0, &mut map);
+        assert!(!dig.is_done());
+        assert_eq!(map.get(12, 8), Some(false));
+        assert!(crab.get_pos().y > 1.0);
+    }
+
+    #[test]
+    fn dig_blowtorch_goes_sideways() {
+        let mut map = new_map();
+        let mut crab = Crab::new("gosho", Rect::new(10.0, 1.0, 4.0, 4.0));
+        let mut dig = Dig::new(WeaponType::Blowtorch, -1.0).unwrap();
+
+        dig.update(&mut crab, Dig::STEP_TIME * Dig::BLOWTORCH_STEPS as f32, &mut map);
+        assert!(dig.is_done());
+        assert!(crab.get_pos().x < 10.0);
+    }
+
+    #[test]
+    fn dig_only_for_digging_tools() {
+        assert!(Dig::new(WeaponType::Girder, 1.0).is_none());
+        assert!(Dig::new(WeaponType::Bazooka, 1.0).is_none());
+    }
+
+    #[test]
+    fn girder_can_place() {
+        let map = new_map();
+        let crab = Crab::new("gosho", Rect::new(10.0, 1.0, 4.0, 4.0));
+        let girder = Girder::new(Point2::new(20.0, 10.0), 0.0);
+        assert!(!girder.can_place(&crab, &[], &map));
+
+        let girder = Girder::new(Point2::new(20.0, 10.0), std::f32::consts::FRAC_PI_2);
+        assert!(!girder.can_place(&crab, &[], &map));
+
+        let map = Map::new(&vec![0; 4 * 200 * 200], 200, 200);
+        let girder = Girder::new(Point2::new(100.0, 100.0), 0.0);
+        assert!(girder.can_place(&crab, &[], &map));
+        assert!(!girder.can_place(&crab, &[Rect::new(90.0, 95.0, 10.0, 10.0)], &map));
+    }
+}
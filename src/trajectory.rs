@@ -0,0 +1,142 @@
+use crate::crab::Crab;
+use crate::map::Map;
+use crate::player::Player;
+use crate::settings::Targets;
+use crate::shot::Shot;
+use ggez::nalgebra::Point2;
+
+/// The path a shot would take, found with the same physics as the game loop.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trajectory {
+    pub points: Vec<Point2<f32>>,
+    /// `None` when the shot leaves the map or sinks.
+    pub impact: Option<Impact>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Impact {
+    pub point: Point2<f32>,
+    /// Team of the crab that is hit, `None` for terrain.
+    pub team: Option<usize>,
+    /// Radius of the crater the shot leaves.
+    pub radius: f32,
+}
+
+const STEP_TIME: f32 = 1.0 / 30.0;
+const MAX_STEPS: usize = 300;
+
+/// Follows `shot` of `shooter` until it hits one of the `targets` or the terrain.
+pub fn predict(map: &Map, players: &[Player], shooter: &Crab, mut shot: Shot, targets: &Targets) -> Trajectory {
+    let (width, height) = (map.get_width() as f32, map.get_height() as f32);
+    let mut points = vec![shot.get_rect().point().into()];
+    let mut cleared = false;
+    for _ in 0..MAX_STEPS {
+        shot.update(STEP_TIME);
+        let rect = shot.get_rect();
+        let point: Point2<f32> = rect.point().into();
+        points.push(point);
+        cleared |= !shooter.get_rect().overlaps(&rect);
+        let hit = players.iter().enumerate().position(|(i, player)| {
+            player.crabs.iter().any(|other| {
+                targets.can_hit(i, &other.name, cleared) && other.get_rect().overlaps(&rect)
+            })
+        });
+        if hit.is_some() || map.on_ground(point) {
+            let impact = Impact {
+                point,
+                team: hit,
+                radius: shot.damage(),
+            };
+            return Trajectory {
+                points,
+                impact: Some(impact),
+            };
+        }
+        if rect.top() < 0.0
+            || rect.left() < 0.0
+            || rect.bottom() > height
+            || rect.right() > width
+            || map.under_water(point)
+        {
+            break;
+        }
+    }
+    Trajectory { points, impact: None }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::config::CONFIG;
+    use crate::settings::MatchSettings;
+    use crate::weapon::{Weapon, WeaponType};
+    use ggez::graphics::Rect;
+    use ggez::nalgebra::Vector2;
+
+    fn new_map() -> Map {
+        let mut data = vec![];
+        for y in 0..300 {
+            for _ in 0..600 {
+                data.extend(&[0, 0, 0, if y >= 250 { 255 } else { 0 }]);
+            }
+        }
+        Map::new(&data, 600, 300)
+    }
+
+    fn new_player() -> Player {
+        Player::new("ivan", vec![Crab::new("gosho", Rect::new(50.0, 218.0, 48.0, 32.0))])
+    }
+
+    fn new_targets() -> Targets {
+        MatchSettings::new(&CONFIG).targets(0, "gosho")
+    }
+
+    fn new_shot(crab: &Crab, direction: Vector2<f32>) -> Shot {
+        let mut weapon = Weapon::new(WeaponType::Bazooka);
+        weapon.set_direction(direction);
+        weapon.fire(crab.get_pos(), 1.5).pop().unwrap()
+    }
+
+    #[test]
+    fn predict_lands_on_terrain() {
+        let map = new_map();
+        let players = vec![new_player()];
+        let crab = &players[0].crabs[0];
+        let shot = new_shot(crab, Vector2::new(0.6, -0.8));
+        let trajectory = predict(&map, &players, crab, shot.clone(), &new_targets());
+        let impact = trajectory.impact.unwrap();
+        assert_eq!(impact.team, None);
+        assert_eq!(impact.radius, shot.damage());
+        assert!(impact.point.x > crab.get_pos().x);
+        assert!(map.on_ground(impact.point));
+        assert_eq!(*trajectory.points.last().unwrap(), impact.point);
+    }
+
+    #[test]
+    fn predict_hits_crab() {
+        let map = new_map();
+        let players = vec![
+            new_player(),
+            Player::new("petar", vec![Crab::new("pesho", Rect::new(150.0, 200.0, 48.0, 50.0))]),
+        ];
+        let shot = new_shot(&players[0].crabs[0], Vector2::new(1.0, 0.0));
+        let impact = predict(&map, &players, &players[0].crabs[0], shot.clone(), &new_targets()).impact.unwrap();
+        assert_eq!(impact.team, Some(1));
+
+        let mut targets = new_targets();
+        targets.sides = vec![0, 0];
+        targets.friendly_fire = false;
+        let impact = predict(&map, &players, &players[0].crabs[0], shot, &targets).impact.unwrap();
+        assert_eq!(impact.team, None);
+    }
+
+    #[test]
+    fn predict_leaves_map() {
+        let map = new_map();
+        let players = vec![new_player()];
+        let crab = &players[0].crabs[0];
+        let shot = new_shot(crab, Vector2::new(-1.0, 0.0));
+        assert_eq!(predict(&map, &players, crab, shot, &new_targets()).impact, None);
+    }
+}
@@ -1,14 +1,44 @@
 use crate::shot::{Shot, ShotType, ShotConfig};
 use ggez::nalgebra::{Point2, Vector2};
 use crate::config::CONFIG;
+use crate::status::{Status, StatusKind};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use std::fmt;
 
-#[derive(Copy, Clone, Debug, PartialEq)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum WeaponType {
     Bazooka,
     Pistol,
     Skip,
+    Girder,
+    Blowtorch,
+    Drill,
+    PoisonDart,
+    FreezeRay,
+}
+
+impl WeaponType {
+    pub const ALL: [WeaponType; 8] = [
+        WeaponType::Bazooka,
+        WeaponType::Pistol,
+        WeaponType::Skip,
+        WeaponType::Girder,
+        WeaponType::Blowtorch,
+        WeaponType::Drill,
+        WeaponType::PoisonDart,
+        WeaponType::FreezeRay,
+    ];
+
+    /// The name used in the config.
+    pub fn from_name(name: &str) -> Option<WeaponType> {
+        Self::ALL.iter().copied().find(|weapon| format!("{:?}", weapon) == name)
+    }
+
+    /// Tools change the terrain instead of firing shots.
+    pub fn is_tool(self) -> bool {
+        matches!(self, WeaponType::Girder | WeaponType::Blowtorch | WeaponType::Drill)
+    }
 }
 
 pub trait Fireable {
@@ -35,6 +65,20 @@ impl Fireable for Pistol {
     }
 }
 
+/// Fires a pistol shot that leaves a status on the crab it hits.
+pub struct StatusGun {
+    status: StatusKind,
+}
+impl Fireable for StatusGun {
+    fn fire(&self, pos: Point2<f32>, d: Vector2<f32>, power: f32) -> Vec<Shot> {
+        Pistol {}
+            .fire(pos, d, power)
+            .into_iter()
+            .map(|shot| shot.with_status(Status::new(self.status)))
+            .collect()
+    }
+}
+
 pub struct Bazooka {}
 impl Fireable for Bazooka {
     fn fire(&self, pos: Point2<f32>, d: Vector2<f32>, power: f32) -> Vec<Shot> {
@@ -84,6 +128,22 @@ impl Weapon {
     }
 }
 
+/// A weapon is saved as its kind and direction, what it fires follows from the kind.
+impl Serialize for Weapon {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.kind, [self.direction.x, self.direction.y]).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Weapon {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let (kind, [x, y]) = <(WeaponType, [f32; 2])>::deserialize(deserializer)?;
+        let mut weapon = Weapon::new(kind);
+        weapon.direction = Vector2::new(x, y);
+        Ok(weapon)
+    }
+}
+
 impl fmt::Debug for Weapon {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         write!(f, "weapon: {:?}", self.kind)
@@ -92,8 +152,12 @@ impl fmt::Debug for Weapon {
 
 pub fn new_weapon(kind: WeaponType) -> Box<dyn Fireable> {
     match kind {
-        WeaponType::Skip => Box::new(Skip{}),
+        WeaponType::Skip | WeaponType::Girder | WeaponType::Blowtorch | WeaponType::Drill => {
+            Box::new(Skip{})
+        }
         WeaponType::Bazooka =>  Box::new(Bazooka{}),
         WeaponType::Pistol => Box::new(Pistol{}),
+        WeaponType::PoisonDart => Box::new(StatusGun { status: StatusKind::Poison }),
+        WeaponType::FreezeRay => Box::new(StatusGun { status: StatusKind::Freeze }),
     }
 }